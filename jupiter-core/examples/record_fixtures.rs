@@ -0,0 +1,50 @@
+//! Records the accounts of an amm into a fixture directory for the offline test harness.
+//!
+//! cargo run -p jupiter-core --example record_fixtures -- <rpc url> <fixture directory> spl-token-swap <pool>
+//! cargo run -p jupiter-core --example record_fixtures -- <rpc url> <fixture directory> symmetry <fund state>
+//! cargo run -p jupiter-core --example record_fixtures -- <rpc url> <fixture directory> step-token-swap <pool>
+//!
+//! A solana-test-validator cloning the accounts works as well as a mainnet rpc.
+use anyhow::{anyhow, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::{env, str::FromStr};
+
+use jupiter_core::amm::Amm;
+use jupiter_core::recorder::AccountRecorder;
+use jupiter_core::spl_token_swap_amm::SplTokenSwapAmm;
+use jupiter_core::step_token_swap_amm::StepTokenSwapAmm;
+use jupiter_core::symmetry_token_swap::SymmetryTokenSwap;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 5 {
+        return Err(anyhow!(
+            "Usage: record_fixtures <rpc url> <fixture directory> <spl-token-swap|symmetry|step-token-swap> <key>"
+        ));
+    }
+    let key = Pubkey::from_str(&args[4]).map_err(|_| anyhow!("Invalid key {}", args[4]))?;
+    let mut recorder = AccountRecorder::new(RpcClient::new(args[1].clone()), &args[2]);
+
+    let mut amm: Box<dyn Amm> = match args[3].as_str() {
+        "spl-token-swap" => {
+            let keyed_accounts = recorder.record_keyed_accounts(&[key])?;
+            Box::new(SplTokenSwapAmm::from_keyed_account(&keyed_accounts[0])?)
+        }
+        "symmetry" => {
+            let keyed_accounts = recorder.record_keyed_accounts(&[key, SymmetryTokenSwap::TOKEN_INFO_ADDRESS])?;
+            Box::new(SymmetryTokenSwap::from_keyed_account(&keyed_accounts[0], &keyed_accounts[1])?)
+        }
+        "step-token-swap" => {
+            let keyed_accounts = recorder.record_keyed_accounts(&[key])?;
+            Box::new(StepTokenSwapAmm::from_keyed_account(&keyed_accounts[0])?)
+        }
+        amm => return Err(anyhow!("Unknown amm {}", amm)),
+    };
+    recorder.record_amm(amm.as_mut())?;
+
+    for recorded_key in recorder.recorded_keys() {
+        println!("{}", recorded_key);
+    }
+    Ok(())
+}
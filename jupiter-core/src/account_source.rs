@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use std::{fs, path::PathBuf, str::FromStr};
+
+/// Where accounts are read from, a live rpc or a fixture directory
+pub trait AccountSource {
+    fn get_account(&self, key: &Pubkey) -> Result<Account>;
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+    /// Accounts along with the slot they were read at
+    fn get_multiple_accounts_with_slot(&self, keys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>)>;
+}
+
+impl AccountSource for RpcClient {
+    fn get_account(&self, key: &Pubkey) -> Result<Account> {
+        Ok(RpcClient::get_account(self, key)?)
+    }
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(RpcClient::get_multiple_accounts(self, keys)?)
+    }
+
+    fn get_multiple_accounts_with_slot(&self, keys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>)> {
+        let response = self.get_multiple_accounts_with_commitment(keys, self.commitment())?;
+        Ok((response.context.slot, response.value))
+    }
+}
+
+/// Account as stored in a fixture file, keys in base58 and data in base64
+#[derive(Serialize, Deserialize)]
+pub struct FixtureAccount {
+    pub pubkey: String,
+    pub owner: String,
+    pub lamports: u64,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    pub data: String,
+    /// Slot the account was fetched at, unknown for synthesized accounts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u64>,
+}
+
+impl FixtureAccount {
+    pub fn new(key: &Pubkey, account: &Account, slot: Option<u64>) -> Self {
+        Self {
+            pubkey: key.to_string(),
+            owner: account.owner.to_string(),
+            lamports: account.lamports,
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+            data: base64::encode(&account.data),
+            slot,
+        }
+    }
+
+    pub fn to_account(&self) -> Result<Account> {
+        Ok(Account {
+            lamports: self.lamports,
+            data: base64::decode(&self.data)?,
+            owner: Pubkey::from_str(&self.owner).map_err(|_| anyhow!("Invalid owner {}", self.owner))?,
+            executable: self.executable,
+            rent_epoch: self.rent_epoch,
+        })
+    }
+}
+
+/// Accounts stored one file per account, named after the account key
+pub struct FixtureAccountSource {
+    directory: PathBuf,
+}
+
+impl FixtureAccountSource {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn account_path(&self, key: &Pubkey) -> PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+
+    pub fn save_account(&self, key: &Pubkey, account: &Account, slot: Option<u64>) -> Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let fixture_account = FixtureAccount::new(key, account, slot);
+        fs::write(self.account_path(key), serde_json::to_string_pretty(&fixture_account)?)?;
+        Ok(())
+    }
+
+    fn get_fixture_account(&self, key: &Pubkey) -> Result<Option<FixtureAccount>> {
+        let path = self.account_path(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(&path)?)?))
+    }
+}
+
+impl AccountSource for FixtureAccountSource {
+    fn get_account(&self, key: &Pubkey) -> Result<Account> {
+        self.get_fixture_account(key)?
+            .with_context(|| format!("Missing fixture {}", self.account_path(key).display()))?
+            .to_account()
+    }
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(self.get_multiple_accounts_with_slot(keys)?.1)
+    }
+
+    /// The slot is the latest the accounts were recorded at, 0 when none was recorded
+    fn get_multiple_accounts_with_slot(&self, keys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>)> {
+        let mut slot = 0;
+        let mut accounts = Vec::new();
+        for key in keys {
+            let fixture_account = self.get_fixture_account(key)?;
+            if let Some(fixture_account) = fixture_account.as_ref() {
+                slot = slot.max(fixture_account.slot.unwrap_or_default());
+            }
+            accounts.push(fixture_account.map(|fixture_account| fixture_account.to_account()).transpose()?);
+        }
+        Ok((slot, accounts))
+    }
+}
+
+#[test]
+fn test_fixture_account_source() {
+    let directory = std::env::temp_dir().join(format!("fixture-account-source-{}", Pubkey::new_unique()));
+    let account_source = FixtureAccountSource::new(&directory);
+    let key = Pubkey::new_unique();
+    let account = Account {
+        lamports: 2_039_280,
+        data: vec![1, 2, 3, 255],
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 361,
+    };
+    account_source.save_account(&key, &account, Some(171_234_567)).unwrap();
+
+    assert_eq!(account_source.get_account(&key).unwrap(), account);
+    let missing_key = Pubkey::new_unique();
+    assert!(account_source.get_account(&missing_key).is_err());
+    assert_eq!(
+        account_source.get_multiple_accounts(&[key, missing_key]).unwrap(),
+        vec![Some(account), None]
+    );
+    assert_eq!(account_source.get_multiple_accounts_with_slot(&[key]).unwrap().0, 171_234_567);
+    fs::remove_dir_all(directory).unwrap();
+}
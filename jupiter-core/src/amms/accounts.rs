@@ -177,4 +177,16 @@ impl SimplePrice {
             slot: valid_slot
         }
     }
+
+    /// Pyth price account holding the price, only the fields read by load are written
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 3312];
+        data[20..24].copy_from_slice(&self.expo.to_le_bytes());
+        data[40..48].copy_from_slice(&self.slot.to_le_bytes());
+        data[208..216].copy_from_slice(&self.price.to_le_bytes());
+        data[216..224].copy_from_slice(&(self.conf as u64).to_le_bytes());
+        data[224..228].copy_from_slice(&self.status.to_le_bytes());
+        data
+    }
 }
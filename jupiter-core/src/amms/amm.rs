@@ -24,6 +24,9 @@ pub struct Quote {
     pub fee_mint: Pubkey,
     pub fee_pct: Decimal,
     pub price_impact_pct: Decimal,
+    /// The out amount comes from a model of the curve rather than the program's own math,
+    /// the router does not route through such quotes
+    pub approximate: bool,
 }
 
 pub type QuoteMintToReferrer = HashMap<Pubkey, Pubkey>;
@@ -45,9 +48,17 @@ pub struct SwapLegAndAccountMetas {
 }
 
 /// Amm might trigger a setup step for the user
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AmmUserSetup {
     SerumDexOpenOrdersSetup { market: Pubkey },
+    // Create the open orders account Jupiter expects on an openbook market
+    OpenbookOpenOrdersSetup { market: Pubkey },
+    // Create the associated token account of owner for mint, no-op if it already exists
+    CreateAssociatedTokenAccount { owner: Pubkey, mint: Pubkey },
+    // Move lamports into the owner's wrapped SOL associated token account
+    WrapSol { owner: Pubkey, amount: u64 },
+    // Close the owner's wrapped SOL associated token account once the swap is done
+    UnwrapSol { owner: Pubkey },
 }
 
 pub trait Amm {
@@ -79,6 +90,11 @@ pub trait Amm {
     fn get_user_setup(&self) -> Option<AmmUserSetup> {
         None
     }
+
+    // Steps the user needs before (and after) the swap given the swap params, get_user_setup by default
+    fn get_user_setup_steps(&self, _swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        self.get_user_setup().into_iter().collect()
+    }
 }
 
 #[derive(Clone)]
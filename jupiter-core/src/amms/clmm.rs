@@ -0,0 +1,453 @@
+//! Concentrated liquidity math shared by the Whirlpool and Raydium CLMM amms.
+//! Prices are square roots in Q64.64 fixed point and fee rates are in hundredths of a bip.
+use spl_math::uint::U256;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+pub const MIN_TICK_INDEX: i32 = -443636;
+pub const MAX_TICK_INDEX: i32 = 443636;
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+pub const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+pub const FEE_RATE_DENOMINATOR: u64 = 1_000_000;
+
+/// sqrt(1.0001^-(2^i)) in Q128.128 for each bit i of a tick index, from Uniswap v3's TickMath
+const SQRT_RATIOS_X128: [u128; 19] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+];
+
+/// Square root price at a tick, rounded down. Matches the programs exactly up to the last bits
+/// of the largest prices, which the max sqrt price bounds anyway.
+pub fn sqrt_price_from_tick_index(tick_index: i32) -> u128 {
+    let tick_index = tick_index.clamp(MIN_TICK_INDEX, MAX_TICK_INDEX);
+    let abs_tick_index = tick_index.unsigned_abs();
+    let mut ratio = U256::one() << 128;
+    for (bit, sqrt_ratio) in SQRT_RATIOS_X128.iter().enumerate() {
+        if abs_tick_index & (1 << bit) != 0 {
+            ratio = (ratio * U256::from(*sqrt_ratio)) >> 128;
+        }
+    }
+    if tick_index > 0 {
+        ratio = U256::MAX / ratio;
+    }
+    let sqrt_price: u128 = (ratio >> 64).as_u128();
+    sqrt_price.clamp(MIN_SQRT_PRICE_X64, MAX_SQRT_PRICE_X64)
+}
+
+/// Greatest tick whose square root price is at most the given one
+pub fn tick_index_from_sqrt_price(sqrt_price: u128) -> i32 {
+    let (mut low, mut high) = (MIN_TICK_INDEX, MAX_TICK_INDEX);
+    while low < high {
+        let middle = low + (high - low + 1) / 2;
+        if sqrt_price_from_tick_index(middle) <= sqrt_price {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    low
+}
+
+fn div_round_up_if(numerator: U256, denominator: U256, round_up: bool) -> U256 {
+    let quotient = numerator / denominator;
+    if round_up && quotient * denominator != numerator {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+/// Amount of token A between two prices for the liquidity: L * (upper - lower) / (upper * lower)
+pub fn get_amount_delta_a(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> Option<u64> {
+    let (lower, upper) = (sqrt_price_0.min(sqrt_price_1), sqrt_price_0.max(sqrt_price_1));
+    if lower == 0 {
+        return None;
+    }
+    let numerator = (U256::from(liquidity) * U256::from(upper - lower)) << 64;
+    let denominator = U256::from(upper) * U256::from(lower);
+    div_round_up_if(numerator, denominator, round_up).try_into().ok()
+}
+
+/// Amount of token B between two prices for the liquidity: L * (upper - lower)
+pub fn get_amount_delta_b(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> Option<u64> {
+    let (lower, upper) = (sqrt_price_0.min(sqrt_price_1), sqrt_price_0.max(sqrt_price_1));
+    let product = U256::from(liquidity) * U256::from(upper - lower);
+    div_round_up_if(product, U256::one() << 64, round_up).try_into().ok()
+}
+
+/// Price after moving the amount of token A in or out, rounded up to stay on the pool's side
+fn get_next_sqrt_price_from_a(sqrt_price: u128, liquidity: u128, amount: u64, amount_specified_is_input: bool) -> Option<u128> {
+    if amount == 0 {
+        return Some(sqrt_price);
+    }
+    let product = U256::from(sqrt_price) * U256::from(amount);
+    let liquidity_shifted = U256::from(liquidity) << 64;
+    let denominator = if amount_specified_is_input {
+        liquidity_shifted + product
+    } else {
+        liquidity_shifted.checked_sub(product)?
+    };
+    if denominator.is_zero() {
+        return None;
+    }
+    let numerator = (U256::from(liquidity) * U256::from(sqrt_price)) << 64;
+    div_round_up_if(numerator, denominator, true).try_into().ok()
+}
+
+/// Price after moving the amount of token B in or out, rounded down to stay on the pool's side
+fn get_next_sqrt_price_from_b(sqrt_price: u128, liquidity: u128, amount: u64, amount_specified_is_input: bool) -> Option<u128> {
+    if liquidity == 0 {
+        return None;
+    }
+    let delta: u128 = div_round_up_if(U256::from(amount) << 64, liquidity.into(), !amount_specified_is_input)
+        .try_into()
+        .ok()?;
+    if amount_specified_is_input {
+        sqrt_price.checked_add(delta)
+    } else {
+        sqrt_price.checked_sub(delta)
+    }
+}
+
+fn get_next_sqrt_price(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u64,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Option<u128> {
+    if amount_specified_is_input == a_to_b {
+        get_next_sqrt_price_from_a(sqrt_price, liquidity, amount, amount_specified_is_input)
+    } else {
+        get_next_sqrt_price_from_b(sqrt_price, liquidity, amount, amount_specified_is_input)
+    }
+}
+
+/// Amount of the specified side, input rounded up or output rounded down
+fn get_amount_fixed_delta(
+    current_sqrt_price: u128,
+    target_sqrt_price: u128,
+    liquidity: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Option<u64> {
+    if a_to_b == amount_specified_is_input {
+        get_amount_delta_a(current_sqrt_price, target_sqrt_price, liquidity, amount_specified_is_input)
+    } else {
+        get_amount_delta_b(current_sqrt_price, target_sqrt_price, liquidity, amount_specified_is_input)
+    }
+}
+
+/// Amount of the other side, output rounded down or input rounded up
+fn get_amount_unfixed_delta(
+    current_sqrt_price: u128,
+    target_sqrt_price: u128,
+    liquidity: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Option<u64> {
+    if a_to_b == amount_specified_is_input {
+        get_amount_delta_b(current_sqrt_price, target_sqrt_price, liquidity, !amount_specified_is_input)
+    } else {
+        get_amount_delta_a(current_sqrt_price, target_sqrt_price, liquidity, !amount_specified_is_input)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SwapStep {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub next_sqrt_price: u128,
+    pub fee_amount: u64,
+}
+
+/// Swap within a single liquidity range, up to the target price at most
+pub fn compute_swap_step(
+    amount_remaining: u64,
+    fee_rate: u32,
+    liquidity: u128,
+    current_sqrt_price: u128,
+    target_sqrt_price: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Option<SwapStep> {
+    let fee_rate = fee_rate as u64;
+    let amount_calc = if amount_specified_is_input {
+        (amount_remaining as u128 * (FEE_RATE_DENOMINATOR - fee_rate) as u128 / FEE_RATE_DENOMINATOR as u128) as u64
+    } else {
+        amount_remaining
+    };
+
+    let initial_amount_fixed_delta = get_amount_fixed_delta(
+        current_sqrt_price,
+        target_sqrt_price,
+        liquidity,
+        amount_specified_is_input,
+        a_to_b,
+    );
+    let reaches_target = match initial_amount_fixed_delta {
+        Some(amount_fixed_delta) => amount_fixed_delta <= amount_calc,
+        None => false,
+    };
+    let next_sqrt_price = if reaches_target {
+        target_sqrt_price
+    } else {
+        get_next_sqrt_price(current_sqrt_price, liquidity, amount_calc, amount_specified_is_input, a_to_b)?
+    };
+    let is_max_swap = next_sqrt_price == target_sqrt_price;
+
+    let amount_unfixed_delta = get_amount_unfixed_delta(
+        current_sqrt_price,
+        next_sqrt_price,
+        liquidity,
+        amount_specified_is_input,
+        a_to_b,
+    )?;
+    let amount_fixed_delta = match (is_max_swap, initial_amount_fixed_delta) {
+        (true, Some(amount_fixed_delta)) => amount_fixed_delta,
+        _ => get_amount_fixed_delta(
+            current_sqrt_price,
+            next_sqrt_price,
+            liquidity,
+            amount_specified_is_input,
+            a_to_b,
+        )?,
+    };
+
+    let (amount_in, mut amount_out) = if amount_specified_is_input {
+        (amount_fixed_delta, amount_unfixed_delta)
+    } else {
+        (amount_unfixed_delta, amount_fixed_delta)
+    };
+    if !amount_specified_is_input && amount_out > amount_remaining {
+        amount_out = amount_remaining;
+    }
+
+    // Short of the target the whole input is used, the rest of it being the fee
+    let fee_amount = if amount_specified_is_input && !is_max_swap {
+        amount_remaining - amount_in
+    } else {
+        div_round_up_if(
+            U256::from(amount_in) * U256::from(fee_rate),
+            U256::from(FEE_RATE_DENOMINATOR - fee_rate),
+            true,
+        )
+        .try_into()
+        .ok()?
+    };
+
+    Some(SwapStep {
+        amount_in,
+        amount_out,
+        next_sqrt_price,
+        fee_amount,
+    })
+}
+
+/// Initialized ticks of the loaded tick arrays, with the tick range they cover.
+/// Ticks outside of it are unknown, a swap reaching them stops there.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickRange {
+    /// Liquidity net of each initialized tick
+    pub ticks: BTreeMap<i32, i128>,
+    pub start_tick_index: i32,
+    /// First tick after the range
+    pub end_tick_index: i32,
+}
+
+impl TickRange {
+    /// Next tick to cross with whether it is initialized, None once out of the range
+    fn next_tick_index(&self, tick_current_index: i32, a_to_b: bool) -> Option<(i32, bool)> {
+        if self.start_tick_index >= self.end_tick_index {
+            return None;
+        }
+        if a_to_b {
+            if tick_current_index < self.start_tick_index {
+                return None;
+            }
+            let upper = tick_current_index.min(self.end_tick_index - 1);
+            Some(match self.ticks.range(self.start_tick_index..=upper).next_back() {
+                Some((tick_index, _)) => (*tick_index, true),
+                None => (self.start_tick_index, false),
+            })
+        } else {
+            if tick_current_index >= self.end_tick_index - 1 {
+                return None;
+            }
+            let lower = (tick_current_index + 1).max(self.start_tick_index);
+            Some(match self.ticks.range(lower..self.end_tick_index).next() {
+                Some((tick_index, _)) => (*tick_index, true),
+                None => (self.end_tick_index - 1, false),
+            })
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SwapResult {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub next_sqrt_price: u128,
+    /// Whether the specified amount was swapped in full within the loaded ticks and price bounds
+    pub is_complete: bool,
+}
+
+/// Swap of the specified amount crossing initialized ticks until it is filled or the ticks run out
+#[allow(clippy::too_many_arguments)]
+pub fn swap(
+    liquidity: u128,
+    sqrt_price: u128,
+    tick_current_index: i32,
+    fee_rate: u32,
+    tick_range: &TickRange,
+    amount: u64,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Option<SwapResult> {
+    let sqrt_price_limit = if a_to_b { MIN_SQRT_PRICE_X64 } else { MAX_SQRT_PRICE_X64 };
+    let mut liquidity = liquidity;
+    let mut current_sqrt_price = sqrt_price;
+    let mut tick_current_index = tick_current_index;
+    let mut amount_remaining = amount;
+    let mut amount_calculated: u64 = 0;
+    let mut fee_amount: u64 = 0;
+
+    while amount_remaining > 0 && current_sqrt_price != sqrt_price_limit {
+        let (next_tick_index, next_tick_initialized) = match tick_range.next_tick_index(tick_current_index, a_to_b) {
+            Some(next_tick) => next_tick,
+            None => break,
+        };
+        let next_tick_sqrt_price = sqrt_price_from_tick_index(next_tick_index);
+        let target_sqrt_price = if a_to_b {
+            next_tick_sqrt_price.max(sqrt_price_limit)
+        } else {
+            next_tick_sqrt_price.min(sqrt_price_limit)
+        };
+
+        let swap_step = compute_swap_step(
+            amount_remaining,
+            fee_rate,
+            liquidity,
+            current_sqrt_price,
+            target_sqrt_price,
+            amount_specified_is_input,
+            a_to_b,
+        )?;
+        if amount_specified_is_input {
+            amount_remaining = amount_remaining.checked_sub(swap_step.amount_in + swap_step.fee_amount)?;
+            amount_calculated = amount_calculated.checked_add(swap_step.amount_out)?;
+        } else {
+            amount_remaining = amount_remaining.checked_sub(swap_step.amount_out)?;
+            amount_calculated = amount_calculated.checked_add(swap_step.amount_in + swap_step.fee_amount)?;
+        }
+        fee_amount += swap_step.fee_amount;
+
+        if swap_step.next_sqrt_price == next_tick_sqrt_price {
+            if next_tick_initialized {
+                let liquidity_net = tick_range.ticks[&next_tick_index];
+                let liquidity_net = if a_to_b { -liquidity_net } else { liquidity_net };
+                liquidity = if liquidity_net < 0 {
+                    liquidity.checked_sub(liquidity_net.unsigned_abs())?
+                } else {
+                    liquidity.checked_add(liquidity_net as u128)?
+                };
+            }
+            tick_current_index = if a_to_b { next_tick_index - 1 } else { next_tick_index };
+        } else if swap_step.next_sqrt_price != current_sqrt_price {
+            tick_current_index = tick_index_from_sqrt_price(swap_step.next_sqrt_price);
+        }
+        current_sqrt_price = swap_step.next_sqrt_price;
+    }
+
+    let amount_swapped = amount - amount_remaining;
+    let (amount_in, amount_out) = if amount_specified_is_input {
+        (amount_swapped, amount_calculated)
+    } else {
+        (amount_calculated, amount_swapped)
+    };
+    Some(SwapResult {
+        amount_in,
+        amount_out,
+        fee_amount,
+        next_sqrt_price: current_sqrt_price,
+        is_complete: amount_remaining == 0,
+    })
+}
+
+#[test]
+fn test_sqrt_price_from_tick_index() {
+    assert_eq!(sqrt_price_from_tick_index(0), 1 << 64);
+    assert_eq!(sqrt_price_from_tick_index(MIN_TICK_INDEX), MIN_SQRT_PRICE_X64);
+    assert_eq!(sqrt_price_from_tick_index(MAX_TICK_INDEX), MAX_SQRT_PRICE_X64);
+    // sqrt(1.0001) and its inverse
+    assert_eq!(sqrt_price_from_tick_index(1), 18447666387855959850);
+    assert_eq!(sqrt_price_from_tick_index(-1), 18445821805675392311);
+
+    for tick_index in [-443_000, -100_000, -1, 0, 1, 64, 100_000, 443_000] {
+        let sqrt_price = sqrt_price_from_tick_index(tick_index);
+        assert_eq!(tick_index_from_sqrt_price(sqrt_price), tick_index);
+        assert_eq!(tick_index_from_sqrt_price(sqrt_price + 1), tick_index);
+        assert_eq!(tick_index_from_sqrt_price(sqrt_price - 1), tick_index - 1);
+    }
+}
+
+#[test]
+fn test_swap_across_ticks() {
+    // Price 1 with liquidity between ticks -64 and 64, and more of it between -8 and 8
+    let mut ticks = BTreeMap::new();
+    ticks.insert(-64, 1_000_000_000_000i128);
+    ticks.insert(-8, 9_000_000_000_000);
+    ticks.insert(8, -9_000_000_000_000);
+    ticks.insert(64, -1_000_000_000_000);
+    let tick_range = TickRange {
+        ticks,
+        start_tick_index: -128,
+        end_tick_index: 128,
+    };
+    let liquidity = 10_000_000_000_000;
+    let sqrt_price = 1 << 64;
+
+    // Small swaps stay in the deepest range, input less 0.3% fee at close to price 1
+    let result = swap(liquidity, sqrt_price, 0, 3_000, &tick_range, 1_000_000, true, true).unwrap();
+    assert!(result.is_complete);
+    assert_eq!(result.fee_amount, 3_000);
+    assert_eq!(result.amount_out, 996_999);
+
+    // Exact out needs at least as much input as exact in gives for the same output
+    let exact_out = swap(liquidity, sqrt_price, 0, 3_000, &tick_range, result.amount_out, false, true).unwrap();
+    assert!(exact_out.is_complete);
+    assert_eq!(exact_out.amount_out, result.amount_out);
+    assert!(exact_out.amount_in <= 1_000_000 && exact_out.amount_in >= 999_990);
+
+    // Large swaps cross both ticks and run out of liquidity past tick -64
+    let result = swap(liquidity, sqrt_price, 0, 3_000, &tick_range, u64::MAX / 2, true, true).unwrap();
+    assert!(!result.is_complete);
+    let expected_out = get_amount_delta_b(sqrt_price_from_tick_index(-8), sqrt_price, liquidity, false).unwrap()
+        + get_amount_delta_b(sqrt_price_from_tick_index(-64), sqrt_price_from_tick_index(-8), 1_000_000_000_000, false)
+            .unwrap();
+    assert!(result.amount_out <= expected_out && result.amount_out + 2 >= expected_out);
+    assert_eq!(result.next_sqrt_price, sqrt_price_from_tick_index(-128));
+
+    // Both directions give back less than they take
+    let there = swap(liquidity, sqrt_price, 0, 3_000, &tick_range, 50_000_000_000, true, false).unwrap();
+    let back = swap(liquidity, there.next_sqrt_price, tick_index_from_sqrt_price(there.next_sqrt_price), 3_000, &tick_range, there.amount_out, true, true).unwrap();
+    assert!(back.amount_out < 50_000_000_000);
+}
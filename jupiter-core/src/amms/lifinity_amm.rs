@@ -0,0 +1,621 @@
+use anchor_lang::{solana_program::hash::hash, ToAccountMetas};
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use spl_math::uint::U256;
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{program_pack::Pack, pubkey, pubkey::Pubkey, sysvar};
+
+use super::accounts::SimplePrice;
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::get_user_token_account_setup;
+use crate::math::checked_ceil_div;
+use jupiter::{
+    accounts::{LifinitySwap, LifinityV2Swap},
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const LIFINITY_V1_PROGRAM_ID: Pubkey = pubkey!("EewxydAPCCVuNEyrVN68PuSYdQ7wKn27V9Gjeoi8dy3S");
+pub const LIFINITY_V2_PROGRAM_ID: Pubkey = pubkey!("2wT8Yq49kHgDzXuPxZSaeLaH1qbmGXtEyPy64bL7aD3c");
+/// Config account every v1 swap takes
+pub const LIFINITY_V1_CONFIG_ACCOUNT: Pubkey = pubkey!("2iT1ff9bG9nkP3h5EqwnGdj6q2uKqEHzxpDL5qjmN8xx");
+
+const PYTH_STATUS_TRADING: u32 = 1;
+/// Oracle prices published further back than this are not quoted against
+const MAX_ORACLE_AGE_SLOTS: u64 = 50;
+
+/// The parts of the Lifinity amm state needed to quote and build swaps, the same in v1 and v2.
+/// Token A is priced in token B by the main oracle over the pc oracle.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LifinityAmmState {
+    pub bump_seed: u8,
+    pub freeze_trade: u8,
+    pub token_a_account: Pubkey,
+    pub token_b_account: Pubkey,
+    pub pool_mint: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub fee_account: Pubkey,
+    pub oracle_main_account: Pubkey,
+    pub oracle_sub_account: Pubkey,
+    /// Price of token B, Pubkey::default() for pools quoted in USD
+    pub oracle_pc_account: Pubkey,
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub owner_trade_fee_numerator: u64,
+    pub owner_trade_fee_denominator: u64,
+    /// Concentration of the liquidity around the oracle price, 0 and 1 for a plain constant product
+    pub concentration: u64,
+}
+
+impl LifinityAmmState {
+    /// End of the fields read, the account holds the pool config after them
+    pub const LEN: usize = 519;
+
+    pub fn discriminator() -> [u8; 8] {
+        hash(b"account:Amm").to_bytes()[..8].try_into().unwrap()
+    }
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() < Self::LEN || account_data[..8] != Self::discriminator() {
+            return Err(anyhow!("Invalid Lifinity amm account"));
+        }
+        let u64_at = |offset: usize| u64::from_le_bytes(account_data[offset..offset + 8].try_into().unwrap());
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(account_data[offset..offset + 32].try_into().unwrap());
+        Ok(Self {
+            // The initializer accounts and amounts come first
+            bump_seed: account_data[121],
+            freeze_trade: account_data[122],
+            token_a_account: pubkey_at(158),
+            token_b_account: pubkey_at(190),
+            pool_mint: pubkey_at(222),
+            token_a_mint: pubkey_at(254),
+            token_b_mint: pubkey_at(286),
+            fee_account: pubkey_at(318),
+            oracle_main_account: pubkey_at(350),
+            oracle_sub_account: pubkey_at(382),
+            oracle_pc_account: pubkey_at(414),
+            trade_fee_numerator: u64_at(446),
+            trade_fee_denominator: u64_at(454),
+            owner_trade_fee_numerator: u64_at(462),
+            owner_trade_fee_denominator: u64_at(470),
+            // Withdraw and host fees follow, then the curve type
+            concentration: u64_at(511),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[..8].copy_from_slice(&Self::discriminator());
+        data[121] = self.bump_seed;
+        data[122] = self.freeze_trade;
+        for (offset, pubkey) in [
+            (158, self.token_a_account),
+            (190, self.token_b_account),
+            (222, self.pool_mint),
+            (254, self.token_a_mint),
+            (286, self.token_b_mint),
+            (318, self.fee_account),
+            (350, self.oracle_main_account),
+            (382, self.oracle_sub_account),
+            (414, self.oracle_pc_account),
+        ] {
+            data[offset..offset + 32].copy_from_slice(pubkey.as_ref());
+        }
+        for (offset, value) in [
+            (446, self.trade_fee_numerator),
+            (454, self.trade_fee_denominator),
+            (462, self.owner_trade_fee_numerator),
+            (470, self.owner_trade_fee_denominator),
+            (511, self.concentration),
+        ] {
+            data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+}
+
+fn validate_price(price: &SimplePrice, oracle: &Pubkey, current_slot: u64) -> Result<()> {
+    if price.status != PYTH_STATUS_TRADING || price.price <= 0 {
+        return Err(anyhow!("Oracle {} has no trading price", oracle));
+    }
+    if current_slot.saturating_sub(price.slot) > MAX_ORACLE_AGE_SLOTS {
+        return Err(anyhow!("Oracle {} price of slot {} is stale at slot {}", oracle, price.slot, current_slot));
+    }
+    Ok(())
+}
+
+/// Lifinity v1 and v2 pools. The program's curve is not public, it is modelled as a constant
+/// product over virtual reserves holding the pool value at the oracle price, balanced on it and
+/// scaled by the concentration, so the pool quotes around the oracle price whatever its inventory.
+/// Rebalancing of the inventory is left out and quotes are flagged approximate.
+pub struct LifinityAmm {
+    key: Pubkey,
+    state: LifinityAmmState,
+    reserves: [u64; 2],
+    oracle_main_price: Option<SimplePrice>,
+    oracle_pc_price: Option<SimplePrice>,
+    current_slot: u64,
+    token_decimals: [u8; 2],
+    program_id: Pubkey,
+}
+
+impl LifinityAmm {
+    /// Token decimals are not in the amm state, they come with the keyed account params as
+    /// `{"token_a_decimals": 9, "token_b_decimals": 6}`
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        let decimals = |name: &str| -> Result<u8> {
+            keyed_account
+                .params
+                .as_ref()
+                .and_then(|params| params.get(name))
+                .and_then(|decimals| decimals.as_u64())
+                .and_then(|decimals| decimals.try_into().ok())
+                .with_context(|| format!("Missing {} param for Lifinity amm {}", name, keyed_account.key))
+        };
+        Ok(Self {
+            key: keyed_account.key,
+            state: LifinityAmmState::load(&keyed_account.account.data)?,
+            reserves: Default::default(),
+            oracle_main_price: None,
+            oracle_pc_price: None,
+            current_slot: 0,
+            token_decimals: [decimals("token_a_decimals")?, decimals("token_b_decimals")?],
+            program_id: keyed_account.account.owner,
+        })
+    }
+
+    fn get_authority(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[self.key.as_ref(), &[self.state.bump_seed]], &self.program_id)
+            .map_err(|_| anyhow!("Invalid bump seed for Lifinity amm {}", self.key))
+    }
+
+    fn has_oracle_pc(&self) -> bool {
+        self.state.oracle_pc_account != Pubkey::default()
+    }
+
+    /// Oracle price of token A in token B as a fraction of native amounts
+    fn oracle_price(&self) -> Result<(U256, U256)> {
+        let main_price = self
+            .oracle_main_price
+            .with_context(|| format!("Oracle of Lifinity amm {} not loaded, update the amm first", self.key))?;
+        validate_price(&main_price, &self.state.oracle_main_account, self.current_slot)?;
+        let ten = U256::from(10);
+        let mut numerator = U256::from(main_price.price) * ten.pow(self.token_decimals[1].into());
+        let mut denominator = ten.pow(U256::from(self.token_decimals[0] as i32 - main_price.expo));
+        match self.oracle_pc_price {
+            Some(pc_price) if self.has_oracle_pc() => {
+                validate_price(&pc_price, &self.state.oracle_pc_account, self.current_slot)?;
+                numerator *= ten.pow(U256::from(-pc_price.expo));
+                denominator *= U256::from(pc_price.price);
+            }
+            _ => (),
+        }
+        Ok((numerator, denominator))
+    }
+
+    /// Amount of token B worth the amount of token A at the oracle price
+    pub fn oracle_value(&self, amount_a: u64) -> Result<u64> {
+        let (numerator, denominator) = self.oracle_price()?;
+        (U256::from(amount_a) * numerator / denominator)
+            .try_into()
+            .map_err(|_| anyhow!("Oracle value overflows"))
+    }
+
+    /// Trade and owner fees, both taken from the input and rounded up
+    fn fee(&self, amount: u64) -> Result<u64> {
+        let fee = |numerator: u64, denominator: u64| -> Result<u128> {
+            if denominator == 0 {
+                return Ok(0);
+            }
+            checked_ceil_div(amount as u128 * numerator as u128, denominator as u128).context("Fee overflows")
+        };
+        let fee = fee(self.state.trade_fee_numerator, self.state.trade_fee_denominator)?
+            .checked_add(fee(self.state.owner_trade_fee_numerator, self.state.owner_trade_fee_denominator)?)
+            .context("Fee overflows")?;
+        fee.try_into().context("Fee overflows")
+    }
+
+    fn fee_pct(&self) -> Decimal {
+        let fee_pct = |numerator: u64, denominator: u64| {
+            if denominator == 0 {
+                Decimal::ZERO
+            } else {
+                Decimal::from(numerator) / Decimal::from(denominator)
+            }
+        };
+        fee_pct(self.state.trade_fee_numerator, self.state.trade_fee_denominator)
+            + fee_pct(self.state.owner_trade_fee_numerator, self.state.owner_trade_fee_denominator)
+    }
+
+    fn clone(&self) -> LifinityAmm {
+        LifinityAmm {
+            key: self.key,
+            state: self.state,
+            reserves: self.reserves,
+            oracle_main_price: self.oracle_main_price,
+            oracle_pc_price: self.oracle_pc_price,
+            current_slot: self.current_slot,
+            token_decimals: self.token_decimals,
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for LifinityAmm {
+    fn label(&self) -> String {
+        if self.program_id == LIFINITY_V2_PROGRAM_ID {
+            String::from("Lifinity V2")
+        } else {
+            String::from("Lifinity")
+        }
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.state.token_a_mint, self.state.token_b_mint]
+    }
+
+    // The clock gives the slot the oracle prices are aged against
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![
+            self.key,
+            self.state.token_a_account,
+            self.state.token_b_account,
+            self.state.oracle_main_account,
+            sysvar::clock::ID,
+        ];
+        if self.has_oracle_pc() {
+            accounts.push(self.state.oracle_pc_account);
+        }
+        accounts
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let get_account = |key: &Pubkey| {
+            accounts_map
+                .get(key)
+                .with_context(|| format!("Missing account {}", key))
+        };
+        let load_price = |key: &Pubkey| -> Result<SimplePrice> {
+            let account_data = get_account(key)?;
+            if account_data.len() < 228 {
+                return Err(anyhow!("Invalid pyth price account {}", key));
+            }
+            Ok(SimplePrice::load(account_data))
+        };
+        self.state = LifinityAmmState::load(get_account(&self.key)?)?;
+        self.reserves = [
+            TokenAccount::unpack(get_account(&self.state.token_a_account)?)?.amount,
+            TokenAccount::unpack(get_account(&self.state.token_b_account)?)?.amount,
+        ];
+        self.oracle_main_price = Some(load_price(&self.state.oracle_main_account)?);
+        if self.has_oracle_pc() {
+            self.oracle_pc_price = Some(load_price(&self.state.oracle_pc_account)?);
+        }
+        let clock = get_account(&sysvar::clock::ID)?;
+        self.current_slot = u64::from_le_bytes(clock.get(..8).context("Invalid clock sysvar")?.try_into()?);
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        if self.state.freeze_trade != 0 {
+            return Err(anyhow!("Lifinity amm {} has trading frozen", self.key));
+        }
+        let (numerator, denominator) = self.oracle_price()?;
+        let [reserve_a, reserve_b] = self.reserves;
+
+        // Virtual reserves of half the pool value each at the oracle price, times the concentration
+        let concentration = U256::from(self.state.concentration.max(1));
+        let pool_value_b = U256::from(reserve_a) * numerator / denominator + U256::from(reserve_b);
+        let virtual_b = pool_value_b * concentration / 2;
+        let virtual_a = virtual_b * denominator / numerator;
+
+        let a_to_b = quote_params.input_mint == self.state.token_a_mint;
+        let (virtual_source, virtual_destination, destination_reserve) = if a_to_b {
+            (virtual_a, virtual_b, reserve_b)
+        } else {
+            (virtual_b, virtual_a, reserve_a)
+        };
+        let fee_amount = self.fee(quote_params.in_amount)?;
+        let in_amount_after_fee = U256::from(
+            quote_params
+                .in_amount
+                .checked_sub(fee_amount)
+                .context("Fee exceeds the in amount")?,
+        );
+        let denominator = virtual_source + in_amount_after_fee;
+        let out_amount: u64 = if denominator.is_zero() {
+            0
+        } else {
+            (virtual_destination * in_amount_after_fee / denominator)
+                .try_into()
+                .map_err(|_| anyhow!("Out amount overflows"))?
+        };
+
+        Ok(Quote {
+            not_enough_liquidity: out_amount > destination_reserve,
+            in_amount: quote_params.in_amount,
+            out_amount,
+            fee_amount,
+            fee_mint: quote_params.input_mint,
+            fee_pct: self.fee_pct(),
+            approximate: true,
+            ..Quote::default()
+        })
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let (swap_source, swap_destination) = if *source_mint == self.state.token_a_mint {
+            (self.state.token_a_account, self.state.token_b_account)
+        } else {
+            (self.state.token_b_account, self.state.token_a_account)
+        };
+        // Pools without a pc oracle take the main one in its place
+        let oracle_pc_account = if self.has_oracle_pc() {
+            self.state.oracle_pc_account
+        } else {
+            self.state.oracle_main_account
+        };
+
+        let (swap, account_metas) = if self.program_id == LIFINITY_V2_PROGRAM_ID {
+            let account_metas = LifinityV2Swap {
+                swap_program: self.program_id,
+                authority: self.get_authority()?,
+                amm: self.key,
+                user_transfer_authority: *user_transfer_authority,
+                source_info: *user_source_token_account,
+                destination_info: *user_destination_token_account,
+                swap_source,
+                swap_destination,
+                pool_mint: self.state.pool_mint,
+                fee_account: self.state.fee_account,
+                token_program: spl_token::ID,
+                oracle_main_account: self.state.oracle_main_account,
+                oracle_sub_account: self.state.oracle_sub_account,
+                oracle_pc_account,
+            }
+            .to_account_metas(None);
+            (Swap::LifinityV2, account_metas)
+        } else {
+            let account_metas = LifinitySwap {
+                swap_program: self.program_id,
+                authority: self.get_authority()?,
+                amm: self.key,
+                user_transfer_authority: *user_transfer_authority,
+                source_info: *user_source_token_account,
+                destination_info: *user_destination_token_account,
+                swap_source,
+                swap_destination,
+                pool_mint: self.state.pool_mint,
+                fee_account: self.state.fee_account,
+                token_program: spl_token::ID,
+                pyth_account: self.state.oracle_main_account,
+                pyth_pc_account: oracle_pc_account,
+                config_account: LIFINITY_V1_CONFIG_ACCOUNT,
+            }
+            .to_account_metas(None);
+            (Swap::Lifinity, account_metas)
+        };
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_lifinity_amm() {
+    use crate::amms::test_harness::{new_keyed_account, token_account_data};
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use crate::symmetry_token_swap::SymmetryTokenSwap;
+    use solana_sdk::{account::create_account_for_test, clock::Clock};
+    use spl_token::native_mint;
+
+    let key = Pubkey::new_unique();
+    let bump_seed = Pubkey::find_program_address(&[key.as_ref()], &LIFINITY_V2_PROGRAM_ID).1;
+    let state = LifinityAmmState {
+        bump_seed,
+        freeze_trade: 0,
+        token_a_account: Pubkey::new_unique(),
+        token_b_account: Pubkey::new_unique(),
+        pool_mint: Pubkey::new_unique(),
+        token_a_mint: native_mint::id(),
+        token_b_mint: USDC_MINT,
+        fee_account: Pubkey::new_unique(),
+        oracle_main_account: Pubkey::new_unique(),
+        oracle_sub_account: Pubkey::new_unique(),
+        oracle_pc_account: Pubkey::new_unique(),
+        trade_fee_numerator: 5,
+        trade_fee_denominator: 10_000,
+        owner_trade_fee_numerator: 0,
+        owner_trade_fee_denominator: 0,
+        concentration: 10,
+    };
+    let data = state.pack();
+    assert_eq!(LifinityAmmState::load(&data).unwrap(), state);
+
+    let params = serde_json::json!({"token_a_decimals": 9, "token_b_decimals": 6});
+    let mut amm = LifinityAmm::from_keyed_account(&new_keyed_account(
+        key,
+        data.clone(),
+        LIFINITY_V2_PROGRAM_ID,
+        Some(params.clone()),
+    ))
+    .unwrap();
+    assert_eq!(amm.label(), "Lifinity V2");
+
+    // SOL at 20 USD and USDC at 1 USD
+    let sol_price = SimplePrice { expo: -8, price: 2_000_000_000, low: 0, high: 0, conf: 0, status: 1, slot: 1_000 };
+    let usdc_price = SimplePrice { expo: -8, price: 100_000_000, low: 0, high: 0, conf: 0, status: 1, slot: 1_000 };
+    let clock = Clock { slot: 1_010, ..Clock::default() };
+    // The pool holds 1_000 SOL and 10_000 USDC, 30_000 USDC of value at the oracle price
+    let mut accounts_map: HashMap<Pubkey, Vec<u8>> = vec![
+        (key, data),
+        (state.token_a_account, token_account_data(native_mint::id(), 1_000_000_000_000)),
+        (state.token_b_account, token_account_data(USDC_MINT, 10_000_000_000)),
+        (state.oracle_main_account, sol_price.pack()),
+        (state.oracle_pc_account, usdc_price.pack()),
+        (sysvar::clock::ID, create_account_for_test(&clock).data),
+    ]
+    .into_iter()
+    .collect();
+    amm.update(&accounts_map).unwrap();
+
+    // Priced like Symmetry prices the same oracle, any other value than buy or sell is the oracle price
+    assert_eq!(
+        amm.oracle_value(1_000_000_000).unwrap(),
+        SymmetryTokenSwap::usd_value(1_000_000_000, 9, SimplePrice::load(&sol_price.pack()), 2).unwrap()
+    );
+
+    // Virtual reserves of 7_500 SOL and 150_000 USDC
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: native_mint::id(),
+            output_mint: USDC_MINT,
+        })
+        .unwrap();
+    assert_eq!(quote.fee_amount, 500_000);
+    assert_eq!(quote.out_amount, 19_987_336);
+    assert!(!quote.not_enough_liquidity);
+    assert!(quote.approximate);
+
+    // More than the 10_000 USDC held by the pool
+    assert!(
+        amm.quote(&QuoteParams {
+            in_amount: 600_000_000_000,
+            input_mint: native_mint::id(),
+            output_mint: USDC_MINT,
+        })
+        .unwrap()
+        .not_enough_liquidity
+    );
+
+    // The quote follows the oracle, at 10 USD per SOL the pool pays half as much
+    let sol_price = SimplePrice { price: 1_000_000_000, ..sol_price };
+    accounts_map.insert(state.oracle_main_account, sol_price.pack());
+    amm.update(&accounts_map).unwrap();
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: native_mint::id(),
+            output_mint: USDC_MINT,
+        })
+        .unwrap();
+    assert!(quote.out_amount > 9_990_000 && quote.out_amount < 9_995_000);
+
+    let sol_quote_params = QuoteParams {
+        in_amount: 1_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: USDC_MINT,
+    };
+    // Past the max age the prices are stale, even when trading
+    let stale_clock = Clock { slot: 1_051, ..clock };
+    accounts_map.insert(sysvar::clock::ID, create_account_for_test(&stale_clock).data);
+    amm.update(&accounts_map).unwrap();
+    assert!(amm.quote(&sol_quote_params).is_err());
+    accounts_map.insert(sysvar::clock::ID, create_account_for_test(&clock).data);
+    amm.update(&accounts_map).unwrap();
+    assert!(amm.quote(&sol_quote_params).is_ok());
+
+    let halted_price = SimplePrice { status: 0, ..sol_price };
+    accounts_map.insert(state.oracle_main_account, halted_price.pack());
+    amm.update(&accounts_map).unwrap();
+    assert!(amm.quote(&sol_quote_params).is_err());
+    accounts_map.insert(state.oracle_main_account, sol_price.pack());
+
+    // A fee above the in amount errors instead of wrapping
+    let greedy_state = LifinityAmmState { owner_trade_fee_numerator: 2, owner_trade_fee_denominator: 1, ..state };
+    accounts_map.insert(key, greedy_state.pack());
+    amm.update(&accounts_map).unwrap();
+    assert!(amm.quote(&sol_quote_params).is_err());
+    accounts_map.insert(key, state.pack());
+    amm.update(&accounts_map).unwrap();
+
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: USDC_MINT,
+            destination_mint: native_mint::id(),
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::LifinityV2
+        }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 14);
+    assert_eq!(account_metas[6].pubkey, state.token_b_account);
+    assert_eq!(account_metas[13].pubkey, state.oracle_pc_account);
+
+    // The same pool on v1, quoted in USD without a pc oracle
+    let v1_state = LifinityAmmState {
+        bump_seed: Pubkey::find_program_address(&[key.as_ref()], &LIFINITY_V1_PROGRAM_ID).1,
+        token_b_mint: USDT_MINT,
+        oracle_pc_account: Pubkey::default(),
+        ..state
+    };
+    let v1_amm = LifinityAmm::from_keyed_account(&new_keyed_account(
+        key,
+        v1_state.pack(),
+        LIFINITY_V1_PROGRAM_ID,
+        Some(params),
+    ))
+    .unwrap();
+    assert_eq!(v1_amm.get_accounts_to_update().len(), 5);
+    let swap_leg_and_account_metas = v1_amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: native_mint::id(),
+            destination_mint: USDT_MINT,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap { swap: Swap::Lifinity }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 14);
+    assert_eq!(account_metas[12].pubkey, v1_state.oracle_main_account);
+    assert_eq!(account_metas[13].pubkey, LIFINITY_V1_CONFIG_ACCOUNT);
+}
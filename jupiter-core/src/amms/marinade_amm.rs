@@ -0,0 +1,516 @@
+use anchor_lang::{solana_program::hash::hash, ToAccountMetas};
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use spl_token::{native_mint, state::Account as TokenAccount};
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{program_pack::Pack, pubkey, pubkey::Pubkey, system_program, sysvar};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::{find_temp_wsol_token_account_address, get_user_token_account_setup};
+use jupiter::{
+    accounts::{MarinadeDeposit, MarinadeUnstake},
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const MARINADE_PROGRAM_ID: Pubkey = pubkey!("MarBmsSgKXdrN1egZf5sqe1TMai9K1rChYNDJgjq7aD");
+
+const BASIS_POINTS_DENOMINATOR: u64 = 10_000;
+
+/// amount * numerator / denominator, the amount itself without a denominator as the program does
+fn proportional(amount: u64, numerator: u64, denominator: u64) -> u64 {
+    if denominator == 0 {
+        return amount;
+    }
+    (amount as u128 * numerator as u128 / denominator as u128) as u64
+}
+
+/// The parts of the Marinade state needed to quote and build deposits and liquid unstakes.
+/// Fees are in basis points.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MarinadeState {
+    pub msol_mint: Pubkey,
+    pub treasury_msol_account: Pubkey,
+    pub reserve_bump_seed: u8,
+    pub msol_mint_authority_bump_seed: u8,
+    /// Lamports the program keeps in the SOL leg so it stays rent exempt
+    pub rent_exempt_for_token_acc: u64,
+    pub delayed_unstake_cooling_down: u64,
+    pub total_active_balance: u64,
+    pub liq_pool_sol_leg_bump_seed: u8,
+    pub liq_pool_msol_leg_authority_bump_seed: u8,
+    pub liq_pool_msol_leg: Pubkey,
+    pub lp_liquidity_target: u64,
+    pub lp_max_fee: u32,
+    pub lp_min_fee: u32,
+    pub available_reserve_balance: u64,
+    pub msol_supply: u64,
+    pub circulating_ticket_balance: u64,
+    pub min_deposit: u64,
+    pub staking_sol_cap: u64,
+    pub emergency_cooling_down: u64,
+}
+
+impl MarinadeState {
+    /// End of the fields read, the account is allocated larger
+    pub const LEN: usize = 576;
+
+    pub fn discriminator() -> [u8; 8] {
+        hash(b"account:State").to_bytes()[..8].try_into().unwrap()
+    }
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() < Self::LEN || account_data[..8] != Self::discriminator() {
+            return Err(anyhow!("Invalid Marinade state account"));
+        }
+        let u64_at = |offset: usize| u64::from_le_bytes(account_data[offset..offset + 8].try_into().unwrap());
+        let u32_at = |offset: usize| u32::from_le_bytes(account_data[offset..offset + 4].try_into().unwrap());
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(account_data[offset..offset + 32].try_into().unwrap());
+        Ok(Self {
+            msol_mint: pubkey_at(8),
+            treasury_msol_account: pubkey_at(104),
+            reserve_bump_seed: account_data[136],
+            msol_mint_authority_bump_seed: account_data[137],
+            rent_exempt_for_token_acc: u64_at(138),
+            // The stake system starts at 150 with the stake list, the validator system at 264
+            delayed_unstake_cooling_down: u64_at(226),
+            total_active_balance: u64_at(376),
+            // The liquidity pool starts at 385 with the lp mint
+            liq_pool_sol_leg_bump_seed: account_data[418],
+            liq_pool_msol_leg_authority_bump_seed: account_data[419],
+            liq_pool_msol_leg: pubkey_at(420),
+            lp_liquidity_target: u64_at(452),
+            lp_max_fee: u32_at(460),
+            lp_min_fee: u32_at(464),
+            available_reserve_balance: u64_at(496),
+            msol_supply: u64_at(504),
+            circulating_ticket_balance: u64_at(528),
+            min_deposit: u64_at(544),
+            staking_sol_cap: u64_at(560),
+            emergency_cooling_down: u64_at(568),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[..8].copy_from_slice(&Self::discriminator());
+        for (offset, pubkey) in [
+            (8, self.msol_mint),
+            (104, self.treasury_msol_account),
+            (420, self.liq_pool_msol_leg),
+        ] {
+            data[offset..offset + 32].copy_from_slice(pubkey.as_ref());
+        }
+        for (offset, value) in [
+            (136, self.reserve_bump_seed),
+            (137, self.msol_mint_authority_bump_seed),
+            (418, self.liq_pool_sol_leg_bump_seed),
+            (419, self.liq_pool_msol_leg_authority_bump_seed),
+        ] {
+            data[offset] = value;
+        }
+        for (offset, value) in [
+            (138, self.rent_exempt_for_token_acc),
+            (226, self.delayed_unstake_cooling_down),
+            (376, self.total_active_balance),
+            (452, self.lp_liquidity_target),
+            (496, self.available_reserve_balance),
+            (504, self.msol_supply),
+            (528, self.circulating_ticket_balance),
+            (544, self.min_deposit),
+            (560, self.staking_sol_cap),
+            (568, self.emergency_cooling_down),
+        ] {
+            data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        data[460..464].copy_from_slice(&self.lp_max_fee.to_le_bytes());
+        data[464..468].copy_from_slice(&self.lp_min_fee.to_le_bytes());
+        data
+    }
+
+    /// Lamports backing the mSOL supply, tickets waiting to be claimed excluded
+    pub fn total_virtual_staked_lamports(&self) -> u64 {
+        (self.total_active_balance as u128
+            + self.delayed_unstake_cooling_down as u128
+            + self.emergency_cooling_down as u128
+            + self.available_reserve_balance as u128)
+            .saturating_sub(self.circulating_ticket_balance as u128) as u64
+    }
+
+    pub fn calc_msol_from_lamports(&self, lamports: u64) -> u64 {
+        proportional(lamports, self.msol_supply, self.total_virtual_staked_lamports())
+    }
+
+    pub fn calc_lamports_from_msol(&self, msol_amount: u64) -> u64 {
+        proportional(msol_amount, self.total_virtual_staked_lamports(), self.msol_supply)
+    }
+
+    /// Liquid unstake fee for the lamports left in the SOL leg after the unstake, from the max fee
+    /// on an empty leg down to the min fee at the liquidity target
+    pub fn liquid_unstake_fee(&self, lamports_after: u64) -> u32 {
+        if lamports_after >= self.lp_liquidity_target {
+            return self.lp_min_fee;
+        }
+        let delta = self.lp_max_fee.saturating_sub(self.lp_min_fee) as u128;
+        self.lp_max_fee - (delta * lamports_after as u128 / self.lp_liquidity_target as u128) as u32
+    }
+}
+
+/// Marinade liquid staking: SOL deposits buy the mSOL of the liquidity pool first and mint the rest,
+/// liquid unstakes sell mSOL to the liquidity pool SOL leg for a fee growing as the leg drains.
+/// Liquid unstakes cannot be routed yet: the router only hands amms account data, never the SOL leg
+/// lamports, so they are quoted as not enough liquidity and left out of routes.
+pub struct MarinadeAmm {
+    key: Pubkey,
+    state: MarinadeState,
+    liq_pool_msol_leg_balance: u64,
+    /// The SOL leg is a system account whose lamports do not come with the account data
+    liq_pool_sol_leg_lamports: Option<u64>,
+    program_id: Pubkey,
+}
+
+impl MarinadeAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        Ok(Self {
+            key: keyed_account.key,
+            state: MarinadeState::load(&keyed_account.account.data)?,
+            liq_pool_msol_leg_balance: 0,
+            liq_pool_sol_leg_lamports: None,
+            program_id: keyed_account.account.owner,
+        })
+    }
+
+    /// Lamports of the liquidity pool SOL leg, a system account `update` only sees the data of.
+    /// Liquid unstakes are not quoted until then. The router never calls this, only direct users of the amm can.
+    pub fn set_liq_pool_sol_leg_lamports(&mut self, lamports: u64) {
+        self.liq_pool_sol_leg_lamports = Some(lamports);
+    }
+
+    fn create_pda(&self, seed: &[u8], bump: u8) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[self.key.as_ref(), seed, &[bump]], &self.program_id)
+            .map_err(|_| anyhow!("Invalid {} bump seed in Marinade state {}", String::from_utf8_lossy(seed), self.key))
+    }
+
+    pub fn liq_pool_sol_leg_pda(&self) -> Result<Pubkey> {
+        self.create_pda(b"liq_sol", self.state.liq_pool_sol_leg_bump_seed)
+    }
+
+    fn is_deposit(&self, input_mint: &Pubkey) -> bool {
+        *input_mint == native_mint::id()
+    }
+
+    fn quote_deposit(&self, lamports: u64) -> Result<Quote> {
+        if lamports < self.state.min_deposit {
+            return Err(anyhow!(
+                "Marinade deposit of {} lamports is under the minimum of {}",
+                lamports,
+                self.state.min_deposit
+            ));
+        }
+        let msol_amount = self.state.calc_msol_from_lamports(lamports);
+        let msol_swapped = msol_amount.min(self.liq_pool_msol_leg_balance);
+        let lamports_swapped = if msol_swapped == msol_amount {
+            lamports
+        } else {
+            self.state.calc_lamports_from_msol(msol_swapped).min(lamports)
+        };
+        let lamports_staked = lamports - lamports_swapped;
+        let msol_minted = self.state.calc_msol_from_lamports(lamports_staked);
+        let staking_sol_cap_reached = lamports_staked > 0
+            && self.state.total_virtual_staked_lamports() as u128 + lamports_staked as u128
+                > self.state.staking_sol_cap as u128;
+
+        Ok(Quote {
+            not_enough_liquidity: staking_sol_cap_reached,
+            in_amount: lamports,
+            out_amount: msol_swapped + msol_minted,
+            fee_mint: native_mint::id(),
+            ..Quote::default()
+        })
+    }
+
+    /// Without the SOL leg lamports the liquidity is unknown and the quote is not enough liquidity
+    fn quote_liquid_unstake(&self, msol_amount: u64) -> Quote {
+        let available_lamports = match self.liq_pool_sol_leg_lamports {
+            Some(lamports) => lamports.saturating_sub(self.state.rent_exempt_for_token_acc),
+            None => {
+                return Quote {
+                    not_enough_liquidity: true,
+                    in_amount: msol_amount,
+                    fee_mint: self.state.msol_mint,
+                    ..Quote::default()
+                }
+            }
+        };
+        let lamports = self.state.calc_lamports_from_msol(msol_amount);
+        let fee = if lamports >= available_lamports {
+            self.state.lp_max_fee
+        } else {
+            self.state.liquid_unstake_fee(available_lamports - lamports)
+        };
+        let msol_fee = proportional(msol_amount, fee as u64, BASIS_POINTS_DENOMINATOR);
+        let out_amount = self.state.calc_lamports_from_msol(msol_amount - msol_fee);
+
+        Quote {
+            not_enough_liquidity: out_amount > available_lamports,
+            in_amount: msol_amount,
+            out_amount,
+            fee_amount: msol_fee,
+            fee_mint: self.state.msol_mint,
+            fee_pct: Decimal::from(fee) / Decimal::from(BASIS_POINTS_DENOMINATOR),
+            ..Quote::default()
+        }
+    }
+
+    fn clone(&self) -> MarinadeAmm {
+        MarinadeAmm {
+            key: self.key,
+            state: self.state,
+            liq_pool_msol_leg_balance: self.liq_pool_msol_leg_balance,
+            liq_pool_sol_leg_lamports: self.liq_pool_sol_leg_lamports,
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for MarinadeAmm {
+    fn label(&self) -> String {
+        String::from("Marinade")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![native_mint::id(), self.state.msol_mint]
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![self.key, self.state.liq_pool_msol_leg]
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let get_account = |key: &Pubkey| {
+            accounts_map
+                .get(key)
+                .with_context(|| format!("Missing account {}", key))
+        };
+        self.state = MarinadeState::load(get_account(&self.key)?)?;
+        self.liq_pool_msol_leg_balance = TokenAccount::unpack(get_account(&self.state.liq_pool_msol_leg)?)?.amount;
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        if self.is_deposit(&quote_params.input_mint) {
+            self.quote_deposit(quote_params.in_amount)
+        } else {
+            Ok(self.quote_liquid_unstake(quote_params.in_amount))
+        }
+    }
+
+    /// Native SOL moves through the user's wrapped SOL token accounts
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let (swap, account_metas) = if self.is_deposit(source_mint) {
+            let account_metas = MarinadeDeposit {
+                marinade_finance_program: self.program_id,
+                state: self.key,
+                msol_mint: self.state.msol_mint,
+                liq_pool_sol_leg_pda: self.liq_pool_sol_leg_pda()?,
+                liq_pool_msol_leg: self.state.liq_pool_msol_leg,
+                liq_pool_msol_leg_authority: self.create_pda(
+                    b"liq_st_sol_authority",
+                    self.state.liq_pool_msol_leg_authority_bump_seed,
+                )?,
+                reserve_pda: self.create_pda(b"reserve", self.state.reserve_bump_seed)?,
+                transfer_from: *user_transfer_authority,
+                mint_to: *user_destination_token_account,
+                msol_mint_authority: self.create_pda(b"st_mint", self.state.msol_mint_authority_bump_seed)?,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                user_wsol_token_account: *user_source_token_account,
+                temp_wsol_token_account: find_temp_wsol_token_account_address(user_transfer_authority),
+                user_transfer_authority: *user_transfer_authority,
+                wsol_mint: native_mint::id(),
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None);
+            (Swap::MarinadeDeposit, account_metas)
+        } else {
+            let account_metas = MarinadeUnstake {
+                marinade_finance_program: self.program_id,
+                state: self.key,
+                msol_mint: self.state.msol_mint,
+                liq_pool_sol_leg_pda: self.liq_pool_sol_leg_pda()?,
+                liq_pool_msol_leg: self.state.liq_pool_msol_leg,
+                treasury_msol_account: self.state.treasury_msol_account,
+                get_msol_from: *user_source_token_account,
+                get_msol_from_authority: *user_transfer_authority,
+                transfer_sol_to: *user_transfer_authority,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                user_wsol_token_account: *user_destination_token_account,
+            }
+            .to_account_metas(None);
+            (Swap::MarinadeUnstake, account_metas)
+        };
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_marinade_amm() {
+    use crate::amms::test_harness::{new_keyed_account, token_account_data};
+
+    let key = Pubkey::new_unique();
+    let bump = |seed: &[u8]| Pubkey::find_program_address(&[key.as_ref(), seed], &MARINADE_PROGRAM_ID).1;
+    // 1 mSOL is worth 1.1 SOL
+    let state = MarinadeState {
+        msol_mint: Pubkey::new_unique(),
+        treasury_msol_account: Pubkey::new_unique(),
+        reserve_bump_seed: bump(b"reserve"),
+        msol_mint_authority_bump_seed: bump(b"st_mint"),
+        rent_exempt_for_token_acc: 2_039_280,
+        delayed_unstake_cooling_down: 0,
+        total_active_balance: 1_050_000_000_000_000,
+        liq_pool_sol_leg_bump_seed: bump(b"liq_sol"),
+        liq_pool_msol_leg_authority_bump_seed: bump(b"liq_st_sol_authority"),
+        liq_pool_msol_leg: Pubkey::new_unique(),
+        lp_liquidity_target: 10_000_000_000_000,
+        lp_max_fee: 300,
+        lp_min_fee: 30,
+        available_reserve_balance: 60_000_000_000_000,
+        msol_supply: 1_000_000_000_000_000,
+        circulating_ticket_balance: 10_000_000_000_000,
+        min_deposit: 1_000,
+        staking_sol_cap: u64::MAX,
+        emergency_cooling_down: 0,
+    };
+    let data = state.pack();
+    assert_eq!(MarinadeState::load(&data).unwrap(), state);
+    assert_eq!(state.total_virtual_staked_lamports(), 1_100_000_000_000_000);
+
+    let mut amm =
+        MarinadeAmm::from_keyed_account(&new_keyed_account(key, data.clone(), MARINADE_PROGRAM_ID, None)).unwrap();
+    let msol_leg = token_account_data(state.msol_mint, 500_000_000);
+    amm.update(&vec![(key, data), (state.liq_pool_msol_leg, msol_leg)].into_iter().collect())
+        .unwrap();
+
+    // Half of the mSOL comes from the liquidity pool, the other half is minted
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_100_000_000,
+            input_mint: native_mint::id(),
+            output_mint: state.msol_mint,
+        })
+        .unwrap();
+    assert_eq!(quote.out_amount, 1_000_000_000);
+    assert!(!quote.not_enough_liquidity);
+    assert!(amm
+        .quote(&QuoteParams {
+            in_amount: 100,
+            input_mint: native_mint::id(),
+            output_mint: state.msol_mint,
+        })
+        .is_err());
+
+    let unstake_quote_params = QuoteParams {
+        in_amount: 1_000_000_000,
+        input_mint: state.msol_mint,
+        output_mint: native_mint::id(),
+    };
+    // Without the SOL leg lamports the liquidity is unknown, so the router cannot route liquid unstakes
+    assert!(amm.quote(&unstake_quote_params).unwrap().not_enough_liquidity);
+    let mut router = crate::router::Router::new();
+    router.add_amm(amm.clone_amm());
+    assert!(router.best_quote(&unstake_quote_params).is_none());
+
+    // 5_000 SOL left after the unstake is half of the target, halfway between the max and min fees
+    let rent_exempt_lamports = state.rent_exempt_for_token_acc;
+    amm.set_liq_pool_sol_leg_lamports(5_001_100_000_000 + rent_exempt_lamports);
+    let quote = amm.quote(&unstake_quote_params).unwrap();
+    assert_eq!(quote.fee_pct, Decimal::new(165, 4));
+    assert_eq!(quote.fee_amount, 16_500_000);
+    assert_eq!(quote.out_amount, 1_081_850_000);
+    assert!(!quote.not_enough_liquidity);
+
+    // Draining the SOL leg takes the max fee of 3% and still lacks the liquidity
+    amm.set_liq_pool_sol_leg_lamports(1_000_000_000 + rent_exempt_lamports);
+    let quote = amm.quote(&unstake_quote_params).unwrap();
+    assert_eq!(quote.fee_amount, 30_000_000);
+    assert_eq!(quote.out_amount, 1_067_000_000);
+    assert!(quote.not_enough_liquidity);
+
+    let user = Pubkey::new_unique();
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: native_mint::id(),
+            destination_mint: state.msol_mint,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: user,
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_100_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::MarinadeDeposit
+        }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 17);
+    assert_eq!(
+        account_metas[3].pubkey,
+        Pubkey::find_program_address(&[key.as_ref(), b"liq_sol"], &MARINADE_PROGRAM_ID).0
+    );
+    assert_eq!(account_metas[13].pubkey, find_temp_wsol_token_account_address(&user));
+
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: state.msol_mint,
+            destination_mint: native_mint::id(),
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: user,
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::MarinadeUnstake
+        }
+    ));
+    assert_eq!(swap_leg_and_account_metas.account_metas.len(), 12);
+}
@@ -0,0 +1,562 @@
+use anchor_lang::{prelude::AccountMeta, ToAccountMetas};
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use spl_math::uint::U256;
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, convert::TryInto, str::FromStr};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey,
+    pubkey::Pubkey,
+};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::get_user_token_account_setup;
+use jupiter::{
+    accounts::MercurialSwap,
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const MERCURIAL_PROGRAM_ID: Pubkey = pubkey!("MERLuDFBMmsHnsBPZw2sDQZHvXFMwp8EdjudcU2HKky");
+
+/// Largest pool the program supports
+pub const MAX_TOKENS: usize = 4;
+const FEE_DENOMINATOR: u64 = 10_000_000_000;
+/// Newton iterations before giving up on convergence, as in the program
+const MAX_ITERATIONS: usize = 256;
+
+/// Mercurial stable swap account, the first token accounts length entries of the arrays are used
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MercurialSwapInfo {
+    pub version: u8,
+    pub is_initialized: bool,
+    pub nonce: u8,
+    pub amplification_coefficient: u64,
+    /// Trade fee over 10^10, taken from the output
+    pub fee_numerator: u64,
+    /// Share of the trade fee going to the admin over 10^10
+    pub admin_fee_numerator: u64,
+    pub token_accounts_length: u8,
+    pub precision_factor: u64,
+    /// Scale each token amount to the same precision before it enters the invariant
+    pub precision_multipliers: [u64; MAX_TOKENS],
+    pub token_accounts: [Pubkey; MAX_TOKENS],
+    pub pool_mint: Pubkey,
+    pub admin_token_mint: Pubkey,
+}
+
+impl MercurialSwapInfo {
+    pub fn token_accounts(&self) -> &[Pubkey] {
+        &self.token_accounts[..self.token_accounts_length as usize]
+    }
+}
+
+impl Sealed for MercurialSwapInfo {}
+
+impl IsInitialized for MercurialSwapInfo {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for MercurialSwapInfo {
+    const LEN: usize = 260;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let u64_at = |offset: usize| u64::from_le_bytes(input[offset..offset + 8].try_into().unwrap());
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(input[offset..offset + 32].try_into().unwrap());
+        let token_accounts_length = input[27];
+        if !(2..=MAX_TOKENS as u8).contains(&token_accounts_length) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut precision_multipliers = [0; MAX_TOKENS];
+        let mut token_accounts = [Pubkey::default(); MAX_TOKENS];
+        for index in 0..MAX_TOKENS {
+            precision_multipliers[index] = u64_at(36 + index * 8);
+            token_accounts[index] = pubkey_at(68 + index * 32);
+        }
+        Ok(Self {
+            version: input[0],
+            is_initialized: match input[1] {
+                0 => false,
+                1 => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: input[2],
+            amplification_coefficient: u64_at(3),
+            fee_numerator: u64_at(11),
+            admin_fee_numerator: u64_at(19),
+            token_accounts_length,
+            precision_factor: u64_at(28),
+            precision_multipliers,
+            token_accounts,
+            pool_mint: pubkey_at(196),
+            admin_token_mint: pubkey_at(228),
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        output[0] = self.version;
+        output[1] = self.is_initialized as u8;
+        output[2] = self.nonce;
+        output[3..11].copy_from_slice(&self.amplification_coefficient.to_le_bytes());
+        output[11..19].copy_from_slice(&self.fee_numerator.to_le_bytes());
+        output[19..27].copy_from_slice(&self.admin_fee_numerator.to_le_bytes());
+        output[27] = self.token_accounts_length;
+        output[28..36].copy_from_slice(&self.precision_factor.to_le_bytes());
+        for index in 0..MAX_TOKENS {
+            output[36 + index * 8..44 + index * 8].copy_from_slice(&self.precision_multipliers[index].to_le_bytes());
+            output[68 + index * 32..100 + index * 32].copy_from_slice(self.token_accounts[index].as_ref());
+        }
+        output[196..228].copy_from_slice(self.pool_mint.as_ref());
+        output[228..260].copy_from_slice(self.admin_token_mint.as_ref());
+    }
+}
+
+/// The StableSwap invariant of a pool of any number of tokens, over amounts scaled to the same precision
+pub struct MultiStableSwap {
+    pub amp_factor: u64,
+}
+
+impl MultiStableSwap {
+    /// Invariant D of the pool, found with Newton's method
+    pub fn compute_d(&self, amounts: &[U256]) -> Option<U256> {
+        let n_coins = U256::from(amounts.len());
+        let sum_x = amounts.iter().fold(U256::zero(), |sum, amount| sum + amount);
+        if sum_x.is_zero() {
+            return Some(U256::zero());
+        }
+        let ann = U256::from(self.amp_factor).checked_mul(n_coins)?;
+
+        let mut d = sum_x;
+        for _ in 0..MAX_ITERATIONS {
+            let mut d_prod = d;
+            for amount in amounts {
+                d_prod = d_prod.checked_mul(d)?.checked_div(amount * n_coins)?;
+            }
+            let d_previous = d;
+            // d = (ann * sum_x + d_prod * n_coins) * d / ((ann - 1) * d + (n_coins + 1) * d_prod)
+            let numerator = d.checked_mul(d_prod * n_coins + ann * sum_x)?;
+            let denominator = d.checked_mul(ann.checked_sub(U256::one())?)? + d_prod * (n_coins + 1);
+            d = numerator.checked_div(denominator)?;
+            if abs_diff(d, d_previous) <= U256::one() {
+                break;
+            }
+        }
+        Some(d)
+    }
+
+    /// Amount of token j keeping the invariant D given the amounts of the other tokens
+    pub fn compute_y(&self, amounts: &[U256], j: usize, d: U256) -> Option<U256> {
+        let n_coins = U256::from(amounts.len());
+        let ann = U256::from(self.amp_factor).checked_mul(n_coins)?;
+        // c = D ** (n + 1) / (n ** (2 * n) * prod' * A)
+        let mut c = d;
+        let mut sum = U256::zero();
+        for (index, amount) in amounts.iter().enumerate() {
+            if index != j {
+                sum += *amount;
+                c = c.checked_mul(d)?.checked_div(amount * n_coins)?;
+            }
+        }
+        c = c.checked_mul(d)?.checked_div(ann * n_coins)?;
+        // b = sum' - (A * n ** n - 1) * D / (A * n ** n), D is subtracted in the denominator below
+        let b = d.checked_div(ann)? + sum;
+
+        let mut y = d;
+        for _ in 0..MAX_ITERATIONS {
+            let y_previous = y;
+            // y = (y * y + c) / (2 * y + b - D)
+            let y_numerator = y.checked_mul(y)?.checked_add(c)?;
+            let y_denominator = (y * 2u8 + b).checked_sub(d)?;
+            y = y_numerator.checked_div(y_denominator)?;
+            if abs_diff(y, y_previous) <= U256::one() {
+                break;
+            }
+        }
+        Some(y)
+    }
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// Mercurial stable pools of 2, 3 or 4 tokens
+pub struct MercurialAmm {
+    key: Pubkey,
+    state: MercurialSwapInfo,
+    reserve_mints: Vec<Pubkey>,
+    reserves: Vec<u64>,
+    program_id: Pubkey,
+}
+
+impl MercurialAmm {
+    /// Mints are not in the swap state, they come with the keyed account params in the order of
+    /// the token accounts as `{"token_mints": ["EPjF...", "Es9v..."]}`
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        let state = MercurialSwapInfo::unpack(
+            keyed_account
+                .account
+                .data
+                .get(..MercurialSwapInfo::LEN)
+                .with_context(|| format!("Invalid Mercurial swap account {}", keyed_account.key))?,
+        )?;
+        let reserve_mints = keyed_account
+            .params
+            .as_ref()
+            .and_then(|params| params.get("token_mints"))
+            .and_then(|token_mints| token_mints.as_array())
+            .with_context(|| format!("Missing token_mints param for Mercurial swap {}", keyed_account.key))?
+            .iter()
+            .map(|token_mint| {
+                token_mint
+                    .as_str()
+                    .and_then(|token_mint| Pubkey::from_str(token_mint).ok())
+                    .with_context(|| format!("Invalid token mint for Mercurial swap {}", keyed_account.key))
+            })
+            .collect::<Result<Vec<Pubkey>>>()?;
+        if reserve_mints.len() != state.token_accounts().len() {
+            return Err(anyhow!(
+                "Mercurial swap {} has {} token accounts but {} token mints",
+                keyed_account.key,
+                state.token_accounts().len(),
+                reserve_mints.len()
+            ));
+        }
+
+        Ok(Self {
+            key: keyed_account.key,
+            reserves: vec![0; reserve_mints.len()],
+            reserve_mints,
+            state,
+            program_id: keyed_account.account.owner,
+        })
+    }
+
+    fn get_authority(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[&self.key.to_bytes(), &[self.state.nonce]], &self.program_id)
+            .map_err(|_| anyhow!("Invalid nonce for Mercurial swap {}", self.key))
+    }
+
+    fn token_index(&self, mint: &Pubkey) -> Result<usize> {
+        self.reserve_mints
+            .iter()
+            .position(|reserve_mint| reserve_mint == mint)
+            .with_context(|| format!("Mint {} is not in Mercurial swap {}", mint, self.key))
+    }
+
+    /// Output of the swap and the trade fee taken from it
+    fn swap_to(&self, source_amount: u64, i: usize, j: usize) -> Option<(u64, u64)> {
+        let multipliers = &self.state.precision_multipliers;
+        let mut amounts: Vec<U256> = self
+            .reserves
+            .iter()
+            .zip(multipliers.iter())
+            .map(|(reserve, multiplier)| U256::from(*reserve) * U256::from(*multiplier))
+            .collect();
+        let stable_swap = MultiStableSwap {
+            amp_factor: self.state.amplification_coefficient,
+        };
+        let d = stable_swap.compute_d(&amounts)?;
+        amounts[i] = amounts[i].checked_add(U256::from(source_amount) * U256::from(multipliers[i]))?;
+        let y = stable_swap.compute_y(&amounts, j, d)?;
+
+        let dy: u64 = amounts[j].checked_sub(y)?.checked_div(U256::from(multipliers[j]))?.try_into().ok()?;
+        let dy_fee: u64 = (U256::from(dy) * U256::from(self.state.fee_numerator) / U256::from(FEE_DENOMINATOR))
+            .try_into()
+            .ok()?;
+        Some((dy.checked_sub(dy_fee)?, dy_fee))
+    }
+
+    fn clone(&self) -> MercurialAmm {
+        MercurialAmm {
+            key: self.key,
+            state: self.state,
+            reserve_mints: self.reserve_mints.clone(),
+            reserves: self.reserves.clone(),
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for MercurialAmm {
+    fn label(&self) -> String {
+        String::from("Mercurial")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        self.reserve_mints.clone()
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.key];
+        accounts.extend_from_slice(self.state.token_accounts());
+        accounts
+    }
+
+    /// The swap state is reloaded for amplification and fee changes, its token accounts must stay the same
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let state = MercurialSwapInfo::unpack(
+            accounts_map
+                .get(&self.key)
+                .with_context(|| format!("Missing account {}", self.key))?
+                .get(..MercurialSwapInfo::LEN)
+                .with_context(|| format!("Invalid Mercurial swap account {}", self.key))?,
+        )?;
+        if state.token_accounts() != self.state.token_accounts() {
+            return Err(anyhow!("Token accounts of Mercurial swap {} changed", self.key));
+        }
+        self.state = state;
+        self.reserves = self
+            .state
+            .token_accounts()
+            .iter()
+            .map(|token_account| {
+                let data = accounts_map
+                    .get(token_account)
+                    .with_context(|| format!("Missing account {}", token_account))?;
+                Ok(TokenAccount::unpack(data)?.amount)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let i = self.token_index(&quote_params.input_mint)?;
+        let j = self.token_index(&quote_params.output_mint)?;
+        if i == j {
+            return Err(anyhow!("Mercurial swap {} cannot swap a token for itself", self.key));
+        }
+
+        let (out_amount, fee_amount) = self
+            .swap_to(quote_params.in_amount, i, j)
+            .context("quote failed")?;
+
+        Ok(Quote {
+            in_amount: quote_params.in_amount,
+            out_amount,
+            fee_amount,
+            fee_mint: quote_params.output_mint,
+            fee_pct: Decimal::from(self.state.fee_numerator) / Decimal::from(FEE_DENOMINATOR),
+            ..Quote::default()
+        })
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let mut account_metas = MercurialSwap {
+            swap_program: self.program_id,
+            swap_state: self.key,
+            token_program: spl_token::ID,
+            pool_authority: self.get_authority()?,
+            user_transfer_authority: *user_transfer_authority,
+            source_token_account: *user_source_token_account,
+            destination_token_account: *user_destination_token_account,
+        }
+        .to_account_metas(None);
+        // Every token account of the pool follows, the program finds the source and destination among them
+        account_metas.extend(
+            self.state
+                .token_accounts()
+                .iter()
+                .map(|token_account| AccountMeta::new(*token_account, false)),
+        );
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap: Swap::Mercurial },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_mercurial_3_pool() {
+    use crate::amms::test_harness::{new_keyed_account, token_account_data};
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use crate::router::{route_decoder::decode_route_instruction, Router};
+    use anchor_lang::InstructionData;
+    use jupiter::jupiter_override::Route;
+
+    // A 9 decimals stable next to USDC and USDT, all scaled to 9 decimals
+    let pai_mint = Pubkey::new_unique();
+    let key = Pubkey::new_unique();
+    let nonce = Pubkey::find_program_address(&[&key.to_bytes()], &MERCURIAL_PROGRAM_ID).1;
+    let token_accounts = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+    let state = MercurialSwapInfo {
+        version: 1,
+        is_initialized: true,
+        nonce,
+        amplification_coefficient: 200,
+        fee_numerator: 4_000_000,
+        admin_fee_numerator: 5_000_000_000,
+        token_accounts_length: 3,
+        precision_factor: 9,
+        precision_multipliers: [1_000, 1_000, 1, 0],
+        token_accounts: [token_accounts[0], token_accounts[1], token_accounts[2], Pubkey::default()],
+        pool_mint: Pubkey::new_unique(),
+        admin_token_mint: Pubkey::new_unique(),
+    };
+    let mut data = vec![0u8; MercurialSwapInfo::LEN];
+    MercurialSwapInfo::pack(state, &mut data).unwrap();
+    assert_eq!(MercurialSwapInfo::unpack(&data).unwrap(), state);
+
+    let params = serde_json::json!({
+        "token_mints": [USDC_MINT.to_string(), USDT_MINT.to_string(), pai_mint.to_string()]
+    });
+    let keyed_account = |data: Vec<u8>| new_keyed_account(key, data, MERCURIAL_PROGRAM_ID, Some(params.clone()));
+    assert!(MercurialAmm::from_keyed_account(&keyed_account(data[..MercurialSwapInfo::LEN - 1].to_vec())).is_err());
+    let mut amm = MercurialAmm::from_keyed_account(&keyed_account(data.clone())).unwrap();
+    assert_eq!(amm.get_reserve_mints(), vec![USDC_MINT, USDT_MINT, pai_mint]);
+    assert_eq!(amm.get_accounts_to_update(), vec![key, token_accounts[0], token_accounts[1], token_accounts[2]]);
+
+    let mut accounts_map: HashMap<Pubkey, Vec<u8>> = vec![
+        (token_accounts[0], USDC_MINT, 1_000_000_000_000),
+        (token_accounts[1], USDT_MINT, 1_000_000_000_000),
+        (token_accounts[2], pai_mint, 1_000_000_000_000_000),
+    ]
+    .into_iter()
+    .map(|(key, mint, amount)| (key, token_account_data(mint, amount)))
+    .collect();
+    accounts_map.insert(key, data);
+    amm.update(&accounts_map).unwrap();
+
+    // A balanced pool swaps close to 1:1 minus the 4 bps trade fee, whatever the decimals
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: USDT_MINT,
+        })
+        .unwrap();
+    assert!(quote.out_amount > 999_000_000 && quote.out_amount < 999_600_000);
+    assert_eq!(quote.fee_amount, (quote.out_amount + quote.fee_amount) * 4 / 10_000);
+    let quote_to_pai = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: pai_mint,
+        })
+        .unwrap();
+    assert!(quote_to_pai.out_amount / 1_000 - quote.out_amount <= 1);
+
+    // Fee changes of the swap state are picked up on update
+    let mut doubled_fee_data = vec![0u8; MercurialSwapInfo::LEN];
+    MercurialSwapInfo::pack(MercurialSwapInfo { fee_numerator: 8_000_000, ..state }, &mut doubled_fee_data).unwrap();
+    let mut doubled_fee_accounts_map = accounts_map.clone();
+    doubled_fee_accounts_map.insert(key, doubled_fee_data);
+    let mut doubled_fee_amm = amm.clone();
+    doubled_fee_amm.update(&doubled_fee_accounts_map).unwrap();
+    let doubled_fee_quote = doubled_fee_amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: USDT_MINT,
+        })
+        .unwrap();
+    assert_eq!(doubled_fee_quote.fee_pct, Decimal::new(8, 4));
+    assert!(doubled_fee_quote.fee_amount > quote.fee_amount);
+
+    // A token cannot be swapped for itself
+    assert!(amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: USDC_MINT,
+        })
+        .is_err());
+    // A token without a precision multiplier errors instead of panicking
+    let mut zero_multiplier_amm = amm.clone();
+    zero_multiplier_amm.state.precision_multipliers[1] = 0;
+    assert!(zero_multiplier_amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: USDT_MINT,
+        })
+        .is_err());
+    assert!(amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: Pubkey::new_unique(),
+        })
+        .is_err());
+
+    let user = Pubkey::new_unique();
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: USDC_MINT,
+            destination_mint: pai_mint,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: user,
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap { swap: Swap::Mercurial }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 10);
+    assert_eq!(account_metas[1].pubkey, key);
+    assert_eq!(account_metas[7..].iter().map(|account_meta| account_meta.pubkey).collect::<Vec<_>>(), token_accounts);
+
+    // The decoder takes every token account of the pool once it is in the router
+    let mut router = Router::new();
+    router.add_amm(amm.clone_amm());
+    let data = Route {
+        swap_leg: SwapLeg::Chain {
+            swap_legs: vec![
+                SwapLeg::Swap { swap: Swap::Mercurial },
+                SwapLeg::Swap { swap: Swap::Mercurial },
+            ],
+        },
+        in_amount: 1_000_000_000,
+        quoted_out_amount: 990_000_000,
+        slippage_bps: 50,
+        platform_fee_bps: 0,
+    }
+    .data();
+    let mut account_keys = vec![spl_token::ID, user, Pubkey::new_unique()];
+    account_keys.extend(account_metas.iter().map(|account_meta| account_meta.pubkey));
+    account_keys.extend(account_metas.iter().map(|account_meta| account_meta.pubkey));
+    let decoded_route = decode_route_instruction(&data, &account_keys, Some(&router)).unwrap();
+    let swaps = decoded_route.swap_leg.swaps();
+    assert_eq!(swaps[0].amm_key, Some(key));
+    assert_eq!(swaps[0].account_keys.len(), 10);
+    assert_eq!(swaps[1].label, "Mercurial");
+    assert_eq!(swaps[1].account_keys.len(), 10);
+}
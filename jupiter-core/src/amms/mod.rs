@@ -1,8 +1,23 @@
 pub mod amm;
+pub mod user_setup;
 
-mod spl_token_swap_amm;
-mod symmetry_token_swap;
+pub mod lifinity_amm;
+pub mod marinade_amm;
+pub mod mercurial_amm;
+pub mod openbook_amm;
+pub mod raydium_amm;
+pub mod raydium_clmm_amm;
+pub mod saber_add_decimals_amm;
+pub mod saber_amm;
+pub mod spl_token_swap_amm;
+pub mod step_token_swap_amm;
+pub mod symmetry_token_swap;
+pub mod whirlpool_amm;
 mod accounts;
+mod clmm;
+mod serum_accounts;
 
 #[cfg(test)]
-mod test_harness;
+pub mod program_test_harness;
+#[cfg(test)]
+pub mod test_harness;
@@ -0,0 +1,370 @@
+use anchor_lang::ToAccountMetas;
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{pubkey::Pubkey, sysvar};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::serum_accounts::{Order, SerumMarket, Slab};
+use super::user_setup::{find_open_orders_address, get_user_token_account_setup};
+use crate::constants::OPENBOOK_PROGRAM_ID;
+use crate::math::checked_ceil_div;
+use jupiter::{
+    accounts::{SerumSwap, SerumSwapMarket},
+    jupiter_override::{Swap, SwapLeg},
+    Side,
+};
+
+/// Taker fee of the base fee tier, the one of the open orders accounts Jupiter creates
+const TAKER_FEE_TENTH_OF_BPS: u128 = 40;
+const TENTH_OF_BPS_DENOMINATOR: u128 = 100_000;
+
+fn taker_fee(native_pc_amount: u128) -> Option<u128> {
+    checked_ceil_div(native_pc_amount.checked_mul(TAKER_FEE_TENTH_OF_BPS)?, TENTH_OF_BPS_DENOMINATOR)
+}
+
+/// Market order against a serum or openbook market. Selling coin is an ask taking the bids,
+/// buying coin is a bid taking the asks, in whole lots.
+pub struct OpenbookAmm {
+    key: Pubkey,
+    market: SerumMarket,
+    bids: Vec<Order>,
+    asks: Vec<Order>,
+    program_id: Pubkey,
+}
+
+impl OpenbookAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        let market = SerumMarket::load(&keyed_account.account.data)?;
+        if market.own_address != keyed_account.key {
+            return Err(anyhow!("Market {} is not stored at its own address", keyed_account.key));
+        }
+        if market.coin_lot_size == 0 || market.pc_lot_size == 0 {
+            return Err(anyhow!("Market {} has no lot sizes", keyed_account.key));
+        }
+        Ok(Self {
+            key: keyed_account.key,
+            market,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            program_id: keyed_account.account.owner,
+        })
+    }
+
+    fn side(&self, input_mint: &Pubkey) -> Side {
+        if *input_mint == self.market.coin_mint {
+            Side::Ask
+        } else {
+            Side::Bid
+        }
+    }
+
+    /// Sells the coin lots of the in amount to the best bids, the fee comes out of the pc received
+    fn quote_ask(&self, in_amount: u64) -> Option<Quote> {
+        let coin_lot_size = self.market.coin_lot_size as u128;
+        let mut coin_lots_remaining = in_amount as u128 / coin_lot_size;
+        let mut pc_lots: u128 = 0;
+        for bid in &self.bids {
+            if coin_lots_remaining == 0 {
+                break;
+            }
+            let fill = coin_lots_remaining.min(bid.quantity as u128);
+            coin_lots_remaining -= fill;
+            pc_lots += fill * bid.price as u128;
+        }
+        let coin_lots_filled = in_amount as u128 / coin_lot_size - coin_lots_remaining;
+        let native_pc_amount = pc_lots * self.market.pc_lot_size as u128;
+        let fee_amount = taker_fee(native_pc_amount)?;
+
+        Some(Quote {
+            not_enough_liquidity: coin_lots_remaining > 0,
+            in_amount: (coin_lots_filled * coin_lot_size) as u64,
+            out_amount: (native_pc_amount - fee_amount) as u64,
+            fee_amount: fee_amount as u64,
+            fee_mint: self.market.pc_mint,
+            ..Quote::default()
+        })
+    }
+
+    /// Buys coin lots from the best asks with the in amount, fee included, as the program caps it
+    fn quote_bid(&self, in_amount: u64) -> Option<Quote> {
+        let pc_lot_size = self.market.pc_lot_size as u128;
+        let max_native_pc_amount = in_amount as u128 * TENTH_OF_BPS_DENOMINATOR
+            / (TENTH_OF_BPS_DENOMINATOR + TAKER_FEE_TENTH_OF_BPS);
+        let mut pc_lots_remaining = max_native_pc_amount / pc_lot_size;
+        let mut coin_lots: u128 = 0;
+        let mut asks = self.asks.iter().filter(|ask| ask.price > 0).peekable();
+        while let Some(ask) = asks.peek() {
+            let fill = (pc_lots_remaining / ask.price as u128).min(ask.quantity as u128);
+            if fill == 0 {
+                break;
+            }
+            pc_lots_remaining -= fill * ask.price as u128;
+            coin_lots += fill;
+            if fill < ask.quantity as u128 {
+                break;
+            }
+            asks.next();
+        }
+        let native_pc_amount = (max_native_pc_amount / pc_lot_size - pc_lots_remaining) * pc_lot_size;
+        let fee_amount = taker_fee(native_pc_amount)?;
+
+        Some(Quote {
+            not_enough_liquidity: asks.peek().is_none() && pc_lots_remaining > 0,
+            in_amount: (native_pc_amount + fee_amount) as u64,
+            out_amount: (coin_lots * self.market.coin_lot_size as u128) as u64,
+            fee_amount: fee_amount as u64,
+            fee_mint: self.market.pc_mint,
+            ..Quote::default()
+        })
+    }
+
+    fn clone(&self) -> OpenbookAmm {
+        OpenbookAmm {
+            key: self.key,
+            market: self.market,
+            bids: self.bids.clone(),
+            asks: self.asks.clone(),
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for OpenbookAmm {
+    fn label(&self) -> String {
+        if self.program_id == OPENBOOK_PROGRAM_ID {
+            String::from("Openbook")
+        } else {
+            String::from("Serum")
+        }
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.market.coin_mint, self.market.pc_mint]
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![self.market.bids, self.market.asks]
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let get_account = |key: &Pubkey| {
+            accounts_map
+                .get(key)
+                .with_context(|| format!("Missing account {}", key))
+        };
+        self.bids = Slab::load_orders(get_account(&self.market.bids)?, true)?;
+        self.asks = Slab::load_orders(get_account(&self.market.asks)?, false)?;
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let quote = match self.side(&quote_params.input_mint) {
+            Side::Ask => self.quote_ask(quote_params.in_amount),
+            Side::Bid => self.quote_bid(quote_params.in_amount),
+        }
+        .context("quote failed")?;
+        Ok(Quote {
+            fee_pct: Decimal::from(TAKER_FEE_TENTH_OF_BPS as u64) / Decimal::from(TENTH_OF_BPS_DENOMINATOR as u64),
+            ..quote
+        })
+    }
+
+    /// Without an open orders address the one Jupiter creates for the user in the setup is used
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            open_order_address,
+            ..
+        } = swap_params;
+
+        let side = self.side(source_mint);
+        let (coin_wallet, pc_wallet) = match side {
+            Side::Ask => (*user_source_token_account, *user_destination_token_account),
+            Side::Bid => (*user_destination_token_account, *user_source_token_account),
+        };
+        let open_orders =
+            open_order_address.unwrap_or_else(|| find_open_orders_address(&self.key, user_transfer_authority));
+
+        let account_metas = SerumSwap {
+            SerumSwapmarket: SerumSwapMarket {
+                market: self.key,
+                open_orders,
+                request_queue: self.market.request_queue,
+                event_queue: self.market.event_queue,
+                bids: self.market.bids,
+                asks: self.market.asks,
+                coin_vault: self.market.coin_vault,
+                pc_vault: self.market.pc_vault,
+                vault_signer: self.market.vault_signer(&self.program_id)?,
+            },
+            authority: *user_transfer_authority,
+            order_payer_token_account: *user_source_token_account,
+            coin_wallet,
+            pc_wallet,
+            dex_program: self.program_id,
+            token_program: spl_token::ID,
+            rent: sysvar::rent::ID,
+        }
+        .to_account_metas(None);
+
+        let swap = if self.program_id == OPENBOOK_PROGRAM_ID {
+            Swap::Openbook { side }
+        } else {
+            Swap::Serum { side }
+        };
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup(&self) -> Option<AmmUserSetup> {
+        Some(if self.program_id == OPENBOOK_PROGRAM_ID {
+            AmmUserSetup::OpenbookOpenOrdersSetup { market: self.key }
+        } else {
+            AmmUserSetup::SerumDexOpenOrdersSetup { market: self.key }
+        })
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        let mut user_setup = get_user_token_account_setup(swap_params);
+        if swap_params.open_order_address.is_none() {
+            user_setup.extend(self.get_user_setup());
+        }
+        user_setup
+    }
+}
+
+#[test]
+fn test_openbook_amm() {
+    use crate::amms::test_harness::new_keyed_account;
+    use crate::constants::{SERUM_V3_PROGRAM_ID, USDC_MINT};
+    use spl_token::native_mint;
+
+    let key = Pubkey::new_unique();
+    let vault_signer_nonce = (0..u64::MAX)
+        .find(|nonce| Pubkey::create_program_address(&[&key.to_bytes(), &nonce.to_le_bytes()], &OPENBOOK_PROGRAM_ID).is_ok())
+        .unwrap();
+    // Lots of 0.001 SOL and 0.000001 USDC, prices are in USDC per SOL times 1_000
+    let market = SerumMarket {
+        own_address: key,
+        vault_signer_nonce,
+        coin_mint: native_mint::id(),
+        pc_mint: USDC_MINT,
+        coin_vault: Pubkey::new_unique(),
+        pc_vault: Pubkey::new_unique(),
+        request_queue: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        coin_lot_size: 1_000_000,
+        pc_lot_size: 1,
+        fee_rate_bps: 0,
+    };
+    let mut amm =
+        OpenbookAmm::from_keyed_account(&new_keyed_account(key, market.pack(), OPENBOOK_PROGRAM_ID, None)).unwrap();
+    assert_eq!(amm.label(), "Openbook");
+
+    // Bids of 1 SOL at 19.99 and 2 SOL at 19.98, asks of 1 SOL at 20.01 and 2 SOL at 20.02
+    let bids = vec![
+        Order { price: 19_980, quantity: 2_000 },
+        Order { price: 19_990, quantity: 1_000 },
+    ];
+    let asks = vec![
+        Order { price: 20_020, quantity: 2_000 },
+        Order { price: 20_010, quantity: 1_000 },
+    ];
+    let accounts_map: HashMap<Pubkey, Vec<u8>> = vec![(market.bids, Slab::pack(&bids)), (market.asks, Slab::pack(&asks))]
+        .into_iter()
+        .collect();
+    amm.update(&accounts_map).unwrap();
+    assert_eq!(amm.bids[0].price, 19_990);
+    assert_eq!(amm.asks[0].price, 20_010);
+
+    // 2 SOL and the dust under a lot, sold for 19.99 + 19.98 USDC less 0.04%
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 2_000_000_999,
+            input_mint: native_mint::id(),
+            output_mint: USDC_MINT,
+        })
+        .unwrap();
+    assert_eq!(quote.in_amount, 2_000_000_000);
+    assert_eq!(quote.fee_amount, 15_988);
+    assert_eq!(quote.out_amount, 39_954_012);
+    assert!(!quote.not_enough_liquidity);
+
+    // 40.03 USDC and the fee buy 1 SOL at 20.01 and 1 more at 20.02
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 40_046_012,
+            input_mint: USDC_MINT,
+            output_mint: native_mint::id(),
+        })
+        .unwrap();
+    assert_eq!(quote.out_amount, 2_000_000_000);
+    assert_eq!(quote.fee_amount, 16_012);
+    assert_eq!(quote.in_amount, 40_046_012);
+    assert!(
+        amm.quote(&QuoteParams {
+            in_amount: 4_000_000_000,
+            input_mint: native_mint::id(),
+            output_mint: USDC_MINT,
+        })
+        .unwrap()
+        .not_enough_liquidity
+    );
+
+    let user = Pubkey::new_unique();
+    let swap_params = SwapParams {
+        source_mint: USDC_MINT,
+        destination_mint: native_mint::id(),
+        user_source_token_account: Pubkey::new_unique(),
+        user_destination_token_account: Pubkey::new_unique(),
+        user_transfer_authority: user,
+        open_order_address: None,
+        quote_mint_to_referrer: None,
+        in_amount: 40_046_012,
+    };
+    assert_eq!(amm.get_user_setup(), Some(AmmUserSetup::OpenbookOpenOrdersSetup { market: key }));
+    let serum_amm = OpenbookAmm {
+        program_id: SERUM_V3_PROGRAM_ID,
+        ..amm.clone()
+    };
+    assert_eq!(serum_amm.get_user_setup(), Some(AmmUserSetup::SerumDexOpenOrdersSetup { market: key }));
+    assert_eq!(
+        amm.get_user_setup_steps(&swap_params),
+        vec![AmmUserSetup::OpenbookOpenOrdersSetup { market: key }]
+    );
+    let swap_leg_and_account_metas = amm.get_swap_leg_and_account_metas(&swap_params).unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::Openbook { side: Side::Bid }
+        }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 16);
+    assert_eq!(account_metas[1].pubkey, find_open_orders_address(&key, &user));
+    assert_eq!(account_metas[11].pubkey, swap_params.user_destination_token_account);
+    assert_eq!(account_metas[12].pubkey, swap_params.user_source_token_account);
+    assert_eq!(account_metas[13].pubkey, OPENBOOK_PROGRAM_ID);
+}
@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Result};
+use solana_program_test::{processor, BanksClient, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use std::collections::HashMap;
+
+use super::amm::{Amm, KeyedAccount};
+
+/// Runs amm instructions against the real programs in a BanksClient, to compare quotes with execution
+pub struct ProgramTestHarness {
+    pub context: ProgramTestContext,
+}
+
+impl ProgramTestHarness {
+    pub async fn start(program_test: ProgramTest) -> Self {
+        Self {
+            context: program_test.start_with_context().await,
+        }
+    }
+
+    pub fn banks_client(&mut self) -> &mut BanksClient {
+        &mut self.context.banks_client
+    }
+
+    pub async fn get_keyed_account(&mut self, key: Pubkey) -> Result<KeyedAccount> {
+        let account = self
+            .banks_client()
+            .get_account(key)
+            .await?
+            .ok_or_else(|| anyhow!("Account {} not found", key))?;
+        Ok(KeyedAccount {
+            key,
+            account,
+            params: None,
+        })
+    }
+
+    pub async fn update_amm(&mut self, amm: &mut dyn Amm) -> Result<()> {
+        let mut accounts_map = HashMap::new();
+        for key in amm.get_accounts_to_update() {
+            if let Some(account) = self.banks_client().get_account(key).await? {
+                accounts_map.insert(key, account.data);
+            }
+        }
+        amm.update(&accounts_map)
+    }
+
+    pub async fn get_token_balance(&mut self, key: Pubkey) -> Result<u64> {
+        Ok(TokenAccount::unpack(&self.get_keyed_account(key).await?.account.data)?.amount)
+    }
+
+    pub async fn process_instruction(&mut self, instruction: Instruction, signers: &[&Keypair]) -> Result<()> {
+        let recent_blockhash = self.banks_client().get_latest_blockhash().await?;
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            recent_blockhash,
+        );
+        Ok(self.context.banks_client.process_transaction(transaction).await?)
+    }
+}
+
+/// Spl token as native code, the BPF build bundled with solana-program-test does not load in its VM here
+pub fn add_spl_token_program(program_test: &mut ProgramTest) {
+    program_test.add_program("spl_token", spl_token::ID, processor!(spl_token::processor::Processor::process));
+}
+
+pub fn add_packed_account<T: Pack>(program_test: &mut ProgramTest, key: Pubkey, state: T, owner: Pubkey) {
+    let mut data = vec![0u8; T::LEN];
+    T::pack(state, &mut data).unwrap();
+    program_test.add_account(
+        key,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+pub fn add_mint(program_test: &mut ProgramTest, key: Pubkey, mint_authority: Pubkey, supply: u64, decimals: u8) {
+    add_packed_account(
+        program_test,
+        key,
+        Mint {
+            mint_authority: COption::Some(mint_authority),
+            supply,
+            decimals,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        spl_token::ID,
+    );
+}
+
+pub fn add_token_account(program_test: &mut ProgramTest, key: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) {
+    add_packed_account(
+        program_test,
+        key,
+        TokenAccount {
+            mint,
+            owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        },
+        spl_token::ID,
+    );
+}
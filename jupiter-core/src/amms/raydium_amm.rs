@@ -0,0 +1,394 @@
+use anchor_lang::ToAccountMetas;
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{program_pack::Pack, pubkey, pubkey::Pubkey};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::serum_accounts::{OpenOrders, SerumMarket};
+use super::user_setup::get_user_token_account_setup;
+use crate::math::checked_ceil_div;
+use jupiter::{
+    accounts::RaydiumSwap,
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const RAYDIUM_AMM_V4_PROGRAM_ID: Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+
+const AMM_AUTHORITY_SEED: &[u8] = b"amm authority";
+
+/// Statuses of the amm allowing swaps, SwapOnly pools keep no orders on the order book
+const STATUS_INITIALIZED: u64 = 1;
+const STATUS_SWAP_ONLY: u64 = 6;
+
+/// The parts of the Raydium AMM v4 state needed to quote and build swaps
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RaydiumAmmInfo {
+    pub status: u64,
+    pub nonce: u64,
+    pub coin_decimals: u64,
+    pub pc_decimals: u64,
+    pub swap_fee_numerator: u64,
+    pub swap_fee_denominator: u64,
+    pub need_take_pnl_coin: u64,
+    pub need_take_pnl_pc: u64,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub open_orders: Pubkey,
+    pub market: Pubkey,
+    pub market_program: Pubkey,
+    pub target_orders: Pubkey,
+}
+
+impl RaydiumAmmInfo {
+    pub const LEN: usize = 752;
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() != Self::LEN {
+            return Err(anyhow!("Invalid Raydium amm account"));
+        }
+        let u64_at = |offset: usize| u64::from_le_bytes(account_data[offset..offset + 8].try_into().unwrap());
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(account_data[offset..offset + 32].try_into().unwrap());
+        Ok(Self {
+            status: u64_at(0),
+            nonce: u64_at(8),
+            coin_decimals: u64_at(32),
+            pc_decimals: u64_at(40),
+            // Fees start at 128 with min separate, trade and pnl fees before the swap fee
+            swap_fee_numerator: u64_at(176),
+            swap_fee_denominator: u64_at(184),
+            need_take_pnl_coin: u64_at(192),
+            need_take_pnl_pc: u64_at(200),
+            coin_vault: pubkey_at(336),
+            pc_vault: pubkey_at(368),
+            coin_mint: pubkey_at(400),
+            pc_mint: pubkey_at(432),
+            lp_mint: pubkey_at(464),
+            open_orders: pubkey_at(496),
+            market: pubkey_at(528),
+            market_program: pubkey_at(560),
+            target_orders: pubkey_at(592),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        for (offset, value) in [
+            (0, self.status),
+            (8, self.nonce),
+            (32, self.coin_decimals),
+            (40, self.pc_decimals),
+            (176, self.swap_fee_numerator),
+            (184, self.swap_fee_denominator),
+            (192, self.need_take_pnl_coin),
+            (200, self.need_take_pnl_pc),
+        ] {
+            data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        for (offset, pubkey) in [
+            (336, self.coin_vault),
+            (368, self.pc_vault),
+            (400, self.coin_mint),
+            (432, self.pc_mint),
+            (464, self.lp_mint),
+            (496, self.open_orders),
+            (528, self.market),
+            (560, self.market_program),
+            (592, self.target_orders),
+        ] {
+            data[offset..offset + 32].copy_from_slice(pubkey.as_ref());
+        }
+        data
+    }
+}
+
+pub struct RaydiumAmm {
+    key: Pubkey,
+    state: RaydiumAmmInfo,
+    /// Coin and pc owned by the pool: vaults plus open orders funds, less the pnl not yet taken
+    reserves: [u64; 2],
+    market: Option<SerumMarket>,
+    program_id: Pubkey,
+}
+
+impl RaydiumAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        Ok(Self {
+            key: keyed_account.key,
+            state: RaydiumAmmInfo::load(&keyed_account.account.data)?,
+            reserves: Default::default(),
+            market: None,
+            program_id: keyed_account.account.owner,
+        })
+    }
+
+    fn get_authority(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[AMM_AUTHORITY_SEED, &[self.state.nonce as u8]], &self.program_id)
+            .map_err(|_| anyhow!("Invalid nonce for Raydium amm {}", self.key))
+    }
+
+    fn uses_order_book(&self) -> bool {
+        self.state.status != STATUS_SWAP_ONLY
+    }
+
+    fn clone(&self) -> RaydiumAmm {
+        RaydiumAmm {
+            key: self.key,
+            state: self.state,
+            reserves: self.reserves,
+            market: self.market,
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for RaydiumAmm {
+    fn label(&self) -> String {
+        String::from("Raydium")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.state.coin_mint, self.state.pc_mint]
+    }
+
+    // The market is only read for the accounts of the swap instruction
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![
+            self.state.coin_vault,
+            self.state.pc_vault,
+            self.state.open_orders,
+            self.state.market,
+        ]
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let get_account = |key: &Pubkey| {
+            accounts_map
+                .get(key)
+                .with_context(|| format!("Missing account {}", key))
+        };
+        let mut coin_amount = TokenAccount::unpack(get_account(&self.state.coin_vault)?)?.amount;
+        let mut pc_amount = TokenAccount::unpack(get_account(&self.state.pc_vault)?)?.amount;
+        if self.uses_order_book() {
+            let open_orders = OpenOrders::load(get_account(&self.state.open_orders)?)?;
+            coin_amount = coin_amount
+                .checked_add(open_orders.native_coin_total)
+                .context("Coin reserve overflow")?;
+            pc_amount = pc_amount
+                .checked_add(open_orders.native_pc_total)
+                .context("Pc reserve overflow")?;
+        }
+        self.reserves = [
+            coin_amount
+                .checked_sub(self.state.need_take_pnl_coin)
+                .context("Coin pnl exceeds the reserve")?,
+            pc_amount
+                .checked_sub(self.state.need_take_pnl_pc)
+                .context("Pc pnl exceeds the reserve")?,
+        ];
+        self.market = Some(SerumMarket::load(get_account(&self.state.market)?)?);
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        if self.state.status != STATUS_INITIALIZED && self.state.status != STATUS_SWAP_ONLY {
+            return Err(anyhow!("Raydium amm {} does not allow swaps", self.key));
+        }
+        if self.state.swap_fee_denominator == 0 {
+            return Err(anyhow!("Raydium amm {} has no swap fee denominator", self.key));
+        }
+        let (source_reserve, destination_reserve) = if quote_params.input_mint == self.state.coin_mint {
+            (self.reserves[0], self.reserves[1])
+        } else {
+            (self.reserves[1], self.reserves[0])
+        };
+
+        // The swap fee is rounded up and taken from the input
+        let in_amount = quote_params.in_amount as u128;
+        let fee_denominator = self.state.swap_fee_denominator as u128;
+        let fee_amount = checked_ceil_div(in_amount * self.state.swap_fee_numerator as u128, fee_denominator)
+            .context("quote failed")?;
+        let in_amount_after_fee = in_amount.checked_sub(fee_amount).context("quote failed")?;
+        let out_amount = (destination_reserve as u128 * in_amount_after_fee)
+            .checked_div(source_reserve as u128 + in_amount_after_fee)
+            .unwrap_or_default();
+
+        Ok(Quote {
+            in_amount: quote_params.in_amount,
+            out_amount: out_amount.try_into()?,
+            fee_amount: fee_amount.try_into()?,
+            fee_mint: quote_params.input_mint,
+            fee_pct: Decimal::from(self.state.swap_fee_numerator) / Decimal::from(self.state.swap_fee_denominator),
+            ..Quote::default()
+        })
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+        let market = self
+            .market
+            .as_ref()
+            .with_context(|| format!("Market {} not loaded, update the amm first", self.state.market))?;
+
+        let account_metas = RaydiumSwap {
+            swap_program: self.program_id,
+            token_program: spl_token::ID,
+            amm_id: self.key,
+            amm_authority: self.get_authority()?,
+            amm_open_orders: self.state.open_orders,
+            pool_coin_token_account: self.state.coin_vault,
+            pool_pc_token_account: self.state.pc_vault,
+            serum_program_id: self.state.market_program,
+            serum_market: self.state.market,
+            serum_bids: market.bids,
+            serum_asks: market.asks,
+            serum_event_queue: market.event_queue,
+            serum_coin_vault_account: market.coin_vault,
+            serum_pc_vault_account: market.pc_vault,
+            serum_vault_signer: market.vault_signer(&self.state.market_program)?,
+            user_source_token_account: *user_source_token_account,
+            user_destination_token_account: *user_destination_token_account,
+            user_source_owner: *user_transfer_authority,
+        }
+        .to_account_metas(None);
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap: Swap::Raydium },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_raydium_amm() {
+    use crate::amms::test_harness::{new_keyed_account, token_account_data};
+    use crate::constants::{OPENBOOK_PROGRAM_ID, USDC_MINT};
+    use spl_token::native_mint;
+
+    let key = Pubkey::new_unique();
+    let nonce = (0..=255u8)
+        .rev()
+        .find(|nonce| Pubkey::create_program_address(&[AMM_AUTHORITY_SEED, &[*nonce]], &RAYDIUM_AMM_V4_PROGRAM_ID).is_ok())
+        .unwrap();
+    let state = RaydiumAmmInfo {
+        status: STATUS_INITIALIZED,
+        nonce: nonce as u64,
+        coin_decimals: 9,
+        pc_decimals: 6,
+        swap_fee_numerator: 25,
+        swap_fee_denominator: 10_000,
+        need_take_pnl_coin: 1_000_000_000,
+        need_take_pnl_pc: 20_000_000,
+        coin_vault: Pubkey::new_unique(),
+        pc_vault: Pubkey::new_unique(),
+        coin_mint: native_mint::id(),
+        pc_mint: USDC_MINT,
+        lp_mint: Pubkey::new_unique(),
+        open_orders: Pubkey::new_unique(),
+        market: Pubkey::new_unique(),
+        market_program: OPENBOOK_PROGRAM_ID,
+        target_orders: Pubkey::new_unique(),
+    };
+    let data = state.pack();
+    assert_eq!(RaydiumAmmInfo::load(&data).unwrap(), state);
+    let mut amm =
+        RaydiumAmm::from_keyed_account(&new_keyed_account(key, data, RAYDIUM_AMM_V4_PROGRAM_ID, None)).unwrap();
+
+    let vault_signer_nonce = (0..u64::MAX)
+        .find(|nonce| Pubkey::create_program_address(&[&state.market.to_bytes(), &nonce.to_le_bytes()], &OPENBOOK_PROGRAM_ID).is_ok())
+        .unwrap();
+    let market = SerumMarket {
+        own_address: state.market,
+        vault_signer_nonce,
+        coin_mint: native_mint::id(),
+        pc_mint: USDC_MINT,
+        coin_vault: Pubkey::new_unique(),
+        pc_vault: Pubkey::new_unique(),
+        request_queue: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        coin_lot_size: 1_000_000,
+        pc_lot_size: 1,
+        fee_rate_bps: 0,
+    };
+    let open_orders = OpenOrders {
+        market: state.market,
+        owner: key,
+        native_coin_free: 500_000_000_000,
+        native_coin_total: 1_000_000_000_000,
+        native_pc_free: 0,
+        native_pc_total: 10_000_000_000,
+    };
+    let accounts_map: HashMap<Pubkey, Vec<u8>> = vec![
+        (state.coin_vault, token_account_data(native_mint::id(), 9_001_000_000_000)),
+        (state.pc_vault, token_account_data(USDC_MINT, 190_020_000_000)),
+        (state.open_orders, open_orders.pack()),
+        (state.market, market.pack()),
+    ]
+    .into_iter()
+    .collect();
+    amm.update(&accounts_map).unwrap();
+    // 10_000 SOL and 200_000 USDC once the open orders are added and the pnl taken out
+    assert_eq!(amm.reserves, [10_000_000_000_000, 200_000_000_000]);
+
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: native_mint::id(),
+            output_mint: USDC_MINT,
+        })
+        .unwrap();
+    assert_eq!(quote.fee_amount, 2_500_000);
+    assert_eq!(quote.out_amount, 19_948_010);
+
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: native_mint::id(),
+            destination_mint: USDC_MINT,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap { swap: Swap::Raydium }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 18);
+    assert_eq!(account_metas[7].pubkey, OPENBOOK_PROGRAM_ID);
+    assert_eq!(account_metas[9].pubkey, market.bids);
+    assert_eq!(account_metas[12].pubkey, market.coin_vault);
+}
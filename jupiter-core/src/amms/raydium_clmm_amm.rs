@@ -0,0 +1,586 @@
+use anchor_lang::{solana_program::hash::hash, ToAccountMetas};
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{instruction::AccountMeta, pubkey, pubkey::Pubkey};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::clmm::{self, TickRange, FEE_RATE_DENOMINATOR};
+use super::user_setup::get_user_token_account_setup;
+use jupiter::{
+    accounts::RaydiumClmmSwap,
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const RAYDIUM_CLMM_PROGRAM_ID: Pubkey = pubkey!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+
+pub const TICK_ARRAY_SIZE: i32 = 60;
+/// Tick arrays passed to a swap, the first one in the instruction and the others as remaining accounts
+pub const SWAP_TICK_ARRAYS: usize = 3;
+/// Bit of the pool status disabling swaps
+const STATUS_SWAP_DISABLED: u8 = 1 << 4;
+/// Tick arrays tracked by the pool bitmap, from -512 to 511 times the ticks in an array
+const TICK_ARRAY_BITMAP_LEN: i32 = 1024;
+
+fn anchor_discriminator(account_name: &str) -> [u8; 8] {
+    hash(format!("account:{}", account_name).as_bytes()).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+}
+
+/// Fee tier shared by the pools created with it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AmmConfig {
+    /// In hundredths of a bip
+    pub trade_fee_rate: u32,
+    pub tick_spacing: u16,
+}
+
+impl AmmConfig {
+    pub const LEN: usize = 117;
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() != Self::LEN || account_data[..8] != anchor_discriminator("AmmConfig") {
+            return Err(anyhow!("Invalid Raydium AmmConfig account"));
+        }
+        Ok(Self {
+            trade_fee_rate: u32::from_le_bytes(account_data[47..51].try_into()?),
+            tick_spacing: u16::from_le_bytes(account_data[51..53].try_into()?),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[..8].copy_from_slice(&anchor_discriminator("AmmConfig"));
+        data[47..51].copy_from_slice(&self.trade_fee_rate.to_le_bytes());
+        data[51..53].copy_from_slice(&self.tick_spacing.to_le_bytes());
+        data
+    }
+}
+
+/// The parts of the Raydium CLMM pool state needed to quote and build swaps
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolState {
+    pub amm_config: Pubkey,
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    pub observation_key: Pubkey,
+    pub tick_spacing: u16,
+    pub liquidity: u128,
+    pub sqrt_price_x64: u128,
+    pub tick_current: i32,
+    pub status: u8,
+    /// Initialized tick arrays, bit i for the array starting at (i - 512) times the ticks in an array
+    pub tick_array_bitmap: [u64; 16],
+}
+
+impl PoolState {
+    pub const LEN: usize = 1544;
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() != Self::LEN || account_data[..8] != anchor_discriminator("PoolState") {
+            return Err(anyhow!("Invalid Raydium PoolState account"));
+        }
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(account_data[offset..offset + 32].try_into().unwrap());
+        let u128_at = |offset: usize| u128::from_le_bytes(account_data[offset..offset + 16].try_into().unwrap());
+        let mut tick_array_bitmap = [0u64; 16];
+        for (i, word) in tick_array_bitmap.iter_mut().enumerate() {
+            let offset = 904 + i * 8;
+            *word = u64::from_le_bytes(account_data[offset..offset + 8].try_into()?);
+        }
+        Ok(Self {
+            amm_config: pubkey_at(9),
+            token_mint_0: pubkey_at(73),
+            token_mint_1: pubkey_at(105),
+            token_vault_0: pubkey_at(137),
+            token_vault_1: pubkey_at(169),
+            observation_key: pubkey_at(201),
+            tick_spacing: u16::from_le_bytes(account_data[235..237].try_into()?),
+            liquidity: u128_at(237),
+            sqrt_price_x64: u128_at(253),
+            tick_current: i32::from_le_bytes(account_data[269..273].try_into()?),
+            status: account_data[389],
+            tick_array_bitmap,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[..8].copy_from_slice(&anchor_discriminator("PoolState"));
+        for (offset, pubkey) in [
+            (9, self.amm_config),
+            (73, self.token_mint_0),
+            (105, self.token_mint_1),
+            (137, self.token_vault_0),
+            (169, self.token_vault_1),
+            (201, self.observation_key),
+        ] {
+            data[offset..offset + 32].copy_from_slice(pubkey.as_ref());
+        }
+        data[235..237].copy_from_slice(&self.tick_spacing.to_le_bytes());
+        data[237..253].copy_from_slice(&self.liquidity.to_le_bytes());
+        data[253..269].copy_from_slice(&self.sqrt_price_x64.to_le_bytes());
+        data[269..273].copy_from_slice(&self.tick_current.to_le_bytes());
+        data[389] = self.status;
+        for (i, word) in self.tick_array_bitmap.iter().enumerate() {
+            let offset = 904 + i * 8;
+            data[offset..offset + 8].copy_from_slice(&word.to_le_bytes());
+        }
+        data
+    }
+
+    fn ticks_in_array(&self) -> i32 {
+        self.tick_spacing as i32 * TICK_ARRAY_SIZE
+    }
+
+    fn current_tick_array_start_index(&self) -> i32 {
+        let ticks_in_array = self.ticks_in_array();
+        self.tick_current.div_euclid(ticks_in_array) * ticks_in_array
+    }
+
+    fn is_tick_array_initialized(&self, tick_array_index: i32) -> bool {
+        let bit = tick_array_index + TICK_ARRAY_BITMAP_LEN / 2;
+        (0..TICK_ARRAY_BITMAP_LEN).contains(&bit)
+            && self.tick_array_bitmap[bit as usize / 64] & (1 << (bit % 64)) != 0
+    }
+
+    /// Start tick indexes of the initialized tick arrays a swap in the direction goes through, from
+    /// the one holding the current tick
+    pub fn tick_array_start_indexes(&self, zero_for_one: bool, count: usize) -> Vec<i32> {
+        let ticks_in_array = self.ticks_in_array();
+        let current_tick_array_index = self.current_tick_array_start_index() / ticks_in_array;
+        let tick_array_indexes: Box<dyn Iterator<Item = i32>> = if zero_for_one {
+            Box::new((-TICK_ARRAY_BITMAP_LEN / 2..=current_tick_array_index).rev())
+        } else {
+            Box::new(current_tick_array_index..TICK_ARRAY_BITMAP_LEN / 2)
+        };
+        tick_array_indexes
+            .filter(|tick_array_index| self.is_tick_array_initialized(*tick_array_index))
+            .take(count)
+            .map(|tick_array_index| tick_array_index * ticks_in_array)
+            .collect()
+    }
+}
+
+/// Initialized ticks of a tick array with their liquidity net
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickArrayState {
+    pub pool_id: Pubkey,
+    pub start_tick_index: i32,
+    pub ticks: Vec<(i32, i128)>,
+}
+
+impl TickArrayState {
+    pub const LEN: usize = 10240;
+    const TICK_LEN: usize = 168;
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() != Self::LEN || account_data[..8] != anchor_discriminator("TickArrayState") {
+            return Err(anyhow!("Invalid Raydium TickArrayState account"));
+        }
+        // Ticks hold their index, they are initialized with some liquidity gross
+        let ticks = account_data[44..44 + TICK_ARRAY_SIZE as usize * Self::TICK_LEN]
+            .chunks(Self::TICK_LEN)
+            .filter(|tick| tick[20..36].iter().any(|byte| *byte != 0))
+            .map(|tick| {
+                (
+                    i32::from_le_bytes(tick[..4].try_into().unwrap()),
+                    i128::from_le_bytes(tick[4..20].try_into().unwrap()),
+                )
+            })
+            .collect();
+        Ok(Self {
+            pool_id: Pubkey::new_from_array(account_data[8..40].try_into()?),
+            start_tick_index: i32::from_le_bytes(account_data[40..44].try_into()?),
+            ticks,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self, tick_spacing: u16) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[..8].copy_from_slice(&anchor_discriminator("TickArrayState"));
+        data[8..40].copy_from_slice(self.pool_id.as_ref());
+        data[40..44].copy_from_slice(&self.start_tick_index.to_le_bytes());
+        for (tick_index, liquidity_net) in &self.ticks {
+            let offset = 44 + ((tick_index - self.start_tick_index) / tick_spacing as i32) as usize * Self::TICK_LEN;
+            data[offset..offset + 4].copy_from_slice(&tick_index.to_le_bytes());
+            data[offset + 4..offset + 20].copy_from_slice(&liquidity_net.to_le_bytes());
+            data[offset + 20..offset + 36].copy_from_slice(&liquidity_net.unsigned_abs().to_le_bytes());
+        }
+        data
+    }
+}
+
+pub fn get_tick_array_address(pool_id: &Pubkey, start_tick_index: i32, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"tick_array", pool_id.as_ref(), &start_tick_index.to_be_bytes()],
+        program_id,
+    )
+    .0
+}
+
+/// Raydium concentrated liquidity pool. Swaps skip the tick arrays without initialized ticks using
+/// the pool bitmap, the ones they go through change with the price so the accounts are dynamic.
+pub struct RaydiumClmmAmm {
+    key: Pubkey,
+    state: PoolState,
+    amm_config: AmmConfig,
+    /// Initialized tick arrays in the zero for one and one for zero directions, with their start tick index
+    tick_arrays: [Vec<(i32, Pubkey)>; 2],
+    /// Ticks of the loaded tick arrays in each direction
+    tick_ranges: [TickRange; 2],
+    program_id: Pubkey,
+}
+
+impl RaydiumClmmAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        let mut amm = Self {
+            key: keyed_account.key,
+            state: PoolState::load(&keyed_account.account.data)?,
+            amm_config: AmmConfig::default(),
+            tick_arrays: Default::default(),
+            tick_ranges: Default::default(),
+            program_id: keyed_account.account.owner,
+        };
+        amm.tick_arrays = amm.get_tick_arrays();
+        Ok(amm)
+    }
+
+    fn get_tick_arrays(&self) -> [Vec<(i32, Pubkey)>; 2] {
+        let tick_arrays = |zero_for_one: bool| {
+            self.state
+                .tick_array_start_indexes(zero_for_one, SWAP_TICK_ARRAYS)
+                .into_iter()
+                .map(|start_tick_index| {
+                    (
+                        start_tick_index,
+                        get_tick_array_address(&self.key, start_tick_index, &self.program_id),
+                    )
+                })
+                .collect()
+        };
+        [tick_arrays(true), tick_arrays(false)]
+    }
+
+    fn direction(zero_for_one: bool) -> usize {
+        if zero_for_one {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn is_zero_for_one(&self, input_mint: &Pubkey) -> bool {
+        *input_mint == self.state.token_mint_0
+    }
+
+    /// Ticks of the tick arrays in the direction up to the first one missing from the accounts.
+    /// Tick arrays in between are not initialized, so the range goes from the current one on.
+    fn load_tick_range(&self, accounts_map: &HashMap<Pubkey, Vec<u8>>, zero_for_one: bool) -> Result<TickRange> {
+        let ticks_in_array = self.state.ticks_in_array();
+        let current_start_tick_index = self.state.current_tick_array_start_index();
+        let first_edge = if zero_for_one {
+            current_start_tick_index + ticks_in_array
+        } else {
+            current_start_tick_index
+        };
+        let mut tick_range = TickRange {
+            start_tick_index: first_edge,
+            end_tick_index: first_edge,
+            ..TickRange::default()
+        };
+        for (start_tick_index, tick_array_key) in &self.tick_arrays[Self::direction(zero_for_one)] {
+            let tick_array = match accounts_map.get(tick_array_key) {
+                Some(account_data) => TickArrayState::load(account_data)?,
+                None => break,
+            };
+            if tick_array.pool_id != self.key || tick_array.start_tick_index != *start_tick_index {
+                return Err(anyhow!("Tick array {} does not belong to pool {}", tick_array_key, self.key));
+            }
+            tick_range.ticks.extend(tick_array.ticks);
+            if zero_for_one {
+                tick_range.start_tick_index = *start_tick_index;
+            } else {
+                tick_range.end_tick_index = start_tick_index + ticks_in_array;
+            }
+        }
+        Ok(tick_range)
+    }
+
+    fn clone(&self) -> RaydiumClmmAmm {
+        RaydiumClmmAmm {
+            key: self.key,
+            state: self.state,
+            amm_config: self.amm_config,
+            tick_arrays: self.tick_arrays.clone(),
+            tick_ranges: self.tick_ranges.clone(),
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for RaydiumClmmAmm {
+    fn label(&self) -> String {
+        String::from("Raydium CLMM")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.state.token_mint_0, self.state.token_mint_1]
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.key, self.state.amm_config];
+        for (_, tick_array_key) in self.tick_arrays.iter().flatten() {
+            if !accounts.contains(tick_array_key) {
+                accounts.push(*tick_array_key);
+            }
+        }
+        accounts
+    }
+
+    /// Tick arrays missing from the accounts, after the price moved to other ones, limit the quotes
+    /// until the next update
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let get_account = |key: &Pubkey| {
+            accounts_map
+                .get(key)
+                .with_context(|| format!("Missing account {}", key))
+        };
+        self.state = PoolState::load(get_account(&self.key)?)?;
+        self.amm_config = AmmConfig::load(get_account(&self.state.amm_config)?)?;
+        self.tick_arrays = self.get_tick_arrays();
+        self.tick_ranges = [
+            self.load_tick_range(accounts_map, true)?,
+            self.load_tick_range(accounts_map, false)?,
+        ];
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        if self.state.status & STATUS_SWAP_DISABLED != 0 {
+            return Err(anyhow!("Raydium CLMM pool {} has swaps disabled", self.key));
+        }
+        let zero_for_one = self.is_zero_for_one(&quote_params.input_mint);
+        let result = clmm::swap(
+            self.state.liquidity,
+            self.state.sqrt_price_x64,
+            self.state.tick_current,
+            self.amm_config.trade_fee_rate,
+            &self.tick_ranges[Self::direction(zero_for_one)],
+            quote_params.in_amount,
+            true,
+            zero_for_one,
+        )
+        .with_context(|| format!("Swap overflows in Raydium CLMM pool {}", self.key))?;
+
+        Ok(Quote {
+            not_enough_liquidity: !result.is_complete,
+            in_amount: result.amount_in,
+            out_amount: result.amount_out,
+            fee_amount: result.fee_amount,
+            fee_mint: quote_params.input_mint,
+            fee_pct: Decimal::from(self.amm_config.trade_fee_rate) / Decimal::from(FEE_RATE_DENOMINATOR),
+            ..Quote::default()
+        })
+    }
+
+    /// The tick arrays after the first one go in the remaining accounts, always as many so the
+    /// route stays decodable. Missing ones repeat the last, the swap never gets there.
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let zero_for_one = self.is_zero_for_one(source_mint);
+        let (input_vault, output_vault) = if zero_for_one {
+            (self.state.token_vault_0, self.state.token_vault_1)
+        } else {
+            (self.state.token_vault_1, self.state.token_vault_0)
+        };
+        let tick_array_keys: Vec<Pubkey> = self.tick_arrays[Self::direction(zero_for_one)]
+            .iter()
+            .map(|(_, tick_array_key)| *tick_array_key)
+            .collect();
+        let last_tick_array = *tick_array_keys
+            .last()
+            .with_context(|| format!("No initialized tick arrays for Raydium CLMM pool {}", self.key))?;
+        let tick_array = |i: usize| tick_array_keys.get(i).copied().unwrap_or(last_tick_array);
+
+        let mut account_metas = RaydiumClmmSwap {
+            swap_program: self.program_id,
+            payer: *user_transfer_authority,
+            amm_config: self.state.amm_config,
+            pool_state: self.key,
+            input_token_account: *user_source_token_account,
+            output_token_account: *user_destination_token_account,
+            input_vault,
+            output_vault,
+            observation_state: self.state.observation_key,
+            token_program: spl_token::ID,
+            tick_array: tick_array(0),
+        }
+        .to_account_metas(None);
+        account_metas.extend((1..SWAP_TICK_ARRAYS).map(|i| AccountMeta::new(tick_array(i), false)));
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap {
+                swap: Swap::RaydiumClmm,
+            },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn has_dynamic_accounts(&self) -> bool {
+        true
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_raydium_clmm_amm() {
+    use crate::amms::test_harness::new_keyed_account;
+    use crate::constants::USDC_MINT;
+
+    let key = Pubkey::new_unique();
+    let usdt_mint = Pubkey::new_unique();
+    // Liquidity between ticks -1440 and 1440, with more of it between -64 and 64 and still more between -8 and 8
+    let mut tick_array_bitmap = [0u64; 16];
+    for tick_array_index in [-3, -1, 0, 3] {
+        let bit = tick_array_index + 512;
+        tick_array_bitmap[bit as usize / 64] |= 1 << (bit % 64);
+    }
+    let state = PoolState {
+        amm_config: Pubkey::new_unique(),
+        token_mint_0: USDC_MINT,
+        token_mint_1: usdt_mint,
+        token_vault_0: Pubkey::new_unique(),
+        token_vault_1: Pubkey::new_unique(),
+        observation_key: Pubkey::new_unique(),
+        tick_spacing: 8,
+        liquidity: 10_500_000_000_000,
+        sqrt_price_x64: 1 << 64,
+        tick_current: 0,
+        status: 0,
+        tick_array_bitmap,
+    };
+    let data = state.pack();
+    assert_eq!(PoolState::load(&data).unwrap(), state);
+    // Tick array -960 holds no initialized ticks and is skipped
+    assert_eq!(state.tick_array_start_indexes(true, 3), vec![0, -480, -1440]);
+    assert_eq!(state.tick_array_start_indexes(false, 3), vec![0, 1440]);
+
+    let mut amm = RaydiumClmmAmm::from_keyed_account(&new_keyed_account(
+        key,
+        data.clone(),
+        RAYDIUM_CLMM_PROGRAM_ID,
+        None,
+    ))
+    .unwrap();
+    assert!(amm.has_dynamic_accounts());
+    assert_eq!(amm.get_accounts_to_update().len(), 6);
+
+    let amm_config = AmmConfig {
+        trade_fee_rate: 100,
+        tick_spacing: 8,
+    };
+    assert_eq!(AmmConfig::load(&amm_config.pack()).unwrap(), amm_config);
+    let tick_array = |start_tick_index: i32, ticks: Vec<(i32, i128)>| {
+        let tick_array = TickArrayState {
+            pool_id: key,
+            start_tick_index,
+            ticks,
+        };
+        let data = tick_array.pack(state.tick_spacing);
+        assert_eq!(TickArrayState::load(&data).unwrap(), tick_array);
+        (get_tick_array_address(&key, start_tick_index, &RAYDIUM_CLMM_PROGRAM_ID), data)
+    };
+    let accounts_map: HashMap<Pubkey, Vec<u8>> = vec![
+        (key, data),
+        (state.amm_config, amm_config.pack()),
+        tick_array(-1440, vec![(-1440, 500_000_000_000)]),
+        tick_array(-480, vec![(-64, 1_000_000_000_000), (-8, 9_000_000_000_000)]),
+        tick_array(0, vec![(8, -9_000_000_000_000), (64, -1_000_000_000_000)]),
+        tick_array(1440, vec![(1440, -500_000_000_000)]),
+    ]
+    .into_iter()
+    .collect();
+    amm.update(&accounts_map).unwrap();
+
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000,
+            input_mint: USDC_MINT,
+            output_mint: usdt_mint,
+        })
+        .unwrap();
+    assert_eq!(quote.fee_amount, 100);
+    assert_eq!(quote.out_amount, 999_899);
+    assert!(!quote.not_enough_liquidity);
+
+    // The swap crosses every tick down to -1440 where the liquidity runs out
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 100_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: usdt_mint,
+        })
+        .unwrap();
+    assert!(quote.not_enough_liquidity);
+    let sqrt_price = clmm::sqrt_price_from_tick_index;
+    let expected_out = clmm::get_amount_delta_b(sqrt_price(-8), 1 << 64, 10_500_000_000_000, false).unwrap()
+        + clmm::get_amount_delta_b(sqrt_price(-64), sqrt_price(-8), 1_500_000_000_000, false).unwrap()
+        + clmm::get_amount_delta_b(sqrt_price(-1440), sqrt_price(-64), 500_000_000_000, false).unwrap();
+    assert!(quote.out_amount <= expected_out && quote.out_amount + 3 >= expected_out);
+
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: usdt_mint,
+            destination_mint: USDC_MINT,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::RaydiumClmm
+        }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 13);
+    assert_eq!(account_metas[6].pubkey, state.token_vault_1);
+    assert_eq!(account_metas[10].pubkey, get_tick_array_address(&key, 0, &RAYDIUM_CLMM_PROGRAM_ID));
+    let last_tick_array = get_tick_array_address(&key, 1440, &RAYDIUM_CLMM_PROGRAM_ID);
+    assert_eq!(account_metas[11].pubkey, last_tick_array);
+    assert_eq!(account_metas[12].pubkey, last_tick_array);
+}
@@ -0,0 +1,284 @@
+use anchor_lang::{solana_program::hash::hash, ToAccountMetas};
+use anyhow::{anyhow, Context, Result};
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{program_pack::Pack, pubkey, pubkey::Pubkey};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::get_user_token_account_setup;
+use jupiter::{
+    accounts::SaberAddDecimals,
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const SABER_ADD_DECIMALS_PROGRAM_ID: Pubkey = pubkey!("DecZY86MU5Gj7kppfUCEmd4LbXXuyZH1yHaP2NTqdiZB");
+
+/// Anchor account of the add decimals program wrapping an underlying mint into one with more decimals
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WrappedToken {
+    pub decimals: u8,
+    /// 10^n, the amount of wrapped tokens one underlying token is worth
+    pub multiplier: u64,
+    pub wrapper_underlying_mint: Pubkey,
+    pub wrapper_underlying_tokens: Pubkey,
+    pub wrapper_mint: Pubkey,
+    pub nonce: u8,
+}
+
+impl WrappedToken {
+    pub const LEN: usize = 8 + 1 + 8 + 3 * 32 + 1;
+
+    pub fn discriminator() -> [u8; 8] {
+        hash(b"account:WrappedToken").to_bytes()[..8].try_into().unwrap()
+    }
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() < Self::LEN || account_data[..8] != Self::discriminator() {
+            return Err(anyhow!("Invalid WrappedToken account"));
+        }
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(account_data[offset..offset + 32].try_into().unwrap());
+        Ok(Self {
+            decimals: account_data[8],
+            multiplier: u64::from_le_bytes(account_data[9..17].try_into()?),
+            wrapper_underlying_mint: pubkey_at(17),
+            wrapper_underlying_tokens: pubkey_at(49),
+            wrapper_mint: pubkey_at(81),
+            nonce: account_data[113],
+        })
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = Self::discriminator().to_vec();
+        data.push(self.decimals);
+        data.extend_from_slice(&self.multiplier.to_le_bytes());
+        data.extend_from_slice(self.wrapper_underlying_mint.as_ref());
+        data.extend_from_slice(self.wrapper_underlying_tokens.as_ref());
+        data.extend_from_slice(self.wrapper_mint.as_ref());
+        data.push(self.nonce);
+        data
+    }
+}
+
+/// Deposits underlying tokens for wrapped ones and withdraws them back, at the fixed multiplier
+pub struct SaberAddDecimalsAmm {
+    key: Pubkey,
+    state: WrappedToken,
+    underlying_reserve: u64,
+    program_id: Pubkey,
+}
+
+impl SaberAddDecimalsAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        Ok(Self {
+            key: keyed_account.key,
+            state: WrappedToken::load(&keyed_account.account.data)?,
+            underlying_reserve: 0,
+            program_id: keyed_account.account.owner,
+        })
+    }
+
+    fn is_deposit(&self, input_mint: &Pubkey) -> bool {
+        *input_mint == self.state.wrapper_underlying_mint
+    }
+
+    fn clone(&self) -> SaberAddDecimalsAmm {
+        SaberAddDecimalsAmm {
+            key: self.key,
+            state: self.state,
+            underlying_reserve: self.underlying_reserve,
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for SaberAddDecimalsAmm {
+    fn label(&self) -> String {
+        String::from("Saber (Decimals)")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.state.wrapper_underlying_mint, self.state.wrapper_mint]
+    }
+
+    // Withdrawals are limited by the underlying tokens held by the wrapper
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![self.state.wrapper_underlying_tokens]
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let wrapper_underlying_tokens = accounts_map
+            .get(&self.state.wrapper_underlying_tokens)
+            .with_context(|| format!("Missing token account {}", self.state.wrapper_underlying_tokens))?;
+        self.underlying_reserve = TokenAccount::unpack(wrapper_underlying_tokens)?.amount;
+
+        Ok(())
+    }
+
+    /// Withdrawals burn whole multiples of the multiplier, the dust of the input stays with the user
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let multiplier = self.state.multiplier;
+        if multiplier == 0 {
+            return Err(anyhow!("Wrapper {} has no multiplier", self.key));
+        }
+
+        let (in_amount, out_amount) = if self.is_deposit(&quote_params.input_mint) {
+            let out_amount = quote_params
+                .in_amount
+                .checked_mul(multiplier)
+                .context("Deposit overflows the wrapped mint")?;
+            (quote_params.in_amount, out_amount)
+        } else {
+            let out_amount = quote_params.in_amount / multiplier;
+            (out_amount * multiplier, out_amount)
+        };
+
+        Ok(Quote {
+            not_enough_liquidity: !self.is_deposit(&quote_params.input_mint) && out_amount > self.underlying_reserve,
+            in_amount,
+            out_amount,
+            ..Quote::default()
+        })
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let (swap, user_underlying_tokens, user_wrapped_tokens) = if self.is_deposit(source_mint) {
+            (
+                Swap::SaberAddDecimalsDeposit,
+                *user_source_token_account,
+                *user_destination_token_account,
+            )
+        } else {
+            (
+                Swap::SaberAddDecimalsWithdraw,
+                *user_destination_token_account,
+                *user_source_token_account,
+            )
+        };
+
+        let account_metas = SaberAddDecimals {
+            add_decimals_program: self.program_id,
+            wrapper: self.key,
+            wrapper_mint: self.state.wrapper_mint,
+            wrapper_underlying_tokens: self.state.wrapper_underlying_tokens,
+            owner: *user_transfer_authority,
+            user_underlying_tokens,
+            user_wrapped_tokens,
+            token_program: spl_token::ID,
+        }
+        .to_account_metas(None);
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_saber_add_decimals() {
+    use crate::amms::test_harness::{new_keyed_account, token_account_data};
+    use crate::constants::USDC_MINT;
+
+    let key = Pubkey::new_unique();
+    let state = WrappedToken {
+        decimals: 9,
+        multiplier: 1_000,
+        wrapper_underlying_mint: USDC_MINT,
+        wrapper_underlying_tokens: Pubkey::new_unique(),
+        wrapper_mint: Pubkey::new_unique(),
+        nonce: 255,
+    };
+    let data = state.pack();
+    assert_eq!(data.len(), WrappedToken::LEN);
+    assert_eq!(WrappedToken::load(&data).unwrap(), state);
+
+    let mut amm = SaberAddDecimalsAmm::from_keyed_account(&new_keyed_account(
+        key,
+        data,
+        SABER_ADD_DECIMALS_PROGRAM_ID,
+        None,
+    ))
+    .unwrap();
+
+    let data = token_account_data(USDC_MINT, 5_000_000);
+    amm.update(&vec![(state.wrapper_underlying_tokens, data)].into_iter().collect())
+        .unwrap();
+
+    let deposit_quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000,
+            input_mint: USDC_MINT,
+            output_mint: state.wrapper_mint,
+        })
+        .unwrap();
+    assert_eq!(deposit_quote.out_amount, 1_000_000_000);
+    assert!(!deposit_quote.not_enough_liquidity);
+
+    let withdraw_quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_999,
+            input_mint: state.wrapper_mint,
+            output_mint: USDC_MINT,
+        })
+        .unwrap();
+    assert_eq!(withdraw_quote.in_amount, 1_000_000_000);
+    assert_eq!(withdraw_quote.out_amount, 1_000_000);
+    assert!(
+        amm.quote(&QuoteParams {
+            in_amount: 6_000_000_000,
+            input_mint: state.wrapper_mint,
+            output_mint: USDC_MINT,
+        })
+        .unwrap()
+        .not_enough_liquidity
+    );
+
+    let (user_underlying_tokens, user_wrapped_tokens) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: state.wrapper_mint,
+            destination_mint: USDC_MINT,
+            user_source_token_account: user_wrapped_tokens,
+            user_destination_token_account: user_underlying_tokens,
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::SaberAddDecimalsWithdraw
+        }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 8);
+    assert_eq!(account_metas[5].pubkey, user_underlying_tokens);
+    assert_eq!(account_metas[6].pubkey, user_wrapped_tokens);
+}
@@ -0,0 +1,581 @@
+use anchor_lang::ToAccountMetas;
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use spl_math::uint::U256;
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey,
+    pubkey::Pubkey,
+    sysvar,
+};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::get_user_token_account_setup;
+use jupiter::{
+    accounts::SaberSwap,
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const SABER_PROGRAM_ID: Pubkey = pubkey!("SSwpkEEcbUqx4vtoEByFjSkhKdCT862DNVb52nZg1UZ");
+
+const N_COINS: u8 = 2;
+/// Newton iterations before giving up on convergence, as in the program
+const MAX_ITERATIONS: usize = 256;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SaberSwapTokenInfo {
+    pub reserves: Pubkey,
+    pub mint: Pubkey,
+    pub admin_fees: Pubkey,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SaberFees {
+    pub admin_trade_fee_numerator: u64,
+    pub admin_trade_fee_denominator: u64,
+    pub admin_withdraw_fee_numerator: u64,
+    pub admin_withdraw_fee_denominator: u64,
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub withdraw_fee_numerator: u64,
+    pub withdraw_fee_denominator: u64,
+}
+
+impl SaberFees {
+    fn fee(amount: u64, numerator: u64, denominator: u64) -> Option<u64> {
+        if numerator == 0 || amount == 0 {
+            return Some(0);
+        }
+        (U256::from(amount) * U256::from(numerator))
+            .checked_div(denominator.into())?
+            .try_into()
+            .ok()
+    }
+
+    pub fn trade_fee(&self, amount: u64) -> Option<u64> {
+        Self::fee(amount, self.trade_fee_numerator, self.trade_fee_denominator)
+    }
+
+    /// Share of the trade fee going to the admin fee account instead of the pool
+    pub fn admin_trade_fee(&self, fee_amount: u64) -> Option<u64> {
+        Self::fee(fee_amount, self.admin_trade_fee_numerator, self.admin_trade_fee_denominator)
+    }
+}
+
+/// Saber stable swap account, without the version byte spl token swap has
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SaberSwapInfo {
+    pub is_initialized: bool,
+    pub is_paused: bool,
+    pub nonce: u8,
+    pub initial_amp_factor: u64,
+    pub target_amp_factor: u64,
+    pub start_ramp_ts: i64,
+    pub stop_ramp_ts: i64,
+    pub future_admin_deadline: i64,
+    pub future_admin_key: Pubkey,
+    pub admin_key: Pubkey,
+    pub token_a: SaberSwapTokenInfo,
+    pub token_b: SaberSwapTokenInfo,
+    pub pool_mint: Pubkey,
+    pub fees: SaberFees,
+}
+
+impl Sealed for SaberSwapInfo {}
+
+impl IsInitialized for SaberSwapInfo {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for SaberSwapInfo {
+    const LEN: usize = 395;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let bool_at = |offset: usize| match input[offset] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProgramError::InvalidAccountData),
+        };
+        let u64_at = |offset: usize| u64::from_le_bytes(input[offset..offset + 8].try_into().unwrap());
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(input[offset..offset + 32].try_into().unwrap());
+        Ok(Self {
+            is_initialized: bool_at(0)?,
+            is_paused: bool_at(1)?,
+            nonce: input[2],
+            initial_amp_factor: u64_at(3),
+            target_amp_factor: u64_at(11),
+            start_ramp_ts: u64_at(19) as i64,
+            stop_ramp_ts: u64_at(27) as i64,
+            future_admin_deadline: u64_at(35) as i64,
+            future_admin_key: pubkey_at(43),
+            admin_key: pubkey_at(75),
+            // Reserves of both tokens, the pool mint, both mints then both admin fee accounts
+            token_a: SaberSwapTokenInfo {
+                reserves: pubkey_at(107),
+                mint: pubkey_at(203),
+                admin_fees: pubkey_at(267),
+            },
+            token_b: SaberSwapTokenInfo {
+                reserves: pubkey_at(139),
+                mint: pubkey_at(235),
+                admin_fees: pubkey_at(299),
+            },
+            pool_mint: pubkey_at(171),
+            fees: SaberFees {
+                admin_trade_fee_numerator: u64_at(331),
+                admin_trade_fee_denominator: u64_at(339),
+                admin_withdraw_fee_numerator: u64_at(347),
+                admin_withdraw_fee_denominator: u64_at(355),
+                trade_fee_numerator: u64_at(363),
+                trade_fee_denominator: u64_at(371),
+                withdraw_fee_numerator: u64_at(379),
+                withdraw_fee_denominator: u64_at(387),
+            },
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        output[0] = self.is_initialized as u8;
+        output[1] = self.is_paused as u8;
+        output[2] = self.nonce;
+        let integers = [
+            self.initial_amp_factor,
+            self.target_amp_factor,
+            self.start_ramp_ts as u64,
+            self.stop_ramp_ts as u64,
+            self.future_admin_deadline as u64,
+        ];
+        for (index, integer) in integers.iter().enumerate() {
+            output[3 + index * 8..11 + index * 8].copy_from_slice(&integer.to_le_bytes());
+        }
+        let pubkeys = [
+            self.future_admin_key,
+            self.admin_key,
+            self.token_a.reserves,
+            self.token_b.reserves,
+            self.pool_mint,
+            self.token_a.mint,
+            self.token_b.mint,
+            self.token_a.admin_fees,
+            self.token_b.admin_fees,
+        ];
+        for (index, pubkey) in pubkeys.iter().enumerate() {
+            output[43 + index * 32..75 + index * 32].copy_from_slice(pubkey.as_ref());
+        }
+        let fees = [
+            self.fees.admin_trade_fee_numerator,
+            self.fees.admin_trade_fee_denominator,
+            self.fees.admin_withdraw_fee_numerator,
+            self.fees.admin_withdraw_fee_denominator,
+            self.fees.trade_fee_numerator,
+            self.fees.trade_fee_denominator,
+            self.fees.withdraw_fee_numerator,
+            self.fees.withdraw_fee_denominator,
+        ];
+        for (index, fee) in fees.iter().enumerate() {
+            output[331 + index * 8..339 + index * 8].copy_from_slice(&fee.to_le_bytes());
+        }
+    }
+}
+
+/// The StableSwap invariant of a two token pool with its amplification coefficient at a point in time
+pub struct StableSwap {
+    pub initial_amp_factor: u64,
+    pub target_amp_factor: u64,
+    pub current_ts: i64,
+    pub start_ramp_ts: i64,
+    pub stop_ramp_ts: i64,
+}
+
+impl StableSwap {
+    /// Amplification coefficient moving linearly from the initial to the target one during the ramp
+    pub fn compute_amp_factor(&self) -> Option<u64> {
+        if self.current_ts >= self.stop_ramp_ts {
+            return Some(self.target_amp_factor);
+        }
+        let time_range = self.stop_ramp_ts.checked_sub(self.start_ramp_ts)? as u128;
+        let time_delta = self.current_ts.checked_sub(self.start_ramp_ts)?.max(0) as u128;
+        let (initial, target) = (self.initial_amp_factor as u128, self.target_amp_factor as u128);
+        let amp_factor = if target >= initial {
+            initial + ((target - initial) * time_delta).checked_div(time_range)?
+        } else {
+            initial - ((initial - target) * time_delta).checked_div(time_range)?
+        };
+        amp_factor.try_into().ok()
+    }
+
+    fn compute_next_d(amp_factor: u64, d_init: U256, d_prod: U256, sum_x: u64) -> Option<U256> {
+        let ann = amp_factor.checked_mul(N_COINS.into())?;
+        let leverage = U256::from(sum_x) * U256::from(ann);
+        // d = (ann * sum_x + d_prod * n_coins) * d / ((ann - 1) * d + (n_coins + 1) * d_prod)
+        let numerator = d_init.checked_mul(d_prod * N_COINS + leverage)?;
+        let denominator = d_init.checked_mul(ann.checked_sub(1)?.into())? + d_prod * (N_COINS + 1);
+        numerator.checked_div(denominator)
+    }
+
+    /// Invariant D of the pool, found with Newton's method
+    pub fn compute_d(&self, amount_a: u64, amount_b: u64) -> Option<U256> {
+        let sum_x = amount_a.checked_add(amount_b)?;
+        if sum_x == 0 {
+            return Some(U256::zero());
+        }
+        let amp_factor = self.compute_amp_factor()?;
+        let amount_a_times_coins = U256::from(amount_a) * N_COINS;
+        let amount_b_times_coins = U256::from(amount_b) * N_COINS;
+
+        let mut d = U256::from(sum_x);
+        for _ in 0..MAX_ITERATIONS {
+            let mut d_prod = d;
+            d_prod = d_prod.checked_mul(d)?.checked_div(amount_a_times_coins)?;
+            d_prod = d_prod.checked_mul(d)?.checked_div(amount_b_times_coins)?;
+            let d_previous = d;
+            d = Self::compute_next_d(amp_factor, d, d_prod, sum_x)?;
+            if abs_diff(d, d_previous) <= U256::one() {
+                break;
+            }
+        }
+        Some(d)
+    }
+
+    /// Amount of the other token keeping the invariant D when this token is at x
+    pub fn compute_y(&self, x: u64, d: U256) -> Option<u64> {
+        let amp_factor = self.compute_amp_factor()?;
+        let ann = amp_factor.checked_mul(N_COINS.into())?;
+        // c = D ** (n + 1) / (n ** (2 * n) * prod' * A)
+        let mut c = d.checked_mul(d)?.checked_div(U256::from(x) * N_COINS)?;
+        c = c.checked_mul(d)?.checked_div(U256::from(ann) * N_COINS)?;
+        // b = sum' - (A * n ** n - 1) * D / (A * n ** n), D is subtracted in the denominator below
+        let b = d.checked_div(ann.into())? + U256::from(x);
+
+        let mut y = d;
+        for _ in 0..MAX_ITERATIONS {
+            let y_previous = y;
+            // y = (y * y + c) / (2 * y + b - D)
+            let y_numerator = y.checked_mul(y)?.checked_add(c)?;
+            let y_denominator = (y * 2u8 + b).checked_sub(d)?;
+            y = y_numerator.checked_div(y_denominator)?;
+            if abs_diff(y, y_previous) <= U256::one() {
+                break;
+            }
+        }
+        y.try_into().ok()
+    }
+
+    /// Output of the swap and the trade fee taken from it
+    pub fn swap_to(
+        &self,
+        source_amount: u64,
+        swap_source_amount: u64,
+        swap_destination_amount: u64,
+        fees: &SaberFees,
+    ) -> Option<(u64, u64)> {
+        let y = self.compute_y(
+            swap_source_amount.checked_add(source_amount)?,
+            self.compute_d(swap_source_amount, swap_destination_amount)?,
+        )?;
+        let dy = swap_destination_amount.checked_sub(y)?;
+        let dy_fee = fees.trade_fee(dy)?;
+        Some((dy.checked_sub(dy_fee)?, dy_fee))
+    }
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+pub struct SaberAmm {
+    key: Pubkey,
+    state: SaberSwapInfo,
+    reserves: [u64; 2],
+    current_ts: i64,
+    program_id: Pubkey,
+}
+
+impl SaberAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        let state = SaberSwapInfo::unpack(
+            keyed_account
+                .account
+                .data
+                .get(..SaberSwapInfo::LEN)
+                .with_context(|| format!("Invalid Saber swap account {}", keyed_account.key))?,
+        )?;
+
+        Ok(Self {
+            key: keyed_account.key,
+            state,
+            reserves: Default::default(),
+            current_ts: 0,
+            program_id: keyed_account.account.owner,
+        })
+    }
+
+    fn get_authority(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[&self.key.to_bytes(), &[self.state.nonce]], &self.program_id)
+            .map_err(|_| anyhow!("Invalid nonce for Saber swap {}", self.key))
+    }
+
+    fn stable_swap(&self) -> StableSwap {
+        StableSwap {
+            initial_amp_factor: self.state.initial_amp_factor,
+            target_amp_factor: self.state.target_amp_factor,
+            current_ts: self.current_ts,
+            start_ramp_ts: self.state.start_ramp_ts,
+            stop_ramp_ts: self.state.stop_ramp_ts,
+        }
+    }
+
+    fn clone(&self) -> SaberAmm {
+        SaberAmm {
+            key: self.key,
+            state: self.state,
+            reserves: self.reserves,
+            current_ts: self.current_ts,
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for SaberAmm {
+    fn label(&self) -> String {
+        String::from("Saber")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.state.token_a.mint, self.state.token_b.mint]
+    }
+
+    // The clock gives the time the amplification coefficient ramp is evaluated at
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![self.state.token_a.reserves, self.state.token_b.reserves, sysvar::clock::ID]
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let get_account = |key: &Pubkey| {
+            accounts_map
+                .get(key)
+                .with_context(|| format!("Missing account {}", key))
+        };
+        self.reserves = [
+            TokenAccount::unpack(get_account(&self.state.token_a.reserves)?)?.amount,
+            TokenAccount::unpack(get_account(&self.state.token_b.reserves)?)?.amount,
+        ];
+        // unix_timestamp follows slot, epoch_start_timestamp, epoch and leader_schedule_epoch
+        let clock = get_account(&sysvar::clock::ID)?;
+        self.current_ts = i64::from_le_bytes(
+            clock
+                .get(32..40)
+                .context("Invalid clock sysvar")?
+                .try_into()?,
+        );
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        if self.state.is_paused {
+            return Err(anyhow!("Saber swap {} is paused", self.key));
+        }
+        let (swap_source_amount, swap_destination_amount) = if quote_params.input_mint == self.state.token_a.mint {
+            (self.reserves[0], self.reserves[1])
+        } else {
+            (self.reserves[1], self.reserves[0])
+        };
+
+        let (out_amount, fee_amount) = self
+            .stable_swap()
+            .swap_to(
+                quote_params.in_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                &self.state.fees,
+            )
+            .context("quote failed")?;
+
+        Ok(Quote {
+            in_amount: quote_params.in_amount,
+            out_amount,
+            fee_amount,
+            fee_mint: quote_params.output_mint,
+            fee_pct: Decimal::from(self.state.fees.trade_fee_numerator)
+                .checked_div(Decimal::from(self.state.fees.trade_fee_denominator))
+                .unwrap_or_default(),
+            ..Quote::default()
+        })
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let (input, output) = if *source_mint == self.state.token_a.mint {
+            (self.state.token_a, self.state.token_b)
+        } else {
+            (self.state.token_b, self.state.token_a)
+        };
+
+        let account_metas = SaberSwap {
+            swap_program: self.program_id,
+            token_program: spl_token::ID,
+            swap: self.key,
+            swap_authority: self.get_authority()?,
+            user_authority: *user_transfer_authority,
+            input_user_account: *user_source_token_account,
+            input_token_account: input.reserves,
+            output_user_account: *user_destination_token_account,
+            output_token_account: output.reserves,
+            fees_token_account: output.admin_fees,
+        }
+        .to_account_metas(None);
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap: Swap::Saber },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_saber_usdc_usdt() {
+    use crate::amms::test_harness::{new_keyed_account, token_account_data};
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use solana_sdk::{account::create_account_for_test, clock::Clock};
+
+    let key = Pubkey::new_unique();
+    let nonce = Pubkey::find_program_address(&[&key.to_bytes()], &SABER_PROGRAM_ID).1;
+    let state = SaberSwapInfo {
+        is_initialized: true,
+        is_paused: false,
+        nonce,
+        initial_amp_factor: 100,
+        target_amp_factor: 200,
+        start_ramp_ts: 1_000_000,
+        stop_ramp_ts: 2_000_000,
+        future_admin_deadline: 0,
+        future_admin_key: Pubkey::default(),
+        admin_key: Pubkey::new_unique(),
+        token_a: SaberSwapTokenInfo {
+            reserves: Pubkey::new_unique(),
+            mint: USDC_MINT,
+            admin_fees: Pubkey::new_unique(),
+        },
+        token_b: SaberSwapTokenInfo {
+            reserves: Pubkey::new_unique(),
+            mint: USDT_MINT,
+            admin_fees: Pubkey::new_unique(),
+        },
+        pool_mint: Pubkey::new_unique(),
+        fees: SaberFees {
+            admin_trade_fee_numerator: 50,
+            admin_trade_fee_denominator: 100,
+            admin_withdraw_fee_numerator: 50,
+            admin_withdraw_fee_denominator: 100,
+            trade_fee_numerator: 4,
+            trade_fee_denominator: 10_000,
+            withdraw_fee_numerator: 0,
+            withdraw_fee_denominator: 10_000,
+        },
+    };
+    let mut data = vec![0u8; SaberSwapInfo::LEN];
+    SaberSwapInfo::pack(state, &mut data).unwrap();
+    assert_eq!(SaberSwapInfo::unpack(&data).unwrap(), state);
+
+    let short_data = data[..SaberSwapInfo::LEN - 1].to_vec();
+    assert!(SaberAmm::from_keyed_account(&new_keyed_account(key, short_data, SABER_PROGRAM_ID, None)).is_err());
+    let mut amm = SaberAmm::from_keyed_account(&new_keyed_account(key, data, SABER_PROGRAM_ID, None)).unwrap();
+
+    // Halfway through the ramp from 100 to 200
+    let clock = Clock {
+        unix_timestamp: 1_500_000,
+        ..Clock::default()
+    };
+    let mut accounts_map: HashMap<Pubkey, Vec<u8>> = [
+        (state.token_a.reserves, USDC_MINT, 10_000_000_000_000),
+        (state.token_b.reserves, USDT_MINT, 8_000_000_000_000),
+    ]
+    .iter()
+    .map(|(key, mint, amount)| (*key, token_account_data(*mint, *amount)))
+    .collect();
+    accounts_map.insert(sysvar::clock::ID, create_account_for_test(&clock).data);
+    amm.update(&accounts_map).unwrap();
+    assert_eq!(amm.stable_swap().compute_amp_factor(), Some(150));
+    // A ramp starting and stopping in the future has no time range to interpolate over
+    let empty_ramp = StableSwap {
+        start_ramp_ts: 2_000_000,
+        stop_ramp_ts: 2_000_000,
+        ..amm.stable_swap()
+    };
+    assert_eq!(empty_ramp.compute_amp_factor(), None);
+
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDC_MINT,
+            output_mint: USDT_MINT,
+        })
+        .unwrap();
+    // Cheaper USDC gets a little less than 1:1, minus the 4 bps trade fee
+    assert_eq!(quote.out_amount, 998_092_696);
+    assert_eq!(quote.fee_amount, 399_396);
+    let reverse_quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: USDT_MINT,
+            output_mint: USDC_MINT,
+        })
+        .unwrap();
+    assert!(reverse_quote.out_amount > 1_000_000_000);
+
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: USDT_MINT,
+            destination_mint: USDC_MINT,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap { swap: Swap::Saber }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 10);
+    assert_eq!(account_metas[6].pubkey, state.token_b.reserves);
+    assert_eq!(account_metas[8].pubkey, state.token_a.reserves);
+    assert_eq!(account_metas[9].pubkey, state.token_a.admin_fees);
+}
@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::{cmp::Reverse, convert::TryInto};
+
+/// Serum and OpenBook accounts start with "serum" and end with "padding"
+const ACCOUNT_HEAD_PADDING: &[u8; 5] = b"serum";
+const ACCOUNT_TAIL_PADDING: &[u8; 7] = b"padding";
+
+fn strip_padding(account_data: &[u8], len: usize) -> Result<&[u8]> {
+    if account_data.len() < ACCOUNT_HEAD_PADDING.len() + len + ACCOUNT_TAIL_PADDING.len()
+        || &account_data[..5] != ACCOUNT_HEAD_PADDING
+        || &account_data[account_data.len() - 7..] != ACCOUNT_TAIL_PADDING
+    {
+        return Err(anyhow!("Invalid serum account"));
+    }
+    Ok(&account_data[5..account_data.len() - 7])
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn pubkey_at(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::new_from_array(data[offset..offset + 32].try_into().unwrap())
+}
+
+/// The parts of a serum dex market state amms need to quote and build swaps
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SerumMarket {
+    pub own_address: Pubkey,
+    pub vault_signer_nonce: u64,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub fee_rate_bps: u64,
+}
+
+impl SerumMarket {
+    const LEN: usize = 376;
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        let data = strip_padding(account_data, Self::LEN)?;
+        Ok(Self {
+            own_address: pubkey_at(data, 8),
+            vault_signer_nonce: u64_at(data, 40),
+            coin_mint: pubkey_at(data, 48),
+            pc_mint: pubkey_at(data, 80),
+            coin_vault: pubkey_at(data, 112),
+            pc_vault: pubkey_at(data, 160),
+            request_queue: pubkey_at(data, 216),
+            event_queue: pubkey_at(data, 248),
+            bids: pubkey_at(data, 280),
+            asks: pubkey_at(data, 312),
+            coin_lot_size: u64_at(data, 344),
+            pc_lot_size: u64_at(data, 352),
+            fee_rate_bps: u64_at(data, 360),
+        })
+    }
+
+    pub fn vault_signer(&self, program_id: &Pubkey) -> Result<Pubkey> {
+        Pubkey::create_program_address(
+            &[&self.own_address.to_bytes(), &self.vault_signer_nonce.to_le_bytes()],
+            program_id,
+        )
+        .map_err(|_| anyhow!("Invalid vault signer nonce for market {}", self.own_address))
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[8..40].copy_from_slice(self.own_address.as_ref());
+        data[40..48].copy_from_slice(&self.vault_signer_nonce.to_le_bytes());
+        for (offset, pubkey) in [
+            (48, self.coin_mint),
+            (80, self.pc_mint),
+            (112, self.coin_vault),
+            (160, self.pc_vault),
+            (216, self.request_queue),
+            (248, self.event_queue),
+            (280, self.bids),
+            (312, self.asks),
+        ] {
+            data[offset..offset + 32].copy_from_slice(pubkey.as_ref());
+        }
+        for (offset, value) in [(344, self.coin_lot_size), (352, self.pc_lot_size), (360, self.fee_rate_bps)] {
+            data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        [&ACCOUNT_HEAD_PADDING[..], &data, &ACCOUNT_TAIL_PADDING[..]].concat()
+    }
+}
+
+/// Balances of a serum open orders account, free funds are settleable and totals include funds in orders
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OpenOrders {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub native_coin_free: u64,
+    pub native_coin_total: u64,
+    pub native_pc_free: u64,
+    pub native_pc_total: u64,
+}
+
+impl OpenOrders {
+    pub const LEN: usize = 3216;
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        let data = strip_padding(account_data, Self::LEN)?;
+        Ok(Self {
+            market: pubkey_at(data, 8),
+            owner: pubkey_at(data, 40),
+            native_coin_free: u64_at(data, 72),
+            native_coin_total: u64_at(data, 80),
+            native_pc_free: u64_at(data, 88),
+            native_pc_total: u64_at(data, 96),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[8..40].copy_from_slice(self.market.as_ref());
+        data[40..72].copy_from_slice(self.owner.as_ref());
+        for (offset, value) in [
+            (72, self.native_coin_free),
+            (80, self.native_coin_total),
+            (88, self.native_pc_free),
+            (96, self.native_pc_total),
+        ] {
+            data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        [&ACCOUNT_HEAD_PADDING[..], &data, &ACCOUNT_TAIL_PADDING[..]].concat()
+    }
+}
+
+/// Resting order of a bids or asks slab, in lots
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Order {
+    /// Pc lots per coin lot
+    pub price: u64,
+    pub quantity: u64,
+}
+
+/// Bids or asks of a serum market, a critbit tree of which only the leaves are read
+pub struct Slab;
+
+impl Slab {
+    /// Account flags then the slab header: bump index, free list length and head, root and leaf count
+    const NODES_OFFSET: usize = 8 + 32;
+    const NODE_LEN: usize = 72;
+    const LEAF_NODE_TAG: u32 = 2;
+
+    /// Orders of the slab, best first: highest bids or lowest asks
+    pub fn load_orders(account_data: &[u8], is_bids: bool) -> Result<Vec<Order>> {
+        let data = strip_padding(account_data, Self::NODES_OFFSET)?;
+        let bump_index = u64_at(data, 8) as usize;
+        // Freed leaves are retagged, every leaf node left holds an order
+        let mut orders: Vec<Order> = data[Self::NODES_OFFSET..]
+            .chunks_exact(Self::NODE_LEN)
+            .take(bump_index)
+            .filter(|node| u32::from_le_bytes(node[..4].try_into().unwrap()) == Self::LEAF_NODE_TAG)
+            .map(|node| Order {
+                // The price is the upper half of the order key
+                price: u64_at(node, 16),
+                quantity: u64_at(node, 56),
+            })
+            .collect();
+        if is_bids {
+            orders.sort_by_key(|order| Reverse(order.price));
+        } else {
+            orders.sort_by_key(|order| order.price);
+        }
+        Ok(orders)
+    }
+
+    #[cfg(test)]
+    pub fn pack(orders: &[Order]) -> Vec<u8> {
+        let mut data = vec![0u8; Self::NODES_OFFSET + orders.len() * Self::NODE_LEN];
+        data[8..16].copy_from_slice(&(orders.len() as u64).to_le_bytes());
+        data[32..40].copy_from_slice(&(orders.len() as u64).to_le_bytes());
+        for (order, node) in orders.iter().zip(data[Self::NODES_OFFSET..].chunks_exact_mut(Self::NODE_LEN)) {
+            node[..4].copy_from_slice(&Self::LEAF_NODE_TAG.to_le_bytes());
+            node[16..24].copy_from_slice(&order.price.to_le_bytes());
+            node[56..64].copy_from_slice(&order.quantity.to_le_bytes());
+        }
+        [&ACCOUNT_HEAD_PADDING[..], &data, &ACCOUNT_TAIL_PADDING[..]].concat()
+    }
+}
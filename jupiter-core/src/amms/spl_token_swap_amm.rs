@@ -10,7 +10,8 @@ use solana_sdk::{program_pack::Pack, pubkey, pubkey::Pubkey};
 use spl_token_swap::curve::base::SwapCurve;
 use spl_token_swap::{curve::calculator::TradeDirection, state::SwapV1};
 
-use super::amm::{Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::get_user_token_account_setup;
 use jupiter::{
     accounts::TokenSwap,
     jupiter_override::{Swap, SwapLeg},
@@ -39,7 +40,7 @@ lazy_static! {
         let mut m = HashMap::new();
         m.insert(spl_token_swap_programs::ORCA_V1, "Orca v1".into());
         m.insert(spl_token_swap_programs::ORCA_V2, "Orca v2".into());
-        // m.insert(spl_token_swap_programs::STEP, "Step".into()); We need to support the STEP state
+        // Step has its own state layout, see StepTokenSwapAmm
         m.insert(spl_token_swap_programs::STEPN, "StepN".into());
         m.insert(spl_token_swap_programs::SAROS, "Saros".into());
         m.insert(spl_token_swap_programs::PENGUIN, "Penguin".into());
@@ -177,7 +178,7 @@ impl Amm for SplTokenSwapAmm {
             user_source_token_account,
             user_transfer_authority,
             open_order_address,
-            quote_mint_to_referrer,
+            quote_mint_to_referrer: _,
         } = swap_params;
 
         let (swap_source, swap_destination) = if *source_mint == self.state.token_a_mint {
@@ -201,6 +202,9 @@ impl Amm for SplTokenSwapAmm {
         }
         .to_account_metas(None);
 
+        // The program takes the host fee account as an optional 12th account, the tokenSwap leg of
+        // the Jupiter IDL has exactly 11 so host fees cannot go to a referrer through Jupiter
+
         Ok(SwapLegAndAccountMetas {
             swap_leg: SwapLeg::Swap {
                 swap: Swap::TokenSwap,
@@ -212,6 +216,10 @@ impl Amm for SplTokenSwapAmm {
     fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
         Box::new(self.clone())
     }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
 }
 
 #[test]
@@ -221,7 +229,7 @@ fn test_new_spl_token_swap() {
 
     const SOL_USDC_POOL: Pubkey = pubkey!("EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U");
 
-    let test_harness = AmmTestHarness::new();
+    let test_harness = AmmTestHarness::new_with_fixtures("synthetic_spl_token_swap_sol_usdc");
     let keyed_account = test_harness.get_keyed_account(SOL_USDC_POOL).unwrap();
     let mut amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
 
@@ -237,4 +245,184 @@ fn test_new_spl_token_swap() {
 
     println!("Token mints: {:?}", amm.reserve_mints);
     println!("Quote result: {:?}", quote);
+    assert_eq!(amm.reserve_mints, [native_mint::id(), USDC_MINT]);
+    assert_eq!(quote.out_amount, 19_939_834);
+
+    // The leg keeps the 11 accounts of the IDL even with a referrer for the pool mint
+    let user = Pubkey::new_unique();
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: native_mint::id(),
+            destination_mint: USDC_MINT,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: user,
+            open_order_address: None,
+            quote_mint_to_referrer: Some(vec![(amm.state.pool_mint, Pubkey::new_unique())].into_iter().collect()),
+            in_amount: 1000000000,
+        })
+        .unwrap();
+    assert_eq!(swap_leg_and_account_metas.account_metas.len(), 11);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_spl_token_swap_quote_matches_execution() {
+    use crate::amms::program_test_harness::{add_mint, add_spl_token_program, add_token_account, ProgramTestHarness};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use solana_program_test::{processor, ProgramTest};
+    use solana_sdk::{account::Account, signature::Keypair, signer::Signer};
+    use spl_token_swap::curve::{base::CurveType, constant_product::ConstantProductCurve, fees::Fees};
+    use spl_token_swap::{instruction::Swap as SwapInstruction, state::SwapVersion};
+    use std::sync::Arc;
+
+    // The spl token swap program runs natively under the Orca v2 program id
+    let program_id = spl_token_swap_programs::ORCA_V2;
+    let mut rng = StdRng::seed_from_u64(42);
+
+    // The quote and the program share the curve code of the spl-token-swap crate, both are held to
+    // this constant product written out on its own: fees of at least one token are taken from the
+    // input and the pool keeps the rounding of the destination. Swaps paying out nothing fail.
+    let fee = |amount: u128, numerator: u64, denominator: u64| match (amount, numerator) {
+        (0, _) | (_, 0) => 0,
+        _ => (amount * numerator as u128 / denominator as u128).max(1),
+    };
+    let expected_out_amount = |in_amount: u64, source_reserve: u64, destination_reserve: u64, fees: &Fees| {
+        let in_amount = in_amount as u128;
+        let in_amount_after_fees = in_amount
+            .checked_sub(fee(in_amount, fees.trade_fee_numerator, fees.trade_fee_denominator))?
+            .checked_sub(fee(in_amount, fees.owner_trade_fee_numerator, fees.owner_trade_fee_denominator))?;
+        let invariant = source_reserve as u128 * destination_reserve as u128;
+        let new_source_reserve = source_reserve as u128 + in_amount_after_fees;
+        let new_destination_reserve = crate::math::checked_ceil_div(invariant, new_source_reserve)?;
+        Some((destination_reserve as u128 - new_destination_reserve) as u64).filter(|out_amount| *out_amount > 0)
+    };
+
+    for _ in 0..4 {
+        let mut program_test = ProgramTest::new(
+            "spl_token_swap",
+            program_id,
+            processor!(spl_token_swap::processor::Processor::process),
+        );
+        add_spl_token_program(&mut program_test);
+        let swap = Pubkey::new_unique();
+        let (authority, bump_seed) = Pubkey::find_program_address(&[&swap.to_bytes()], &program_id);
+        let (token_a_mint, token_b_mint, pool_mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (token_a, token_b, pool_fee_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let user = Keypair::new();
+        let (user_token_a, user_token_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        add_mint(&mut program_test, token_a_mint, Pubkey::new_unique(), u64::MAX, rng.gen_range(0..10));
+        add_mint(&mut program_test, token_b_mint, Pubkey::new_unique(), u64::MAX, rng.gen_range(0..10));
+        add_mint(&mut program_test, pool_mint, authority, 1_000_000_000, 6);
+        add_token_account(&mut program_test, token_a, token_a_mint, authority, rng.gen_range(1_000_000..1_000_000_000_000_000));
+        add_token_account(&mut program_test, token_b, token_b_mint, authority, rng.gen_range(1_000_000..1_000_000_000_000_000));
+        add_token_account(&mut program_test, pool_fee_account, pool_mint, Pubkey::new_unique(), 0);
+        add_token_account(&mut program_test, user_token_a, token_a_mint, user.pubkey(), u64::MAX / 2);
+        add_token_account(&mut program_test, user_token_b, token_b_mint, user.pubkey(), u64::MAX / 2);
+
+        let state = SwapV1 {
+            is_initialized: true,
+            bump_seed,
+            token_program_id: spl_token::ID,
+            token_a,
+            token_b,
+            pool_mint,
+            token_a_mint,
+            token_b_mint,
+            pool_fee_account,
+            fees: Fees {
+                trade_fee_numerator: rng.gen_range(0..100),
+                trade_fee_denominator: 10000,
+                owner_trade_fee_numerator: rng.gen_range(0..20),
+                owner_trade_fee_denominator: 10000,
+                owner_withdraw_fee_numerator: 0,
+                owner_withdraw_fee_denominator: 0,
+                host_fee_numerator: 0,
+                host_fee_denominator: 0,
+            },
+            swap_curve: SwapCurve {
+                curve_type: CurveType::ConstantProduct,
+                calculator: Arc::new(ConstantProductCurve),
+            },
+        };
+        let fees = state.fees.clone();
+        let mut data = vec![1u8; SwapVersion::LATEST_LEN];
+        SwapV1::pack(state, &mut data[1..]).unwrap();
+        program_test.add_account(
+            swap,
+            Account {
+                lamports: 1_000_000_000,
+                data,
+                owner: program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+
+        let mut harness = ProgramTestHarness::start(program_test).await;
+        let keyed_account = harness.get_keyed_account(swap).await.unwrap();
+        let mut amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+
+        for _ in 0..5 {
+            harness.update_amm(&mut amm).await.unwrap();
+            let (source_mint, destination_mint, swap_source, swap_destination, user_source, user_destination) =
+                if rng.gen_bool(0.5) {
+                    (token_a_mint, token_b_mint, token_a, token_b, user_token_a, user_token_b)
+                } else {
+                    (token_b_mint, token_a_mint, token_b, token_a, user_token_b, user_token_a)
+                };
+            let source_reserve = harness.get_token_balance(swap_source).await.unwrap();
+            let destination_reserve = harness.get_token_balance(swap_destination).await.unwrap();
+            // Small amounts too, where the minimum fee of one token applies
+            let in_amount = if rng.gen_bool(0.2) {
+                rng.gen_range(1..1_000)
+            } else {
+                rng.gen_range(1..source_reserve / 5)
+            };
+            let expected_out_amount = match expected_out_amount(in_amount, source_reserve, destination_reserve, &fees) {
+                Some(expected_out_amount) => expected_out_amount,
+                None => continue,
+            };
+
+            let quote = amm
+                .quote(&QuoteParams {
+                    in_amount,
+                    input_mint: source_mint,
+                    output_mint: destination_mint,
+                })
+                .unwrap();
+            let account_metas = amm
+                .get_swap_leg_and_account_metas(&SwapParams {
+                    source_mint,
+                    destination_mint,
+                    user_source_token_account: user_source,
+                    user_destination_token_account: user_destination,
+                    user_transfer_authority: user.pubkey(),
+                    open_order_address: None,
+                    quote_mint_to_referrer: None,
+                    in_amount,
+                })
+                .unwrap()
+                .account_metas;
+
+            // Jupiter token swap accounts lead with the swap program and the token program
+            let keys: Vec<Pubkey> = account_metas.iter().map(|account_meta| account_meta.pubkey).collect();
+            let instruction = spl_token_swap::instruction::swap(
+                &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7], &keys[8], &keys[9],
+                &keys[10], None,
+                SwapInstruction {
+                    amount_in: in_amount,
+                    minimum_amount_out: 0,
+                },
+            )
+            .unwrap();
+
+            let balance_before = harness.get_token_balance(user_destination).await.unwrap();
+            harness.process_instruction(instruction, &[&user]).await.unwrap();
+            let balance_after = harness.get_token_balance(user_destination).await.unwrap();
+            assert_eq!(quote.out_amount, expected_out_amount);
+            assert_eq!(balance_after - balance_before, expected_out_amount);
+        }
+    }
 }
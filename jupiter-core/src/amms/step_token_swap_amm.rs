@@ -0,0 +1,415 @@
+use anchor_lang::ToAccountMetas;
+use anyhow::{anyhow, Context, Result};
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey,
+    pubkey::Pubkey,
+};
+use spl_token_swap::curve::{base::SwapCurve, calculator::TradeDirection, fees::Fees};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::get_user_token_account_setup;
+use jupiter::{
+    accounts::StepSwap,
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const STEP_TOKEN_SWAP_PROGRAM_ID: Pubkey = pubkey!("SSwpMgqNDsyV7mAgN9ady4bDVu5ySjmmXejXvy2vLt1");
+
+/// Step's fork of the spl token swap state: fees without the host fee and a pool nonce after the curve
+#[derive(Debug, Default, PartialEq)]
+pub struct StepSwapState {
+    pub is_initialized: bool,
+    pub nonce: u8,
+    pub token_program_id: Pubkey,
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub pool_mint: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub pool_fee_account: Pubkey,
+    pub fees: Fees,
+    pub swap_curve: SwapCurve,
+    pub pool_nonce: u8,
+}
+
+// SwapCurve is only Clone in spl token swap tests, its calculator is shared instead
+impl Clone for StepSwapState {
+    fn clone(&self) -> Self {
+        StepSwapState {
+            fees: self.fees.clone(),
+            swap_curve: SwapCurve {
+                curve_type: self.swap_curve.curve_type,
+                calculator: self.swap_curve.calculator.clone(),
+            },
+            ..*self
+        }
+    }
+}
+
+const FEES_LEN: usize = 48;
+const SWAP_CURVE_LEN: usize = 33;
+
+impl Sealed for StepSwapState {}
+
+impl IsInitialized for StepSwapState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for StepSwapState {
+    const LEN: usize = 2 + 7 * 32 + FEES_LEN + SWAP_CURVE_LEN + 1;
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let pubkey_at = |index: usize| Pubkey::new_from_array(input[2 + index * 32..34 + index * 32].try_into().unwrap());
+        let u64_at = |offset: usize| u64::from_le_bytes(input[offset..offset + 8].try_into().unwrap());
+        let fees_offset = 2 + 7 * 32;
+        let curve_offset = fees_offset + FEES_LEN;
+        Ok(Self {
+            is_initialized: match input[0] {
+                0 => false,
+                1 => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: input[1],
+            token_program_id: pubkey_at(0),
+            token_a: pubkey_at(1),
+            token_b: pubkey_at(2),
+            pool_mint: pubkey_at(3),
+            token_a_mint: pubkey_at(4),
+            token_b_mint: pubkey_at(5),
+            pool_fee_account: pubkey_at(6),
+            fees: Fees {
+                trade_fee_numerator: u64_at(fees_offset),
+                trade_fee_denominator: u64_at(fees_offset + 8),
+                owner_trade_fee_numerator: u64_at(fees_offset + 16),
+                owner_trade_fee_denominator: u64_at(fees_offset + 24),
+                owner_withdraw_fee_numerator: u64_at(fees_offset + 32),
+                owner_withdraw_fee_denominator: u64_at(fees_offset + 40),
+                host_fee_numerator: 0,
+                host_fee_denominator: 0,
+            },
+            swap_curve: SwapCurve::unpack_from_slice(&input[curve_offset..curve_offset + SWAP_CURVE_LEN])?,
+            pool_nonce: input[curve_offset + SWAP_CURVE_LEN],
+        })
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        output[0] = self.is_initialized as u8;
+        output[1] = self.nonce;
+        let pubkeys = [
+            self.token_program_id,
+            self.token_a,
+            self.token_b,
+            self.pool_mint,
+            self.token_a_mint,
+            self.token_b_mint,
+            self.pool_fee_account,
+        ];
+        for (index, pubkey) in pubkeys.iter().enumerate() {
+            output[2 + index * 32..34 + index * 32].copy_from_slice(pubkey.as_ref());
+        }
+        let fees_offset = 2 + 7 * 32;
+        let fees = [
+            self.fees.trade_fee_numerator,
+            self.fees.trade_fee_denominator,
+            self.fees.owner_trade_fee_numerator,
+            self.fees.owner_trade_fee_denominator,
+            self.fees.owner_withdraw_fee_numerator,
+            self.fees.owner_withdraw_fee_denominator,
+        ];
+        for (index, fee) in fees.iter().enumerate() {
+            output[fees_offset + index * 8..fees_offset + (index + 1) * 8].copy_from_slice(&fee.to_le_bytes());
+        }
+        let curve_offset = fees_offset + FEES_LEN;
+        self.swap_curve
+            .pack_into_slice(&mut output[curve_offset..curve_offset + SWAP_CURVE_LEN]);
+        output[curve_offset + SWAP_CURVE_LEN] = self.pool_nonce;
+    }
+}
+
+pub struct StepTokenSwapAmm {
+    key: Pubkey,
+    state: StepSwapState,
+    reserve_mints: [Pubkey; 2],
+    reserves: [u128; 2],
+    program_id: Pubkey,
+}
+
+impl StepTokenSwapAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        // Skip the first byte which is version
+        let state = StepSwapState::unpack(&keyed_account.account.data[1..])?;
+        let reserve_mints = [state.token_a_mint, state.token_b_mint];
+
+        Ok(Self {
+            key: keyed_account.key,
+            state,
+            reserve_mints,
+            program_id: keyed_account.account.owner,
+            reserves: Default::default(),
+        })
+    }
+
+    fn get_authority(&self) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[&self.key.to_bytes(), &[self.state.nonce]], &self.program_id)
+            .map_err(|_| anyhow!("Invalid nonce for Step swap {}", self.key))
+    }
+
+    fn clone(&self) -> StepTokenSwapAmm {
+        StepTokenSwapAmm {
+            key: self.key,
+            state: self.state.clone(),
+            reserve_mints: self.reserve_mints,
+            reserves: self.reserves,
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for StepTokenSwapAmm {
+    fn label(&self) -> String {
+        String::from("Step")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        self.reserve_mints.to_vec()
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![self.state.token_a, self.state.token_b]
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let token_a_account = accounts_map
+            .get(&self.state.token_a)
+            .with_context(|| format!("Missing token account {}", self.state.token_a))?;
+        let token_b_account = accounts_map
+            .get(&self.state.token_b)
+            .with_context(|| format!("Missing token account {}", self.state.token_b))?;
+
+        self.reserves = [
+            TokenAccount::unpack(token_a_account)?.amount.into(),
+            TokenAccount::unpack(token_b_account)?.amount.into(),
+        ];
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let (trade_direction, swap_source_amount, swap_destination_amount) =
+            if quote_params.input_mint == self.reserve_mints[0] {
+                (TradeDirection::AtoB, self.reserves[0], self.reserves[1])
+            } else {
+                (TradeDirection::BtoA, self.reserves[1], self.reserves[0])
+            };
+
+        let swap_result = self
+            .state
+            .swap_curve
+            .swap(
+                quote_params.in_amount.into(),
+                swap_source_amount,
+                swap_destination_amount,
+                trade_direction,
+                &self.state.fees,
+            )
+            .context("quote failed")?;
+
+        Ok(Quote {
+            in_amount: swap_result.source_amount_swapped.try_into()?,
+            out_amount: swap_result.destination_amount_swapped.try_into()?,
+            fee_amount: (swap_result.trade_fee + swap_result.owner_fee).try_into()?,
+            fee_mint: quote_params.input_mint,
+            ..Quote::default()
+        })
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let (swap_source, swap_destination) = if *source_mint == self.state.token_a_mint {
+            (self.state.token_a, self.state.token_b)
+        } else {
+            (self.state.token_b, self.state.token_a)
+        };
+
+        let account_metas = StepSwap {
+            token_swap_program: self.program_id,
+            token_program: spl_token::ID,
+            swap: self.key,
+            authority: self.get_authority()?,
+            user_transfer_authority: *user_transfer_authority,
+            source: *user_source_token_account,
+            swap_source,
+            swap_destination,
+            destination: *user_destination_token_account,
+            pool_mint: self.state.pool_mint,
+            pool_fee: self.state.pool_fee_account,
+        }
+        .to_account_metas(None);
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap { swap: Swap::Step },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_step_token_swap() {
+    use crate::amms::test_harness::{new_keyed_account, token_account_data};
+    use spl_token_swap::curve::{base::CurveType, constant_product::ConstantProductCurve};
+    use std::sync::Arc;
+
+    let key = Pubkey::new_unique();
+    let nonce = Pubkey::find_program_address(&[&key.to_bytes()], &STEP_TOKEN_SWAP_PROGRAM_ID).1;
+    let (token_a_mint, token_b_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let state = StepSwapState {
+        is_initialized: true,
+        nonce,
+        token_program_id: spl_token::ID,
+        token_a: Pubkey::new_unique(),
+        token_b: Pubkey::new_unique(),
+        pool_mint: Pubkey::new_unique(),
+        token_a_mint,
+        token_b_mint,
+        pool_fee_account: Pubkey::new_unique(),
+        fees: Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        },
+        swap_curve: SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Arc::new(ConstantProductCurve),
+        },
+        pool_nonce: 254,
+    };
+    // First byte is the version
+    let mut data = vec![1u8; 1 + StepSwapState::LEN];
+    StepSwapState::pack(state.clone(), &mut data[1..]).unwrap();
+    assert_eq!(StepSwapState::unpack(&data[1..]).unwrap(), state);
+
+    let mut amm =
+        StepTokenSwapAmm::from_keyed_account(&new_keyed_account(key, data, STEP_TOKEN_SWAP_PROGRAM_ID, None)).unwrap();
+    assert_eq!(amm.get_reserve_mints(), vec![token_a_mint, token_b_mint]);
+
+    let accounts_map = [(state.token_a, token_a_mint, 1_000_000_000_000), (state.token_b, token_b_mint, 20_000_000_000)]
+        .iter()
+        .map(|(key, mint, amount)| (*key, token_account_data(*mint, *amount)))
+        .collect();
+    amm.update(&accounts_map).unwrap();
+
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000_000,
+            input_mint: token_a_mint,
+            output_mint: token_b_mint,
+        })
+        .unwrap();
+    // 0.3% of the input is kept as fees before the constant product swap
+    assert_eq!(quote.fee_amount, 3_000_000);
+    assert_eq!(quote.out_amount, 19_920_139);
+
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: token_b_mint,
+            destination_mint: token_a_mint,
+            user_source_token_account: Pubkey::new_unique(),
+            user_destination_token_account: Pubkey::new_unique(),
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap { swap: Swap::Step }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 11);
+    assert_eq!(account_metas[0].pubkey, STEP_TOKEN_SWAP_PROGRAM_ID);
+    assert_eq!(
+        account_metas[3].pubkey,
+        Pubkey::find_program_address(&[&key.to_bytes()], &STEP_TOKEN_SWAP_PROGRAM_ID).0
+    );
+    assert_eq!(account_metas[6].pubkey, state.token_b);
+    assert_eq!(account_metas[7].pubkey, state.token_a);
+}
+
+/// Decodes the pools recorded from a cluster under fixtures/step_<pool>, byte for byte. None is checked in yet,
+/// record one with `record_fixtures -- <rpc url> jupiter-core/fixtures/step_<pool> step-token-swap <pool>`
+#[test]
+fn test_step_recorded_pools() {
+    use crate::amms::test_harness::AmmTestHarness;
+    use spl_token::state::Account as TokenAccount;
+    use std::str::FromStr;
+
+    let fixtures = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    for entry in std::fs::read_dir(fixtures).unwrap() {
+        let fixture_name = entry.unwrap().file_name().into_string().unwrap();
+        let key = match fixture_name.strip_prefix("step_") {
+            Some(key) => Pubkey::from_str(key).unwrap(),
+            None => continue,
+        };
+        let test_harness = AmmTestHarness::new_with_fixtures(&fixture_name);
+        let keyed_account = test_harness.get_keyed_account(key).unwrap();
+        assert_eq!(keyed_account.account.owner, STEP_TOKEN_SWAP_PROGRAM_ID);
+        let mut amm = StepTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+
+        // Packing the decoded state over the recorded bytes changes none of them
+        let recorded_data = &keyed_account.account.data[1..1 + StepSwapState::LEN];
+        let mut data = recorded_data.to_vec();
+        StepSwapState::pack(amm.state.clone(), &mut data).unwrap();
+        assert_eq!(data, recorded_data);
+        assert!(amm.get_authority().is_ok());
+
+        let token_accounts = [(amm.state.token_a, amm.state.token_a_mint), (amm.state.token_b, amm.state.token_b_mint)];
+        for (token_account, mint) in token_accounts {
+            let token_account = test_harness.get_keyed_account(token_account).unwrap();
+            assert_eq!(TokenAccount::unpack(&token_account.account.data).unwrap().mint, mint);
+        }
+        test_harness.update_amm(&mut amm);
+        assert!(amm.reserves.iter().all(|reserve| *reserve > 0));
+        amm.quote(&QuoteParams {
+            in_amount: 1_000_000,
+            input_mint: amm.state.token_a_mint,
+            output_mint: amm.state.token_b_mint,
+        })
+        .unwrap();
+    }
+}
@@ -1,30 +1,40 @@
 use anchor_lang::prelude::AccountMeta;
-use anchor_lang::prelude::*;
-use anyhow::Result;
-use std::collections::HashMap;
+use anyhow::{anyhow, Context, Result};
+use std::{collections::HashMap, convert::{TryFrom, TryInto}};
 
 use crate::amms::amm::{Amm, KeyedAccount};
-use solana_sdk::{ pubkey, pubkey::Pubkey, instruction::Instruction};
+use solana_sdk::{ pubkey, pubkey::Pubkey, instruction::Instruction, sysvar};
 use rust_decimal::Decimal;
 
 use super::accounts::{FundState, CurveData, TokenInfo, SimplePrice, TokenPriceData, MAX_TOKENS_IN_ASSET_POOL};
-use super::amm::{Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::amm::{AmmUserSetup, Quote, QuoteMintToReferrer, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::user_setup::get_user_token_account_setup;
 use jupiter::jupiter_override::{Swap, SwapLeg};
 
+/// Split of the curve fees, all in the output token
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SymmetryFees {
+    pub symmetry_fee: u64,
+    pub host_fee: u64,
+    pub manager_fee: u64,
+    pub fund_fee: u64,
+}
+
 pub struct SymmetryTokenSwap {
     key: Pubkey,
     label: String,
     fund_state: FundState,
     token_info: TokenInfo,
     curve_data: CurveData,
+    current_slot: u64,
 }
 
 impl SymmetryTokenSwap {
 
     const SYMMETRY_PROGRAM_ADDRESS: Pubkey = pubkey!("2KehYt3KsEQR53jYcxjbQp2d2kCp4AkuQW68atufRwSr");
-    const TOKEN_INFO_ADDRESS: Pubkey = pubkey!("4Rn7pKKyiSNKZXKCoLqEpRznX1rhveV4dW1DCg6hRoVH");
+    pub const TOKEN_INFO_ADDRESS: Pubkey = pubkey!("4Rn7pKKyiSNKZXKCoLqEpRznX1rhveV4dW1DCg6hRoVH");
     const CURVE_DATA_ADDRESS: Pubkey = pubkey!("4QMjSHuM3iS7Fdfi8kZJfHRKoEJSDHEtEwqbChsTcUVK");
-    const PDA_ADDRESS: Pubkey = pubkey!("BLBYiq48WcLQ5SxiftyKmPtmsZPUBEnDEjqEnKGAR4zx");
+    pub const PDA_ADDRESS: Pubkey = pubkey!("BLBYiq48WcLQ5SxiftyKmPtmsZPUBEnDEjqEnKGAR4zx");
     const SWAP_FEE_ADDRESS: Pubkey = pubkey!("AWfpfzA6FYbqx4JLz75PDgsjH7jtBnnmJ6MXW5zNY2Ei");
 
     const ASSOCIATED_TOKEN_PROGRAM_ADDRESS: Pubkey = pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
@@ -47,6 +57,7 @@ impl SymmetryTokenSwap {
             fund_state: fund_state,
             token_info: token_info,
             curve_data: CurveData::empty(),
+            current_slot: 0,
         })
     }
 
@@ -75,12 +86,15 @@ impl SymmetryTokenSwap {
             curve_data: CurveData {
                 buy: self.curve_data.buy,
                 sell: self.curve_data.sell
-            }
+            },
+            current_slot: self.current_slot,
         }
     }
 
-    pub fn usd_value(amount: u64, decimals: u64, pyth_price: SimplePrice, low_or_high: u8) -> u64 {
-        let mut pow_den: u128 = u128::pow(10,decimals as u32 + (-pyth_price.expo) as u32);
+    /// Numerator and denominator scaling a token amount times its pyth price to a usd value of 6 decimals
+    fn usd_scale(decimals: u64, pyth_price: SimplePrice) -> Option<(u128, u128)> {
+        let exponent = u32::try_from(decimals).ok()?.checked_add(u32::try_from(-(pyth_price.expo as i64)).ok()?)?;
+        let mut pow_den: u128 = 10u128.checked_pow(exponent)?;
         let mut pow_num: u128 = 1000000;
         if pow_den > pow_num {
             pow_den /= pow_num;
@@ -89,36 +103,37 @@ impl SymmetryTokenSwap {
             pow_num /= pow_den;
             pow_den = 1;
         }
-        let price = match low_or_high {
-            0 => pyth_price.low,
-            1 => pyth_price.high,
-            _ => pyth_price.price
-        };
-        ((amount as u128) * (price as u128) * pow_num / pow_den) as u64
+        Some((pow_num, pow_den))
     }
 
-    pub fn amount_from_usd_value(usd_value: u64, decimals: u64, pyth_price: SimplePrice, low_or_high: u8) -> u64 {
-        let mut pow_den: u128 = u128::pow(10,decimals as u32 + (-pyth_price.expo) as u32);
-        let mut pow_num: u128 = 1000000;
-        if pow_den > pow_num {
-            pow_den /= pow_num;
-            pow_num = 1;
-        } else {
-            pow_num /= pow_den;
-            pow_den = 1;
-        }
+    fn price(pyth_price: SimplePrice, low_or_high: u8) -> Option<u128> {
         let price = match low_or_high {
             0 => pyth_price.low,
             1 => pyth_price.high,
             _ => pyth_price.price
         };
-        ((usd_value as u128) * pow_den / (price as u128) / pow_num) as u64
+        u128::try_from(price).ok()
+    }
+
+    /// None when the value does not fit a u64
+    pub fn usd_value(amount: u64, decimals: u64, pyth_price: SimplePrice, low_or_high: u8) -> Option<u64> {
+        let (pow_num, pow_den) = SymmetryTokenSwap::usd_scale(decimals, pyth_price)?;
+        let price = SymmetryTokenSwap::price(pyth_price, low_or_high)?;
+        ((amount as u128).checked_mul(price)?.checked_mul(pow_num)? / pow_den).try_into().ok()
+    }
+
+    /// None when the amount does not fit a u64 or the price is zero
+    pub fn amount_from_usd_value(usd_value: u64, decimals: u64, pyth_price: SimplePrice, low_or_high: u8) -> Option<u64> {
+        let (pow_num, pow_den) = SymmetryTokenSwap::usd_scale(decimals, pyth_price)?;
+        let price = SymmetryTokenSwap::price(pyth_price, low_or_high)?;
+        ((usd_value as u128).checked_mul(pow_den)?.checked_div(price)? / pow_num).try_into().ok()
     }
 
-    pub fn mul_div(a: u64, b: u64, c: u64) -> u64 {
+    /// None when the result does not fit a u64
+    pub fn mul_div(a: u64, b: u64, c: u64) -> Option<u64> {
         match c {
-            0 => 0,
-            _ => ((a as u128) * (b as u128) / (c as u128)) as u64
+            0 => Some(0),
+            _ => ((a as u128) * (b as u128) / (c as u128)).try_into().ok()
         }
     }
 
@@ -129,7 +144,7 @@ impl SymmetryTokenSwap {
         amount_value: u64,
         prism_data: TokenPriceData,
         decimals: u8,
-    ) -> u64 {
+    ) -> Option<u64> {
         let curve_start_amount = if current_amount < target_amount
             { target_amount } else { current_amount };
     
@@ -142,7 +157,7 @@ impl SymmetryTokenSwap {
             decimals as u64,
             pyth,
             SymmetryTokenSwap::BUY_PRICE,
-        );
+        )?;
         let mut current_price = pyth_price;
     
         let mut amount_from_target_weight: u64 = 0;
@@ -160,15 +175,14 @@ impl SymmetryTokenSwap {
                 amount_from_target_weight - (curve_start_amount - current_amount),
                 prism_data.amount[step]
             );
-            let value_in_interval = SymmetryTokenSwap::mul_div(amount_in_interval, current_price, expo);
+            let value_in_interval = SymmetryTokenSwap::mul_div(amount_in_interval, current_price, expo)?;
             if value_in_interval > amount_value_left {
-                 return SymmetryTokenSwap::mul_div(amount_value_left, expo, current_price) + current_output_amount;
+                 return SymmetryTokenSwap::mul_div(amount_value_left, expo, current_price)?.checked_add(current_output_amount);
             }
             current_output_amount += amount_in_interval;
             amount_value_left -= value_in_interval;
         }
-        current_output_amount += SymmetryTokenSwap::mul_div(amount_value_left, expo, current_price);
-        current_output_amount
+        SymmetryTokenSwap::mul_div(amount_value_left, expo, current_price)?.checked_add(current_output_amount)
     }
 
     pub fn calculate_output_value_for_selling_asset(
@@ -178,7 +192,7 @@ impl SymmetryTokenSwap {
         amount: u64,
         prism_data: TokenPriceData,
         decimals: u8,
-    ) -> u64 {
+    ) -> Option<u64> {
         let curve_start_amount = if current_amount > target_amount
             { target_amount } else { current_amount };
     
@@ -191,7 +205,7 @@ impl SymmetryTokenSwap {
             decimals as u64,
             pyth,
             SymmetryTokenSwap::SELL_PRICE,
-        );
+        )?;
         let mut current_price = pyth_price;
     
         let mut amount_from_target_weight: u64 = 0;
@@ -209,74 +223,55 @@ impl SymmetryTokenSwap {
                 amount_from_target_weight - (current_amount - curve_start_amount),
                 prism_data.amount[step],
             );
-            let value_in_interval = SymmetryTokenSwap::mul_div(amount_in_interval, current_price, expo);
+            let value_in_interval = SymmetryTokenSwap::mul_div(amount_in_interval, current_price, expo)?;
     
             if amount_in_interval > amount_left {
-                 return SymmetryTokenSwap::mul_div(amount_left, current_price, expo) + current_output_value;
+                 return SymmetryTokenSwap::mul_div(amount_left, current_price, expo)?.checked_add(current_output_value);
             }
             current_output_value += value_in_interval;
             amount_left -= amount_in_interval;
         }
-        current_output_value += SymmetryTokenSwap::mul_div(amount_left, current_price, expo);
+        current_output_value += SymmetryTokenSwap::mul_div(amount_left, current_price, expo)?;
     
-        current_output_value
+        Some(current_output_value)
     }
 
+    // Prices are aged against the slot of the clock sysvar read in update
     fn validate_price(
-        price: SimplePrice
-    ) {
-        if Clock::get().unwrap_or_default().slot >= price.slot + 50 || price.status != 1{
-            panic!();
+        price: SimplePrice,
+        current_slot: u64,
+    ) -> Result<()> {
+        if current_slot >= price.slot.saturating_add(50) || price.status != 1 {
+            return Err(anyhow!("Oracle price of slot {} is stale at slot {}", price.slot, current_slot));
         }
         if price.price < 0 || price.conf * 10 > price.price as u128 {
-            panic!();
-        }
-    }
-    
-}
-
-impl Amm for SymmetryTokenSwap {
-    fn label(&self) -> String {
-        String::from("Symmetry")
-    }
-
-    fn key(&self) -> Pubkey {
-        self.key
-    }
-
-    fn get_reserve_mints(&self) -> Vec<Pubkey> {
-        let mut vec: Vec<Pubkey> = Vec::new();
-        for i in 0..self.fund_state.num_of_tokens as usize {
-            vec.push(self.token_info.token_mint[self.fund_state.current_comp_token[i] as usize])
-        }
-        return vec;
-    }
-
-    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
-        let mut accounts_to_update: Vec<Pubkey> = Vec::new();
-        accounts_to_update.push(SymmetryTokenSwap::CURVE_DATA_ADDRESS);
-        accounts_to_update.push(self.key);
-        for i in 0..MAX_TOKENS_IN_ASSET_POOL {
-            if self.token_info.oracle[i] != Pubkey::default() {
-                accounts_to_update.push(self.token_info.oracle[i])
-            }
+            return Err(anyhow!("Oracle price {} is too uncertain, confidence {}", price.price, price.conf));
         }
-        return accounts_to_update;
+        Ok(())
     }
 
-    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
-        self.curve_data = CurveData::load(accounts_map.get(&SymmetryTokenSwap::CURVE_DATA_ADDRESS).unwrap());
-        self.fund_state = FundState::load(accounts_map.get(&self.key).unwrap());
-        for i in 0..50 {
-            if self.token_info.oracle[i] != Pubkey::default() {
-                self.token_info.oracle_price[i] = SimplePrice::load(accounts_map.get(&self.token_info.oracle[i]).unwrap());
-            }
+    /// Host fee goes to the referrer token account of the destination mint when there is one,
+    /// otherwise to the associated token account of the fund host
+    pub fn get_host_fee_account(
+        &self,
+        destination_mint: &Pubkey,
+        quote_mint_to_referrer: Option<&QuoteMintToReferrer>,
+    ) -> Pubkey {
+        match quote_mint_to_referrer.and_then(|referrers| referrers.get(destination_mint)) {
+            Some(referrer) => *referrer,
+            None => Pubkey::find_program_address(
+                &[
+                    &self.fund_state.host_pubkey.to_bytes(),
+                    &SymmetryTokenSwap::SPL_TOKEN_PROGRAM_ADDRESS.to_bytes(),
+                    &destination_mint.to_bytes()
+                ],
+                &SymmetryTokenSwap::ASSOCIATED_TOKEN_PROGRAM_ADDRESS
+            ).0,
         }
-
-        Ok(())
     }
 
-    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+    /// Quote along with how the curve fees are split between Symmetry, the host, the manager and the fund
+    pub fn quote_with_fees(&self, quote_params: &QuoteParams) -> Result<(Quote, SymmetryFees)> {
         
         let from_amount: u64 = quote_params.in_amount;
         let from_token_id: u64 = self.token_info.token_mint.iter().position(|&x| x == quote_params.input_mint).unwrap() as u64;
@@ -290,37 +285,39 @@ impl Amm for SymmetryTokenSwap {
         let mut fund_worth = 0;
         for i in 0..(self.fund_state.num_of_tokens as usize) {
             let token = self.fund_state.current_comp_token[i] as usize;
-            SymmetryTokenSwap::validate_price(self.token_info.oracle_price[token]);
+            SymmetryTokenSwap::validate_price(self.token_info.oracle_price[token], self.current_slot)?;
             fund_worth += SymmetryTokenSwap::usd_value(
                 self.fund_state.current_comp_amount[i],
                 self.token_info.decimals[token] as u64,
                 self.token_info.oracle_price[token],
                 SymmetryTokenSwap::AVG_PRICE,
-            );
+            ).context("Usd value overflows")?;
         }
 
         let from_token_price = self.token_info.oracle_price[from_token_id as usize];
         let to_token_price= self.token_info.oracle_price[to_token_id as usize];
         
         let from_token_target_amount: u64 = SymmetryTokenSwap::amount_from_usd_value(
-            SymmetryTokenSwap::mul_div(self.fund_state.target_weight[from_token_index], fund_worth, self.fund_state.weight_sum),
+            SymmetryTokenSwap::mul_div(self.fund_state.target_weight[from_token_index], fund_worth, self.fund_state.weight_sum)
+                .context("Target value overflows")?,
             self.token_info.decimals[from_token_id as usize] as u64,
             from_token_price,
             SymmetryTokenSwap::AVG_PRICE,
-        );
+        ).context("Usd value overflows")?;
         let to_token_target_amount: u64 = SymmetryTokenSwap::amount_from_usd_value(
-            SymmetryTokenSwap::mul_div(self.fund_state.target_weight[to_token_index], fund_worth, self.fund_state.weight_sum),
+            SymmetryTokenSwap::mul_div(self.fund_state.target_weight[to_token_index], fund_worth, self.fund_state.weight_sum)
+                .context("Target value overflows")?,
             self.token_info.decimals[to_token_id as usize] as u64,
             to_token_price,
             SymmetryTokenSwap::AVG_PRICE,
-        );
+        ).context("Usd value overflows")?;
 
         let from_token_value = SymmetryTokenSwap::usd_value(
             from_amount,
             self.token_info.decimals[from_token_id as usize] as u64,
             from_token_price,
             SymmetryTokenSwap::SELL_PRICE,
-        );
+        ).context("Usd value overflows")?;
 
         let value = match from_token_id as usize {
             0 => from_token_value,
@@ -331,7 +328,7 @@ impl Amm for SymmetryTokenSwap {
                 from_amount,
                 self.curve_data.sell[from_token_id as usize],
                 self.token_info.decimals[from_token_id as usize],
-            ),
+            ).context("Usd value overflows")?,
         };
 
         let mut to_amount = match to_token_id as usize {
@@ -340,7 +337,7 @@ impl Amm for SymmetryTokenSwap {
                 self.token_info.decimals[to_token_id as usize] as u64,
                 to_token_price,
                 SymmetryTokenSwap::BUY_PRICE,
-            ),
+            ).context("Usd value overflows")?,
             _ => SymmetryTokenSwap::calculate_output_amount_for_buying_asset(
                 self.fund_state.current_comp_amount[to_token_index],
                 to_token_target_amount,
@@ -348,7 +345,7 @@ impl Amm for SymmetryTokenSwap {
                 value,
                 self.curve_data.buy[to_token_id as usize],
                 self.token_info.decimals[to_token_id as usize],
-            ),
+            ).context("Usd value overflows")?,
         };
 
         let mut amount_without_curve = SymmetryTokenSwap::amount_from_usd_value(
@@ -356,7 +353,7 @@ impl Amm for SymmetryTokenSwap {
             self.token_info.decimals[to_token_id as usize] as u64,
             to_token_price,
             SymmetryTokenSwap::BUY_PRICE,
-        );
+        ).context("Usd value overflows")?;
         
         let mut fee_due_nel: u64 = 0;
         if amount_without_curve > self.fund_state.current_comp_amount[to_token_index] {
@@ -369,9 +366,9 @@ impl Amm for SymmetryTokenSwap {
         }
 
         let total_fees = amount_without_curve - to_amount;
-        let symmetry_fee = SymmetryTokenSwap::mul_div(total_fees, 5, 100);
-        let host_fee = SymmetryTokenSwap::mul_div(total_fees, 20, 100);
-        let manager_fee = SymmetryTokenSwap::mul_div(total_fees, 20, 100);
+        let symmetry_fee = SymmetryTokenSwap::mul_div(total_fees, 5, 100).context("Fee overflows")?;
+        let host_fee = SymmetryTokenSwap::mul_div(total_fees, 20, 100).context("Fee overflows")?;
+        let manager_fee = SymmetryTokenSwap::mul_div(total_fees, 20, 100).context("Fee overflows")?;
         let fund_fee = total_fees - symmetry_fee - host_fee - manager_fee;
 
         fund_worth = fund_worth - SymmetryTokenSwap::usd_value(
@@ -379,13 +376,13 @@ impl Amm for SymmetryTokenSwap {
             self.token_info.decimals[from_token_id as usize] as u64,
             from_token_price,
             SymmetryTokenSwap::AVG_PRICE,
-        );
+        ).context("Usd value overflows")?;
         fund_worth = fund_worth - SymmetryTokenSwap::usd_value(
             self.fund_state.current_comp_amount[to_token_index],
             self.token_info.decimals[to_token_id as usize] as u64,
             to_token_price,
             SymmetryTokenSwap::AVG_PRICE,
-        );
+        ).context("Usd value overflows")?;
 
         let max_from_token_change = from_amount * 101 / 100;
         let from_token_worth_after_swap: u64 = SymmetryTokenSwap::usd_value(
@@ -393,7 +390,7 @@ impl Amm for SymmetryTokenSwap {
             self.token_info.decimals[from_token_id as usize] as u64,
             from_token_price,
             SymmetryTokenSwap::AVG_PRICE,
-        );
+        ).context("Usd value overflows")?;
         let mut max_to_token_change = (amount_without_curve - fund_fee) * 101 / 100;
         if max_to_token_change > self.fund_state.current_comp_amount[to_token_index] {
             max_to_token_change = self.fund_state.current_comp_amount[to_token_index]
@@ -403,7 +400,7 @@ impl Amm for SymmetryTokenSwap {
             self.token_info.decimals[to_token_id as usize] as u64,
             to_token_price,
             SymmetryTokenSwap::AVG_PRICE,
-        );
+        ).context("Usd value overflows")?;
         fund_worth = fund_worth + from_token_worth_after_swap;
         fund_worth = fund_worth + to_token_worth_after_swap;
 
@@ -417,11 +414,11 @@ impl Amm for SymmetryTokenSwap {
             (allowed_from_target_weight) * (fund_worth as u128))
              && (from_token_id != 0 as u64)
               && (allowed_from_target_weight < 10000 as u128) {
-            return Ok(Quote {
+            return Ok((Quote {
                 not_enough_liquidity: true,
                 out_amount: 0,
                 ..Quote::default()
-            })
+            }, SymmetryFees::default()))
         }
 
         let allowed_to_target_weight =
@@ -430,31 +427,37 @@ impl Amm for SymmetryTokenSwap {
 
         if (to_token_worth_after_swap as u128) * (self.fund_state.weight_sum as u128) <
             (allowed_to_target_weight) * (fund_worth as u128) {
-                return Ok(Quote {
+                return Ok((Quote {
                     not_enough_liquidity: true,
                     out_amount: 0,
                     ..Quote::default()
-                })
+                }, SymmetryFees::default()))
         }
         
         let all_fees = total_fees + fee_due_nel;
         let zero_slippage_price = amount_without_curve - fund_fee + all_fees;
         
-        Ok(Quote {
+        let fee_pct = SymmetryTokenSwap::mul_div(all_fees, 1000000, zero_slippage_price).context("Fee overflows")?;
+        let quote = Quote {
             in_amount: quote_params.in_amount,
             out_amount: to_amount,
             fee_amount: all_fees,
             fee_mint: quote_params.output_mint,
-            price_impact_pct: Decimal::new(SymmetryTokenSwap::mul_div(all_fees, 1000000, zero_slippage_price) as i64, 4),
-            fee_pct: Decimal::new(SymmetryTokenSwap::mul_div(all_fees, 1000000, zero_slippage_price) as i64, 4),
+            price_impact_pct: Decimal::new(fee_pct as i64, 4),
+            fee_pct: Decimal::new(fee_pct as i64, 4),
             ..Quote::default()
-        })
+        };
+        let fees = SymmetryFees {
+            symmetry_fee,
+            host_fee,
+            manager_fee,
+            fund_fee,
+        };
+        Ok((quote, fees))
     }
 
-    fn get_swap_leg_and_account_metas(
-        &self,
-        swap_params: &SwapParams,
-    ) -> Result<SwapLegAndAccountMetas> {
+    /// Swap instruction of the Symmetry program, its accounts are the account metas of the swap leg
+    pub fn get_swap_instruction(&self, swap_params: &SwapParams) -> Result<Instruction> {
         let SwapParams {
             destination_mint,
             in_amount,
@@ -462,7 +465,7 @@ impl Amm for SymmetryTokenSwap {
             user_destination_token_account,
             user_source_token_account,
             user_transfer_authority,
-            open_order_address,
+            open_order_address: _,
             quote_mint_to_referrer,
         } = swap_params;
         
@@ -477,14 +480,7 @@ impl Amm for SymmetryTokenSwap {
             ], 
             &SymmetryTokenSwap::ASSOCIATED_TOKEN_PROGRAM_ADDRESS
         ).0;
-        let host_to_fee: Pubkey = Pubkey::find_program_address(
-            &[
-                &self.fund_state.host_pubkey.to_bytes(),
-                &SymmetryTokenSwap::SPL_TOKEN_PROGRAM_ADDRESS.to_bytes(),
-                &destination_mint.to_bytes()
-            ], 
-            &SymmetryTokenSwap::ASSOCIATED_TOKEN_PROGRAM_ADDRESS
-        ).0;
+        let host_to_fee: Pubkey = self.get_host_fee_account(destination_mint, quote_mint_to_referrer.as_ref());
         let manager_to_fee: Pubkey = Pubkey::find_program_address(
             &[
                 &self.fund_state.manager.to_bytes(),
@@ -525,23 +521,103 @@ impl Amm for SymmetryTokenSwap {
         data.extend_from_slice(&in_amount.to_le_bytes());
         data.extend_from_slice(&minimum_amount_out.to_le_bytes());
     
-        let swap_instruction = Instruction {
+        Ok(Instruction {
             program_id: SymmetryTokenSwap::SYMMETRY_PROGRAM_ADDRESS,
-            accounts: account_metas.clone(),
+            accounts: account_metas,
             data,
-        };
+        })
+    }
+}
+
+impl Amm for SymmetryTokenSwap {
+    fn label(&self) -> String {
+        String::from("Symmetry")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        let mut vec: Vec<Pubkey> = Vec::new();
+        for i in 0..self.fund_state.num_of_tokens as usize {
+            vec.push(self.token_info.token_mint[self.fund_state.current_comp_token[i] as usize])
+        }
+        return vec;
+    }
+
+    // The clock gives the slot the oracle prices are aged against
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut accounts_to_update: Vec<Pubkey> = Vec::new();
+        accounts_to_update.push(SymmetryTokenSwap::CURVE_DATA_ADDRESS);
+        accounts_to_update.push(self.key);
+        accounts_to_update.push(sysvar::clock::ID);
+        for i in 0..MAX_TOKENS_IN_ASSET_POOL {
+            if self.token_info.oracle[i] != Pubkey::default() {
+                accounts_to_update.push(self.token_info.oracle[i])
+            }
+        }
+        return accounts_to_update;
+    }
+
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        self.curve_data = CurveData::load(accounts_map.get(&SymmetryTokenSwap::CURVE_DATA_ADDRESS).unwrap());
+        self.fund_state = FundState::load(accounts_map.get(&self.key).unwrap());
+        for i in 0..50 {
+            if self.token_info.oracle[i] != Pubkey::default() {
+                self.token_info.oracle_price[i] = SimplePrice::load(accounts_map.get(&self.token_info.oracle[i]).unwrap());
+            }
+        }
+        let clock = accounts_map.get(&sysvar::clock::ID).context("Missing clock sysvar")?;
+        self.current_slot = u64::from_le_bytes(clock.get(..8).context("Invalid clock sysvar")?.try_into()?);
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let (quote, _) = self.quote_with_fees(quote_params)?;
+        Ok(quote)
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let swap_instruction = self.get_swap_instruction(swap_params)?;
 
         Ok(SwapLegAndAccountMetas {
             swap_leg: SwapLeg::Swap {
                 swap: Swap::TokenSwap,
             },
-            account_metas,
+            account_metas: swap_instruction.accounts,
         })
     }
 
     fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
         Box::new(self.clone())
     }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        let mut user_setup = get_user_token_account_setup(swap_params);
+
+        // Swap fee, host fee and manager fee are paid out in the destination token,
+        // the host fee to the referrer token account instead when there is one
+        let mut fee_owners = vec![SymmetryTokenSwap::SWAP_FEE_ADDRESS, self.fund_state.manager];
+        let has_referrer = matches!(
+            &swap_params.quote_mint_to_referrer,
+            Some(referrers) if referrers.contains_key(&swap_params.destination_mint)
+        );
+        if !has_referrer {
+            fee_owners.push(self.fund_state.host_pubkey);
+        }
+        for fee_owner in fee_owners {
+            user_setup.push(AmmUserSetup::CreateAssociatedTokenAccount {
+                owner: fee_owner,
+                mint: swap_params.destination_mint,
+            });
+        }
+        user_setup
+    }
 }
 
 #[test]
@@ -555,7 +631,7 @@ fn test_symetry_token_swap() {
     const TOKEN_INFO_ACCOUNT: Pubkey = SymmetryTokenSwap::TOKEN_INFO_ADDRESS;
     const FUND_STATE_ACCOUNT: Pubkey = pubkey!("Db86JGJnM58KtcZjqf8JFn3md98TDWJZLJJFBzkEWccZ");
 
-    let test_harness = AmmTestHarness::new();
+    let test_harness = AmmTestHarness::new_with_fixtures("synthetic_symmetry_sol_usdc");
     let fund_state_account = test_harness.get_keyed_account(FUND_STATE_ACCOUNT).unwrap();
     let token_info_account = test_harness.get_keyed_account(TOKEN_INFO_ACCOUNT).unwrap();
     let mut token_swap = SymmetryTokenSwap::from_keyed_account(&fund_state_account, &token_info_account).unwrap();
@@ -581,6 +657,8 @@ fn test_symetry_token_swap() {
         })
         .unwrap();
     println!("Quote result: {:?}", quote);
+    assert!(!quote.not_enough_liquidity);
+    assert_eq!(quote.out_amount, 1_998_514_202);
     
     /* Get swap leg and account metas */
     println!("------------");
@@ -601,14 +679,348 @@ fn test_symetry_token_swap() {
         ], 
         &SymmetryTokenSwap::ASSOCIATED_TOKEN_PROGRAM_ADDRESS
     ).0;
-    let _ = token_swap.get_swap_leg_and_account_metas(&SwapParams {
+    let referrer = Pubkey::new_unique();
+    let mut quote_mint_to_referrer = HashMap::new();
+    quote_mint_to_referrer.insert(to_token_mint, referrer);
+    let mut swap_params = SwapParams {
         source_mint: from_token_mint, 
         destination_mint: to_token_mint,
         user_source_token_account: user_source,
         user_destination_token_account: user_destination,
         user_transfer_authority: user,
         open_order_address: Option::None,
-        quote_mint_to_referrer: Option::None,
+        quote_mint_to_referrer: Some(quote_mint_to_referrer),
         in_amount: in_amount
-    });
+    };
+    let swap_leg_and_account_metas = token_swap.get_swap_leg_and_account_metas(&swap_params).unwrap();
+
+    /* Host fee goes to the referrer, which needs no host token account */
+    let host_token_account_setup = AmmUserSetup::CreateAssociatedTokenAccount {
+        owner: token_swap.fund_state.host_pubkey,
+        mint: to_token_mint,
+    };
+    assert_eq!(swap_leg_and_account_metas.account_metas[8].pubkey, referrer);
+    assert!(!token_swap.get_user_setup_steps(&swap_params).contains(&host_token_account_setup));
+
+    /* Without a referrer for the destination mint it goes to the fund host */
+    swap_params.quote_mint_to_referrer = Some(vec![(from_token_mint, referrer)].into_iter().collect());
+    let swap_leg_and_account_metas = token_swap.get_swap_leg_and_account_metas(&swap_params).unwrap();
+    assert_eq!(
+        swap_leg_and_account_metas.account_metas[8].pubkey,
+        token_swap.get_host_fee_account(&to_token_mint, None)
+    );
+    assert!(token_swap.get_user_setup_steps(&swap_params).contains(&host_token_account_setup));
+
+    /* An oracle 50 slots behind the clock is stale */
+    let sol_token_id = token_swap.token_info.token_mint.iter().position(|&x| x == SOL_TOKEN_MINT).unwrap();
+    let current_slot = token_swap.current_slot;
+    let sol_quote_params = QuoteParams {
+        input_mint: from_token_mint,
+        in_amount,
+        output_mint: to_token_mint,
+    };
+    let sol_price = token_swap.token_info.oracle_price[sol_token_id];
+    let mut stale_token_swap = token_swap.clone();
+    stale_token_swap.token_info.oracle_price[sol_token_id].slot = current_slot - 50;
+    assert!(stale_token_swap.quote(&sol_quote_params).is_err());
+    stale_token_swap.token_info.oracle_price[sol_token_id].slot = current_slot - 49;
+    assert!(stale_token_swap.quote(&sol_quote_params).is_ok());
+
+    /* Even when every oracle of the fund is equally stale */
+    let mut stale_token_swap = token_swap.clone();
+    stale_token_swap.current_slot = current_slot + 50;
+    assert!(stale_token_swap.quote(&sol_quote_params).is_err());
+
+    /* So is an oracle confidence over a tenth of the price */
+    let mut uncertain_token_swap = token_swap.clone();
+    uncertain_token_swap.token_info.oracle_price[sol_token_id].conf = sol_price.price as u128 / 10 + 1;
+    assert!(uncertain_token_swap.quote(&sol_quote_params).is_err());
+}
+
+#[test]
+fn test_mul_div() {
+    assert_eq!(SymmetryTokenSwap::mul_div(u64::MAX, 3, 4), Some(u64::MAX / 4 * 3 + 2));
+    assert_eq!(SymmetryTokenSwap::mul_div(u64::MAX, 2, 1), None);
+    assert_eq!(SymmetryTokenSwap::mul_div(1, 1, 0), Some(0));
+}
+
+/// Executes the Symmetry program binary, which is not checked in. CI dumps it to jupiter-core/tests/fixtures with
+/// `solana program dump 2KehYt3KsEQR53jYcxjbQp2d2kCp4AkuQW68atufRwSr symmetry.so`, elsewhere the test is skipped
+/// without it
+#[cfg(test)]
+#[tokio::test]
+async fn test_symmetry_quote_matches_execution() {
+    use crate::account_source::{AccountSource, FixtureAccountSource};
+    use crate::amms::program_test_harness::{add_mint, add_spl_token_program, add_token_account, ProgramTestHarness};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use solana_program_test::ProgramTest;
+    use solana_sdk::{signature::Keypair, signer::Signer};
+    use spl_associated_token_account::get_associated_token_address;
+
+    const FUND_STATE_ACCOUNT: Pubkey = pubkey!("Db86JGJnM58KtcZjqf8JFn3md98TDWJZLJJFBzkEWccZ");
+
+    let program_dir = std::env::var("BPF_OUT_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"));
+    if !program_dir.join("symmetry.so").exists() {
+        assert!(std::env::var("CI").is_err(), "CI must provide symmetry.so in {}", program_dir.display());
+        println!("Skipping, symmetry.so is not in {}", program_dir.display());
+        return;
+    }
+
+    let mut program_test = ProgramTest::new("symmetry", SymmetryTokenSwap::SYMMETRY_PROGRAM_ADDRESS, None);
+    add_spl_token_program(&mut program_test);
+
+    let fixtures = FixtureAccountSource::new(
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/synthetic_symmetry_sol_usdc"),
+    );
+    let keyed_account = |key: Pubkey| KeyedAccount {
+        key,
+        account: fixtures.get_account(&key).unwrap(),
+        params: None,
+    };
+    let mut amm = SymmetryTokenSwap::from_keyed_account(
+        &keyed_account(FUND_STATE_ACCOUNT),
+        &keyed_account(SymmetryTokenSwap::TOKEN_INFO_ADDRESS),
+    )
+    .unwrap();
+    program_test.add_account(FUND_STATE_ACCOUNT, fixtures.get_account(&FUND_STATE_ACCOUNT).unwrap());
+    program_test.add_account(
+        SymmetryTokenSwap::TOKEN_INFO_ADDRESS,
+        fixtures.get_account(&SymmetryTokenSwap::TOKEN_INFO_ADDRESS).unwrap(),
+    );
+    // The bank keeps its own clock, warped below to the slot of the fixture clock
+    for key in amm.get_accounts_to_update() {
+        if key != sysvar::clock::ID {
+            program_test.add_account(key, fixtures.get_account(&key).unwrap());
+        }
+    }
+
+    // Fund token accounts, the user and the fee accounts in every fund token
+    let user = Keypair::new();
+    let mut user_token_accounts = HashMap::new();
+    for i in 0..amm.fund_state.num_of_tokens as usize {
+        let token = amm.fund_state.current_comp_token[i] as usize;
+        let mint = amm.token_info.token_mint[token];
+        add_mint(&mut program_test, mint, Pubkey::new_unique(), u64::MAX, amm.token_info.decimals[token]);
+        add_token_account(
+            &mut program_test,
+            amm.token_info.pda_ta[token],
+            mint,
+            SymmetryTokenSwap::PDA_ADDRESS,
+            amm.fund_state.current_comp_amount[i],
+        );
+        let user_token_account = get_associated_token_address(&user.pubkey(), &mint);
+        add_token_account(&mut program_test, user_token_account, mint, user.pubkey(), u64::MAX / 2);
+        user_token_accounts.insert(mint, user_token_account);
+        for owner in [SymmetryTokenSwap::SWAP_FEE_ADDRESS, amm.fund_state.host_pubkey, amm.fund_state.manager] {
+            add_token_account(&mut program_test, get_associated_token_address(&owner, &mint), mint, owner, 0);
+        }
+    }
+
+    let mut harness = ProgramTestHarness::start(program_test).await;
+    let clock = fixtures.get_account(&sysvar::clock::ID).unwrap();
+    harness.context.warp_to_slot(u64::from_le_bytes(clock.data[..8].try_into().unwrap())).unwrap();
+    let mut rng = StdRng::seed_from_u64(42);
+    let mints = amm.get_reserve_mints();
+    for _ in 0..10 {
+        harness.update_amm(&mut amm).await.unwrap();
+        let source_mint = mints[rng.gen_range(0..mints.len())];
+        let destination_mint = *mints.iter().find(|mint| **mint != source_mint).unwrap();
+        let source_token = amm.token_info.token_mint.iter().position(|mint| *mint == source_mint).unwrap();
+        let in_amount = rng.gen_range(1..10u64.pow(amm.token_info.decimals[source_token] as u32 + 3));
+
+        let quote = amm
+            .quote(&QuoteParams {
+                in_amount,
+                input_mint: source_mint,
+                output_mint: destination_mint,
+            })
+            .unwrap();
+        if quote.not_enough_liquidity {
+            continue;
+        }
+        let instruction = amm
+            .get_swap_instruction(&SwapParams {
+                source_mint,
+                destination_mint,
+                user_source_token_account: user_token_accounts[&source_mint],
+                user_destination_token_account: user_token_accounts[&destination_mint],
+                user_transfer_authority: user.pubkey(),
+                open_order_address: None,
+                quote_mint_to_referrer: None,
+                in_amount,
+            })
+            .unwrap();
+
+        let user_destination = user_token_accounts[&destination_mint];
+        let balance_before = harness.get_token_balance(user_destination).await.unwrap();
+        harness.process_instruction(instruction, &[&user]).await.unwrap();
+        let balance_after = harness.get_token_balance(user_destination).await.unwrap();
+        assert!(balance_after - balance_before >= quote.out_amount);
+    }
+}
+
+#[cfg(test)]
+mod curve_strategies {
+    use super::*;
+    use proptest::{array::uniform10, prelude::*};
+
+    prop_compose! {
+        /// Pyth price of a token worth between 1e-6 and 1000 usd, with low <= price <= high
+        pub fn simple_price()(
+            expo in -10i32..=-6,
+            low in 1i64..=1_000_000_000,
+            spread in 0i64..=1_000_000,
+        ) -> SimplePrice {
+            let scale = i64::pow(10, (-expo - 6) as u32);
+            SimplePrice {
+                expo,
+                price: low * scale,
+                low: low * scale,
+                high: (low + spread) * scale,
+                conf: 0,
+                status: 1,
+                slot: 0,
+            }
+        }
+    }
+
+    prop_compose! {
+        /// Curve segments of at least one whole token each, priced up to 1000 usd
+        pub fn token_price_data(decimals: u8)(
+            amount in uniform10(u64::pow(10, decimals as u32)..=1_000_000_000_000),
+            price in uniform10(1u64..=1_000_000_000),
+        ) -> TokenPriceData {
+            TokenPriceData { amount, price }
+        }
+    }
+
+    prop_compose! {
+        pub fn curve()(decimals in 6u8..=9)(
+            decimals in Just(decimals),
+            pyth in simple_price(),
+            buy in token_price_data(decimals),
+            sell in token_price_data(decimals),
+            current_amount in 0u64..=100_000_000_000_000,
+            target_amount in 0u64..=100_000_000_000_000,
+        ) -> (u8, SimplePrice, TokenPriceData, TokenPriceData, u64, u64) {
+            (decimals, pyth, buy, sell, current_amount, target_amount)
+        }
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_usd_value_inverse(
+        amount in 0u64..=1_000_000_000_000_000,
+        usd_value in 0u64..=1_000_000_000_000_000,
+        decimals in 0u64..=9,
+        expo in -12i32..=-4,
+        price in 1i64..=1_000_000_000_000,
+        low_or_high in 0u8..=2,
+    ) {
+        let pyth = SimplePrice { expo, price, low: price, high: price, conf: 0, status: 1, slot: 0 };
+        let exact_usd_value = |amount: u64| {
+            amount as u128 * price as u128 * 1_000_000 / u128::pow(10, decimals as u32 + (-expo) as u32)
+        };
+
+        // Converting a value to an amount rounds down, by less than one unit of the token,
+        // and gives nothing rather than a truncated amount when it does not fit a u64
+        let exact_amount = usd_value as u128 * u128::pow(10, decimals as u32 + (-expo) as u32) / (price as u128 * 1_000_000);
+        let amount_for_value = SymmetryTokenSwap::amount_from_usd_value(usd_value, decimals, pyth, low_or_high);
+        proptest::prop_assert_eq!(amount_for_value, u64::try_from(exact_amount).ok());
+        if let Some(amount_for_value) = amount_for_value {
+            proptest::prop_assert!(SymmetryTokenSwap::usd_value(amount_for_value, decimals, pyth, low_or_high).unwrap() <= usd_value);
+            // One unit more is worth at least the value, or more than a u64 holds
+            let value_of_one_more = amount_for_value
+                .checked_add(1)
+                .and_then(|amount| SymmetryTokenSwap::usd_value(amount, decimals, pyth, low_or_high));
+            proptest::prop_assert!(!matches!(value_of_one_more, Some(value) if value < usd_value));
+        }
+
+        // The value of an amount is exact when it fits a u64, and the round trip never gives back more
+        let value = SymmetryTokenSwap::usd_value(amount, decimals, pyth, low_or_high);
+        proptest::prop_assert_eq!(value, u64::try_from(exact_usd_value(amount)).ok());
+        if let Some(value) = value {
+            proptest::prop_assert!(SymmetryTokenSwap::amount_from_usd_value(value, decimals, pyth, low_or_high).unwrap() <= amount);
+        }
+    }
+
+    #[test]
+    fn test_buying_output_is_monotonic(
+        (decimals, pyth, buy, _sell, current_amount, target_amount) in curve_strategies::curve(),
+        value in 0u64..=10_000_000_000_000_000,
+        extra_value in 0u64..=1_000_000_000_000,
+    ) {
+        let out_amount = |value| SymmetryTokenSwap::calculate_output_amount_for_buying_asset(
+            current_amount, target_amount, pyth, value, buy, decimals,
+        ).unwrap();
+        proptest::prop_assert!(out_amount(value) <= out_amount(value + extra_value));
+    }
+
+    #[test]
+    fn test_selling_value_is_monotonic(
+        (decimals, pyth, _buy, sell, current_amount, target_amount) in curve_strategies::curve(),
+        amount in 0u64..=100_000_000_000_000,
+        extra_amount in 0u64..=1_000_000_000_000,
+    ) {
+        let out_value = |amount| SymmetryTokenSwap::calculate_output_value_for_selling_asset(
+            current_amount, target_amount, pyth, amount, sell, decimals,
+        ).unwrap();
+        proptest::prop_assert!(out_value(amount) <= out_value(amount + extra_amount));
+    }
+
+    #[test]
+    fn test_sell_then_buy_creates_no_value(
+        (decimals, pyth, buy, sell, current_amount, target_amount) in curve_strategies::curve(),
+        amount in 0u64..=100_000_000_000_000,
+    ) {
+        let value = SymmetryTokenSwap::calculate_output_value_for_selling_asset(
+            current_amount, target_amount, pyth, amount, sell, decimals,
+        ).unwrap();
+        let bought_back = SymmetryTokenSwap::calculate_output_amount_for_buying_asset(
+            current_amount, target_amount, pyth, value, buy, decimals,
+        ).unwrap();
+        proptest::prop_assert!(bought_back <= amount);
+    }
+
+    #[test]
+    fn test_curve_segment_boundaries(
+        (decimals, pyth, buy, sell, target_amount, _) in curve_strategies::curve(),
+        segments in 0usize..=super::accounts::NUM_OF_POINTS_IN_CURVE_DATA,
+    ) {
+        // Starting on the target, filling whole segments is priced at each segment's running best price
+        let expo = u64::pow(10, decimals as u32);
+        let sell_pyth_price = SymmetryTokenSwap::usd_value(expo, decimals as u64, pyth, SymmetryTokenSwap::SELL_PRICE).unwrap();
+        let mut sell_price = sell_pyth_price;
+        let mut sell_amount = 0;
+        let mut sell_value = 0;
+        let buy_pyth_price = SymmetryTokenSwap::usd_value(expo, decimals as u64, pyth, SymmetryTokenSwap::BUY_PRICE).unwrap();
+        let mut buy_price = buy_pyth_price;
+        let mut buy_amount = 0;
+        let mut buy_value = 0;
+        for step in 0..segments {
+            sell_price = sell_price.min((sell.price[step] * 9 + sell_pyth_price) / 10);
+            sell_amount += sell.amount[step];
+            sell_value += SymmetryTokenSwap::mul_div(sell.amount[step], sell_price, expo).unwrap();
+            buy_price = buy_price.max((buy.price[step] * 9 + buy_pyth_price) / 10);
+            buy_amount += buy.amount[step];
+            buy_value += SymmetryTokenSwap::mul_div(buy.amount[step], buy_price, expo).unwrap();
+        }
+
+        proptest::prop_assert_eq!(
+            SymmetryTokenSwap::calculate_output_value_for_selling_asset(
+                target_amount, target_amount, pyth, sell_amount, sell, decimals,
+            ).unwrap(),
+            sell_value
+        );
+        proptest::prop_assert_eq!(
+            SymmetryTokenSwap::calculate_output_amount_for_buying_asset(
+                target_amount, target_amount, pyth, buy_value, buy, decimals,
+            ).unwrap(),
+            buy_amount
+        );
+    }
 }
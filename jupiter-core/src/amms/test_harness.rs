@@ -1,25 +1,43 @@
 use anyhow::Result;
 use solana_client::rpc_client::RpcClient;
-use solana_sdk::pubkey::Pubkey;
-use std::collections::HashMap;
+use solana_sdk::{account::Account, program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::{Account as TokenAccount, AccountState};
+use spl_token_swap::curve::base::{CurveType, SwapCurve};
+use spl_token_swap::curve::constant_product::ConstantProductCurve;
+use spl_token_swap::curve::fees::Fees;
+use spl_token_swap::state::{SwapV1, SwapVersion};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
+use crate::account_source::{AccountSource, FixtureAccountSource};
 use crate::config;
 
 use super::amm::{Amm, KeyedAccount};
+use super::spl_token_swap_amm::{SplTokenSwapAmm, SPL_TOKEN_SWAP_PROGRAMS};
 
 pub struct AmmTestHarness {
-    pub client: RpcClient,
+    pub account_source: Box<dyn AccountSource>,
 }
 
 impl AmmTestHarness {
+    /// Reads accounts from mainnet, to try an amm against the current state
+    #[allow(dead_code)]
     pub fn new() -> Self {
         Self {
-            client: RpcClient::new(config::RPC_URL),
+            account_source: Box::new(RpcClient::new(config::RPC_URL)),
+        }
+    }
+
+    /// Reads accounts from the checked in snapshot under jupiter-core/fixtures, no network needed
+    pub fn new_with_fixtures(fixture_name: &str) -> Self {
+        Self {
+            account_source: Box::new(FixtureAccountSource::new(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(fixture_name),
+            )),
         }
     }
 
     pub fn get_keyed_account(&self, key: Pubkey) -> Result<KeyedAccount> {
-        let account = self.client.get_account(&key)?;
+        let account = self.account_source.get_account(&key)?;
         Ok(KeyedAccount {
             key,
             account,
@@ -32,7 +50,7 @@ impl AmmTestHarness {
         println!("{:?}", accounts_to_update);
 
         let accounts_map = self
-            .client
+            .account_source
             .get_multiple_accounts(&accounts_to_update)
             .unwrap()
             .iter()
@@ -46,3 +64,90 @@ impl AmmTestHarness {
         amm.update(&accounts_map).unwrap();
     }
 }
+
+/// Data of an initialized token account holding the amount, for amm tests packing their own state
+pub fn token_account_data(mint: Pubkey, amount: u64) -> Vec<u8> {
+    let mut data = vec![0u8; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount {
+            mint,
+            owner: Pubkey::new_unique(),
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    data
+}
+
+/// Amm account owned by its program, as from_keyed_account gets it
+pub fn new_keyed_account(key: Pubkey, data: Vec<u8>, owner: Pubkey, params: Option<serde_json::Value>) -> KeyedAccount {
+    KeyedAccount {
+        key,
+        account: Account {
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+        params,
+    }
+}
+
+/// Constant product spl token swap pool built in memory, for tests that do not need mainnet state
+pub fn new_spl_token_swap_amm(
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> SplTokenSwapAmm {
+    let state = SwapV1 {
+        is_initialized: true,
+        bump_seed: 255,
+        token_program_id: spl_token::ID,
+        token_a: Pubkey::new_unique(),
+        token_b: Pubkey::new_unique(),
+        pool_mint: Pubkey::new_unique(),
+        token_a_mint,
+        token_b_mint,
+        pool_fee_account: Pubkey::new_unique(),
+        fees: Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        },
+        swap_curve: SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Arc::new(ConstantProductCurve),
+        },
+    };
+    // First byte is the version
+    let mut data = vec![1u8; SwapVersion::LATEST_LEN];
+    SwapV1::pack(state, &mut data[1..]).unwrap();
+    let keyed_account = new_keyed_account(
+        Pubkey::new_unique(),
+        data,
+        SPL_TOKEN_SWAP_PROGRAMS.keys().next().copied().unwrap(),
+        None,
+    );
+    let mut amm = SplTokenSwapAmm::from_keyed_account(&keyed_account).unwrap();
+
+    let accounts_to_update = amm.get_accounts_to_update();
+    let accounts_map = [(token_a_mint, reserve_a), (token_b_mint, reserve_b)]
+        .iter()
+        .zip(accounts_to_update.iter())
+        .fold(HashMap::new(), |mut m, ((mint, amount), key)| {
+            m.insert(*key, token_account_data(*mint, *amount));
+            m
+        });
+    amm.update(&accounts_map).unwrap();
+    amm
+}
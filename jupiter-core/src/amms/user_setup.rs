@@ -0,0 +1,226 @@
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anyhow::{Context, Result};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, system_instruction, system_program, sysvar};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use spl_token::native_mint;
+use std::collections::HashSet;
+
+use super::amm::{AmmUserSetup, SwapParams};
+use crate::constants::{OPENBOOK_PROGRAM_ID, SERUM_V3_PROGRAM_ID};
+
+/// Instructions to place around the swap instructions
+#[derive(Default)]
+pub struct UserSetupInstructions {
+    pub setup_instructions: Vec<Instruction>,
+    pub cleanup_instructions: Vec<Instruction>,
+}
+
+/// Open orders account Jupiter expects for the user on a serum/openbook market
+pub fn find_open_orders_address(market: &Pubkey, owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"open_orders", &market.to_bytes(), &owner.to_bytes()],
+        &jupiter::ID,
+    )
+    .0
+}
+
+/// Account Jupiter unwraps the user's wrapped SOL into for swaps taking native SOL
+pub fn find_temp_wsol_token_account_address(owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"temp-wsol-token-account", &owner.to_bytes()], &jupiter::ID).0
+}
+
+/// Setup shared by amms moving tokens between the user's associated token accounts
+pub fn get_user_token_account_setup(swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+    let owner = swap_params.user_transfer_authority;
+    let mut user_setup = Vec::new();
+
+    if swap_params.source_mint == native_mint::id()
+        && swap_params.user_source_token_account
+            == get_associated_token_address(&owner, &native_mint::id())
+    {
+        user_setup.push(AmmUserSetup::CreateAssociatedTokenAccount {
+            owner,
+            mint: native_mint::id(),
+        });
+        user_setup.push(AmmUserSetup::WrapSol {
+            owner,
+            amount: swap_params.in_amount,
+        });
+    }
+
+    if swap_params.user_destination_token_account
+        == get_associated_token_address(&owner, &swap_params.destination_mint)
+    {
+        user_setup.push(AmmUserSetup::CreateAssociatedTokenAccount {
+            owner,
+            mint: swap_params.destination_mint,
+        });
+        if swap_params.destination_mint == native_mint::id() {
+            user_setup.push(AmmUserSetup::UnwrapSol { owner });
+        }
+    }
+
+    user_setup
+}
+
+/// Turns the setup steps of one or many amms into instructions, the payer funds account creations
+/// and is the owner of open orders accounts.
+/// Account creations are deduplicated and come first, wraps of the same owner are merged.
+pub fn get_user_setup_instructions(
+    user_setup: &[AmmUserSetup],
+    payer: &Pubkey,
+) -> Result<UserSetupInstructions> {
+    let mut created_accounts: HashSet<Pubkey> = HashSet::new();
+    let mut closed_accounts: HashSet<Pubkey> = HashSet::new();
+    let mut wraps: Vec<(Pubkey, u64)> = Vec::new();
+    let mut instructions = UserSetupInstructions::default();
+
+    for step in user_setup {
+        match step {
+            AmmUserSetup::SerumDexOpenOrdersSetup { market } | AmmUserSetup::OpenbookOpenOrdersSetup { market } => {
+                let dex_program = if matches!(step, AmmUserSetup::OpenbookOpenOrdersSetup { .. }) {
+                    OPENBOOK_PROGRAM_ID
+                } else {
+                    SERUM_V3_PROGRAM_ID
+                };
+                let open_orders = find_open_orders_address(market, payer);
+                if !created_accounts.insert(open_orders) {
+                    continue;
+                }
+                instructions.setup_instructions.push(Instruction {
+                    program_id: jupiter::ID,
+                    accounts: jupiter::accounts::CreateOpenOrders {
+                        open_orders,
+                        payer: *payer,
+                        dex_program,
+                        system_program: system_program::ID,
+                        rent: sysvar::rent::ID,
+                        market: *market,
+                    }
+                    .to_account_metas(None),
+                    data: jupiter::instruction::CreateOpenOrders.data(),
+                });
+            }
+            AmmUserSetup::CreateAssociatedTokenAccount { owner, mint } => {
+                if !created_accounts.insert(get_associated_token_address(owner, mint)) {
+                    continue;
+                }
+                instructions
+                    .setup_instructions
+                    .push(create_associated_token_account_idempotent(
+                        payer,
+                        owner,
+                        mint,
+                        &spl_token::ID,
+                    ));
+            }
+            AmmUserSetup::WrapSol { owner, amount } => {
+                match wraps.iter_mut().find(|(wrap_owner, _)| wrap_owner == owner) {
+                    Some((_, total_amount)) => {
+                        *total_amount = total_amount.checked_add(*amount).context("Wrapped SOL amount overflows")?
+                    }
+                    None => wraps.push((*owner, *amount)),
+                }
+            }
+            AmmUserSetup::UnwrapSol { owner } => {
+                let wsol_account = get_associated_token_address(owner, &native_mint::id());
+                if !closed_accounts.insert(wsol_account) {
+                    continue;
+                }
+                instructions.cleanup_instructions.push(
+                    spl_token::instruction::close_account(
+                        &spl_token::ID,
+                        &wsol_account,
+                        owner,
+                        owner,
+                        &[],
+                    )?,
+                );
+            }
+        }
+    }
+
+    // Wrapping has to come after the wrapped SOL account creation
+    for (owner, amount) in wraps {
+        let wsol_account = get_associated_token_address(&owner, &native_mint::id());
+        instructions
+            .setup_instructions
+            .push(system_instruction::transfer(&owner, &wsol_account, amount));
+        instructions
+            .setup_instructions
+            .push(spl_token::instruction::sync_native(&spl_token::ID, &wsol_account)?);
+    }
+
+    Ok(instructions)
+}
+
+#[test]
+fn test_get_user_setup_instructions() {
+    use crate::constants::USDC_MINT;
+
+    let user = Pubkey::new_unique();
+    let swap_params = SwapParams {
+        source_mint: native_mint::id(),
+        destination_mint: USDC_MINT,
+        user_source_token_account: get_associated_token_address(&user, &native_mint::id()),
+        user_destination_token_account: get_associated_token_address(&user, &USDC_MINT),
+        user_transfer_authority: user,
+        open_order_address: None,
+        quote_mint_to_referrer: None,
+        in_amount: 1_000_000_000,
+    };
+
+    let mut user_setup = get_user_token_account_setup(&swap_params);
+    assert_eq!(
+        user_setup,
+        vec![
+            AmmUserSetup::CreateAssociatedTokenAccount {
+                owner: user,
+                mint: native_mint::id()
+            },
+            AmmUserSetup::WrapSol {
+                owner: user,
+                amount: 1_000_000_000
+            },
+            AmmUserSetup::CreateAssociatedTokenAccount {
+                owner: user,
+                mint: USDC_MINT
+            },
+        ]
+    );
+
+    // A second leg out of the same accounts only adds to the wrapped amount
+    user_setup.extend(get_user_token_account_setup(&swap_params));
+    let instructions = get_user_setup_instructions(&user_setup, &user).unwrap();
+
+    // 2 account creations, transfer and sync native
+    assert_eq!(instructions.setup_instructions.len(), 4);
+    assert!(instructions.cleanup_instructions.is_empty());
+    let transfer_data = &instructions.setup_instructions[2].data;
+    assert_eq!(transfer_data[4..12], 2_000_000_000u64.to_le_bytes());
+
+    // Wraps adding up past a u64 fail instead of wrapping around
+    let wrap = AmmUserSetup::WrapSol {
+        owner: user,
+        amount: u64::MAX,
+    };
+    assert!(get_user_setup_instructions(&[wrap.clone(), wrap], &user).is_err());
+
+    // Open orders are created under the dex program of their market
+    let serum_market = Pubkey::new_unique();
+    let openbook_market = Pubkey::new_unique();
+    let instructions = get_user_setup_instructions(
+        &[
+            AmmUserSetup::SerumDexOpenOrdersSetup { market: serum_market },
+            AmmUserSetup::OpenbookOpenOrdersSetup { market: openbook_market },
+        ],
+        &user,
+    )
+    .unwrap();
+    assert_eq!(instructions.setup_instructions.len(), 2);
+    assert_eq!(instructions.setup_instructions[0].accounts[0].pubkey, find_open_orders_address(&serum_market, &user));
+    assert_eq!(instructions.setup_instructions[0].accounts[2].pubkey, SERUM_V3_PROGRAM_ID);
+    assert_eq!(instructions.setup_instructions[1].accounts[2].pubkey, OPENBOOK_PROGRAM_ID);
+}
@@ -0,0 +1,587 @@
+use anchor_lang::{solana_program::hash::hash, InstructionData, ToAccountMetas};
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use std::{collections::HashMap, convert::TryInto};
+
+use crate::amms::amm::{Amm, KeyedAccount};
+use solana_sdk::{instruction::Instruction, pubkey, pubkey::Pubkey};
+
+use super::amm::{AmmUserSetup, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use super::clmm::{self, TickRange, FEE_RATE_DENOMINATOR, MAX_TICK_INDEX, MIN_TICK_INDEX};
+use super::user_setup::get_user_token_account_setup;
+use crate::router::slippage::get_amount_with_slippage;
+use jupiter::{
+    accounts::{WhirlpoolSwap, WhirlpoolSwapExactOutput},
+    jupiter_override::{Swap, SwapLeg},
+};
+
+pub const WHIRLPOOL_PROGRAM_ID: Pubkey = pubkey!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+
+pub const TICK_ARRAY_SIZE: i32 = 88;
+/// Tick arrays a swap can cross, the swap instruction takes three
+const MAX_TICK_ARRAYS: usize = 3;
+
+fn anchor_discriminator(account_name: &str) -> [u8; 8] {
+    hash(format!("account:{}", account_name).as_bytes()).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+}
+
+/// The parts of the Whirlpool state needed to quote and build swaps
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Whirlpool {
+    pub tick_spacing: u16,
+    /// In hundredths of a bip
+    pub fee_rate: u16,
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub token_mint_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub token_mint_b: Pubkey,
+    pub token_vault_b: Pubkey,
+}
+
+impl Whirlpool {
+    pub const LEN: usize = 653;
+
+    pub fn load(account_data: &[u8]) -> Result<Self> {
+        if account_data.len() != Self::LEN || account_data[..8] != anchor_discriminator("Whirlpool") {
+            return Err(anyhow!("Invalid Whirlpool account"));
+        }
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(account_data[offset..offset + 32].try_into().unwrap());
+        let u128_at = |offset: usize| u128::from_le_bytes(account_data[offset..offset + 16].try_into().unwrap());
+        Ok(Self {
+            tick_spacing: u16::from_le_bytes(account_data[41..43].try_into()?),
+            fee_rate: u16::from_le_bytes(account_data[45..47].try_into()?),
+            liquidity: u128_at(49),
+            sqrt_price: u128_at(65),
+            tick_current_index: i32::from_le_bytes(account_data[81..85].try_into()?),
+            token_mint_a: pubkey_at(101),
+            token_vault_a: pubkey_at(133),
+            token_mint_b: pubkey_at(181),
+            token_vault_b: pubkey_at(213),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[..8].copy_from_slice(&anchor_discriminator("Whirlpool"));
+        data[41..43].copy_from_slice(&self.tick_spacing.to_le_bytes());
+        data[45..47].copy_from_slice(&self.fee_rate.to_le_bytes());
+        data[49..65].copy_from_slice(&self.liquidity.to_le_bytes());
+        data[65..81].copy_from_slice(&self.sqrt_price.to_le_bytes());
+        data[81..85].copy_from_slice(&self.tick_current_index.to_le_bytes());
+        for (offset, pubkey) in [
+            (101, self.token_mint_a),
+            (133, self.token_vault_a),
+            (181, self.token_mint_b),
+            (213, self.token_vault_b),
+        ] {
+            data[offset..offset + 32].copy_from_slice(pubkey.as_ref());
+        }
+        data
+    }
+
+    fn ticks_in_array(&self) -> i32 {
+        self.tick_spacing as i32 * TICK_ARRAY_SIZE
+    }
+
+    /// Start tick indexes of the tick arrays a swap in the direction goes through, the first one
+    /// holding the current tick. Swaps from b to a start in the next array on its last tick.
+    pub fn tick_array_start_indexes(&self, a_to_b: bool) -> Vec<i32> {
+        let ticks_in_array = self.ticks_in_array();
+        let shift = if a_to_b { 0 } else { self.tick_spacing as i32 };
+        let start_tick_index = (self.tick_current_index + shift).div_euclid(ticks_in_array) * ticks_in_array;
+        (0..MAX_TICK_ARRAYS as i32)
+            .map(|offset| {
+                if a_to_b {
+                    start_tick_index - offset * ticks_in_array
+                } else {
+                    start_tick_index + offset * ticks_in_array
+                }
+            })
+            .filter(|start_tick_index| {
+                *start_tick_index + ticks_in_array > MIN_TICK_INDEX && *start_tick_index <= MAX_TICK_INDEX
+            })
+            .collect()
+    }
+}
+
+/// Initialized ticks of a tick array with their liquidity net
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickArray {
+    pub start_tick_index: i32,
+    pub ticks: Vec<(i32, i128)>,
+    pub whirlpool: Pubkey,
+}
+
+impl TickArray {
+    pub const LEN: usize = 9988;
+    const TICK_LEN: usize = 113;
+
+    /// Tick indexes are only known from the tick spacing of the whirlpool
+    pub fn load(account_data: &[u8], tick_spacing: u16) -> Result<Self> {
+        if account_data.len() != Self::LEN || account_data[..8] != anchor_discriminator("TickArray") {
+            return Err(anyhow!("Invalid TickArray account"));
+        }
+        let start_tick_index = i32::from_le_bytes(account_data[8..12].try_into()?);
+        let ticks = account_data[12..12 + TICK_ARRAY_SIZE as usize * Self::TICK_LEN]
+            .chunks(Self::TICK_LEN)
+            .enumerate()
+            .filter(|(_, tick)| tick[0] != 0)
+            .map(|(i, tick)| {
+                (
+                    start_tick_index + i as i32 * tick_spacing as i32,
+                    i128::from_le_bytes(tick[1..17].try_into().unwrap()),
+                )
+            })
+            .collect();
+        Ok(Self {
+            start_tick_index,
+            ticks,
+            whirlpool: Pubkey::new_from_array(account_data[Self::LEN - 32..].try_into()?),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn pack(&self, tick_spacing: u16) -> Vec<u8> {
+        let mut data = vec![0u8; Self::LEN];
+        data[..8].copy_from_slice(&anchor_discriminator("TickArray"));
+        data[8..12].copy_from_slice(&self.start_tick_index.to_le_bytes());
+        for (tick_index, liquidity_net) in &self.ticks {
+            let offset = 12 + ((tick_index - self.start_tick_index) / tick_spacing as i32) as usize * Self::TICK_LEN;
+            data[offset] = 1;
+            data[offset + 1..offset + 17].copy_from_slice(&liquidity_net.to_le_bytes());
+            data[offset + 17..offset + 33].copy_from_slice(&liquidity_net.unsigned_abs().to_le_bytes());
+        }
+        data[Self::LEN - 32..].copy_from_slice(self.whirlpool.as_ref());
+        data
+    }
+}
+
+pub fn get_tick_array_address(whirlpool: &Pubkey, start_tick_index: i32, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"tick_array", whirlpool.as_ref(), start_tick_index.to_string().as_bytes()],
+        program_id,
+    )
+    .0
+}
+
+pub fn get_oracle_address(whirlpool: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle", whirlpool.as_ref()], program_id).0
+}
+
+/// Orca Whirlpool concentrated liquidity pool, quoting across the initialized ticks of the tick arrays
+/// around the current price. The tick arrays change as the price moves, so the accounts are dynamic.
+pub struct WhirlpoolAmm {
+    key: Pubkey,
+    state: Whirlpool,
+    /// Tick array addresses in the a to b and b to a directions, from the current tick
+    tick_array_keys: [Vec<Pubkey>; 2],
+    /// Ticks of the loaded contiguous tick arrays in each direction
+    tick_ranges: [TickRange; 2],
+    oracle: Pubkey,
+    program_id: Pubkey,
+}
+
+impl WhirlpoolAmm {
+    pub fn from_keyed_account(keyed_account: &KeyedAccount) -> Result<Self> {
+        let program_id = keyed_account.account.owner;
+        let mut amm = Self {
+            key: keyed_account.key,
+            state: Whirlpool::load(&keyed_account.account.data)?,
+            tick_array_keys: Default::default(),
+            tick_ranges: Default::default(),
+            oracle: get_oracle_address(&keyed_account.key, &program_id),
+            program_id,
+        };
+        amm.tick_array_keys = amm.get_tick_array_keys();
+        Ok(amm)
+    }
+
+    fn get_tick_array_keys(&self) -> [Vec<Pubkey>; 2] {
+        let keys = |a_to_b: bool| {
+            self.state
+                .tick_array_start_indexes(a_to_b)
+                .into_iter()
+                .map(|start_tick_index| get_tick_array_address(&self.key, start_tick_index, &self.program_id))
+                .collect()
+        };
+        [keys(true), keys(false)]
+    }
+
+    fn direction(a_to_b: bool) -> usize {
+        if a_to_b {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn is_a_to_b(&self, input_mint: &Pubkey) -> bool {
+        *input_mint == self.state.token_mint_a
+    }
+
+    /// Ticks of the tick arrays in the direction up to the first one missing from the accounts
+    fn load_tick_range(&self, accounts_map: &HashMap<Pubkey, Vec<u8>>, a_to_b: bool) -> Result<TickRange> {
+        let ticks_in_array = self.state.ticks_in_array();
+        let start_tick_indexes = self.state.tick_array_start_indexes(a_to_b);
+        // The range grows from the edge of the first tick array in the swap direction
+        let first_edge = if a_to_b {
+            start_tick_indexes[0] + ticks_in_array
+        } else {
+            start_tick_indexes[0]
+        };
+        let mut tick_range = TickRange {
+            start_tick_index: first_edge,
+            end_tick_index: first_edge,
+            ..TickRange::default()
+        };
+        for (start_tick_index, tick_array_key) in start_tick_indexes
+            .into_iter()
+            .zip(&self.tick_array_keys[Self::direction(a_to_b)])
+        {
+            let tick_array = match accounts_map.get(tick_array_key) {
+                Some(account_data) => TickArray::load(account_data, self.state.tick_spacing)?,
+                None => break,
+            };
+            if tick_array.whirlpool != self.key || tick_array.start_tick_index != start_tick_index {
+                return Err(anyhow!("Tick array {} does not belong to whirlpool {}", tick_array_key, self.key));
+            }
+            tick_range.ticks.extend(tick_array.ticks);
+            if a_to_b {
+                tick_range.start_tick_index = start_tick_index;
+            } else {
+                tick_range.end_tick_index = start_tick_index + ticks_in_array;
+            }
+        }
+        Ok(tick_range)
+    }
+
+    fn swap(&self, input_mint: &Pubkey, amount: u64, amount_specified_is_input: bool) -> Result<Quote> {
+        let a_to_b = self.is_a_to_b(input_mint);
+        let result = clmm::swap(
+            self.state.liquidity,
+            self.state.sqrt_price,
+            self.state.tick_current_index,
+            self.state.fee_rate as u32,
+            &self.tick_ranges[Self::direction(a_to_b)],
+            amount,
+            amount_specified_is_input,
+            a_to_b,
+        )
+        .with_context(|| format!("Swap overflows in whirlpool {}", self.key))?;
+
+        Ok(Quote {
+            not_enough_liquidity: !result.is_complete,
+            in_amount: result.amount_in,
+            out_amount: result.amount_out,
+            fee_amount: result.fee_amount,
+            fee_mint: *input_mint,
+            fee_pct: Decimal::from(self.state.fee_rate) / Decimal::from(FEE_RATE_DENOMINATOR),
+            ..Quote::default()
+        })
+    }
+
+    /// Quote for receiving exactly the out amount, the in amount being what it takes including fees
+    pub fn quote_exact_out(&self, input_mint: &Pubkey, out_amount: u64) -> Result<Quote> {
+        self.swap(input_mint, out_amount, false)
+    }
+
+    /// Jupiter instruction receiving exactly the out amount of an exact out quote, spending at most
+    /// the in amount of the swap params plus the slippage
+    pub fn get_swap_exact_out_instruction(
+        &self,
+        swap_params: &SwapParams,
+        out_amount: u64,
+        slippage_bps: u16,
+        platform_fee_bps: u8,
+    ) -> Result<Instruction> {
+        let (a_to_b, accounts) = self.swap_accounts(swap_params)?;
+        Ok(Instruction {
+            program_id: jupiter::ID,
+            accounts: WhirlpoolSwapExactOutput {
+                swap_program: accounts.swap_program,
+                token_program: accounts.token_program,
+                token_authority: accounts.token_authority,
+                whirlpool: accounts.whirlpool,
+                token_owner_account_a: accounts.token_owner_account_a,
+                token_vault_a: accounts.token_vault_a,
+                token_owner_account_b: accounts.token_owner_account_b,
+                token_vault_b: accounts.token_vault_b,
+                tick_array0: accounts.tick_array0,
+                tick_array1: accounts.tick_array1,
+                tick_array2: accounts.tick_array2,
+                oracle: accounts.oracle,
+            }
+            .to_account_metas(None),
+            data: jupiter::instruction::WhirlpoolSwapExactOutput {
+                _out_amount: out_amount,
+                _in_amount_with_slippage: get_amount_with_slippage(swap_params.in_amount, slippage_bps)?,
+                _a_to_b: a_to_b,
+                _platform_fee_bps: platform_fee_bps,
+            }
+            .data(),
+        })
+    }
+
+    /// Accounts of a swap in the direction of the source mint
+    fn swap_accounts(&self, swap_params: &SwapParams) -> Result<(bool, WhirlpoolSwap)> {
+        let SwapParams {
+            source_mint,
+            user_destination_token_account,
+            user_source_token_account,
+            user_transfer_authority,
+            ..
+        } = swap_params;
+
+        let a_to_b = self.is_a_to_b(source_mint);
+        let (token_owner_account_a, token_owner_account_b) = if a_to_b {
+            (*user_source_token_account, *user_destination_token_account)
+        } else {
+            (*user_destination_token_account, *user_source_token_account)
+        };
+        // Tick arrays past the bounds of the ticks repeat the last one, the swap never gets there
+        let tick_array_keys = &self.tick_array_keys[Self::direction(a_to_b)];
+        let tick_array = |i: usize| tick_array_keys.get(i).or_else(|| tick_array_keys.last()).copied();
+        let tick_array_0 = tick_array(0).with_context(|| format!("No tick arrays for whirlpool {}", self.key))?;
+
+        Ok((
+            a_to_b,
+            WhirlpoolSwap {
+                swap_program: self.program_id,
+                token_program: spl_token::ID,
+                token_authority: *user_transfer_authority,
+                whirlpool: self.key,
+                token_owner_account_a,
+                token_vault_a: self.state.token_vault_a,
+                token_owner_account_b,
+                token_vault_b: self.state.token_vault_b,
+                tick_array0: tick_array_0,
+                tick_array1: tick_array(1).unwrap_or(tick_array_0),
+                tick_array2: tick_array(2).unwrap_or(tick_array_0),
+                oracle: self.oracle,
+            },
+        ))
+    }
+
+    fn clone(&self) -> WhirlpoolAmm {
+        WhirlpoolAmm {
+            key: self.key,
+            state: self.state,
+            tick_array_keys: self.tick_array_keys.clone(),
+            tick_ranges: self.tick_ranges.clone(),
+            oracle: self.oracle,
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl Amm for WhirlpoolAmm {
+    fn label(&self) -> String {
+        String::from("Orca (Whirlpools)")
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.state.token_mint_a, self.state.token_mint_b]
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.key];
+        for tick_array_key in self.tick_array_keys.iter().flatten() {
+            if !accounts.contains(tick_array_key) {
+                accounts.push(*tick_array_key);
+            }
+        }
+        accounts
+    }
+
+    /// Tick arrays missing from the accounts, after the price moved to other ones, limit the quotes
+    /// until the next update
+    fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let whirlpool = accounts_map
+            .get(&self.key)
+            .with_context(|| format!("Missing whirlpool {}", self.key))?;
+        self.state = Whirlpool::load(whirlpool)?;
+        self.tick_array_keys = self.get_tick_array_keys();
+        self.tick_ranges = [
+            self.load_tick_range(accounts_map, true)?,
+            self.load_tick_range(accounts_map, false)?,
+        ];
+
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        self.swap(&quote_params.input_mint, quote_params.in_amount, true)
+    }
+
+    fn get_swap_leg_and_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let (a_to_b, accounts) = self.swap_accounts(swap_params)?;
+        let account_metas = accounts.to_account_metas(None);
+
+        Ok(SwapLegAndAccountMetas {
+            swap_leg: SwapLeg::Swap {
+                swap: Swap::Whirlpool { a_to_b },
+            },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn has_dynamic_accounts(&self) -> bool {
+        true
+    }
+
+    fn get_user_setup_steps(&self, swap_params: &SwapParams) -> Vec<AmmUserSetup> {
+        get_user_token_account_setup(swap_params)
+    }
+}
+
+#[test]
+fn test_whirlpool_amm() {
+    use crate::amms::test_harness::new_keyed_account;
+    use crate::constants::USDC_MINT;
+    use anchor_lang::{AnchorDeserialize, Discriminator};
+
+    let key = Pubkey::new_unique();
+    let usdt_mint = Pubkey::new_unique();
+    // Price 1 with liquidity between ticks -64 and 64, and more of it between -8 and 8
+    let state = Whirlpool {
+        tick_spacing: 8,
+        fee_rate: 3_000,
+        liquidity: 10_000_000_000_000,
+        sqrt_price: 1 << 64,
+        tick_current_index: 0,
+        token_mint_a: USDC_MINT,
+        token_vault_a: Pubkey::new_unique(),
+        token_mint_b: usdt_mint,
+        token_vault_b: Pubkey::new_unique(),
+    };
+    let data = state.pack();
+    assert_eq!(Whirlpool::load(&data).unwrap(), state);
+    assert_eq!(state.tick_array_start_indexes(true), vec![0, -704, -1408]);
+    assert_eq!(state.tick_array_start_indexes(false), vec![0, 704, 1408]);
+
+    let mut amm =
+        WhirlpoolAmm::from_keyed_account(&new_keyed_account(key, data.clone(), WHIRLPOOL_PROGRAM_ID, None)).unwrap();
+    assert!(amm.has_dynamic_accounts());
+    // The first tick array is shared by both directions
+    assert_eq!(amm.get_accounts_to_update().len(), 6);
+
+    let tick_array = |start_tick_index: i32, ticks: Vec<(i32, i128)>| {
+        let tick_array = TickArray {
+            start_tick_index,
+            ticks,
+            whirlpool: key,
+        };
+        let data = tick_array.pack(state.tick_spacing);
+        assert_eq!(TickArray::load(&data, state.tick_spacing).unwrap(), tick_array);
+        (get_tick_array_address(&key, start_tick_index, &WHIRLPOOL_PROGRAM_ID), data)
+    };
+    let accounts_map: HashMap<Pubkey, Vec<u8>> = vec![
+        (key, data),
+        tick_array(-704, vec![(-64, 1_000_000_000_000), (-8, 9_000_000_000_000)]),
+        tick_array(0, vec![(8, -9_000_000_000_000), (64, -1_000_000_000_000)]),
+        tick_array(-1408, vec![]),
+    ]
+    .into_iter()
+    .collect();
+    amm.update(&accounts_map).unwrap();
+
+    let quote = amm
+        .quote(&QuoteParams {
+            in_amount: 1_000_000,
+            input_mint: USDC_MINT,
+            output_mint: usdt_mint,
+        })
+        .unwrap();
+    assert_eq!(quote.fee_amount, 3_000);
+    assert_eq!(quote.out_amount, 996_999);
+    assert!(!quote.not_enough_liquidity);
+
+    let exact_out_quote = amm.quote_exact_out(&USDC_MINT, 996_999).unwrap();
+    assert_eq!(exact_out_quote.out_amount, 996_999);
+    assert!(exact_out_quote.in_amount <= 1_000_000);
+
+    // The exact out instruction carries the quote and the same accounts as the swap leg
+    let exact_out_swap_params = SwapParams {
+        source_mint: USDC_MINT,
+        destination_mint: usdt_mint,
+        user_source_token_account: Pubkey::new_unique(),
+        user_destination_token_account: Pubkey::new_unique(),
+        user_transfer_authority: Pubkey::new_unique(),
+        open_order_address: None,
+        quote_mint_to_referrer: None,
+        in_amount: exact_out_quote.in_amount,
+    };
+    let instruction = amm
+        .get_swap_exact_out_instruction(&exact_out_swap_params, exact_out_quote.out_amount, 50, 0)
+        .unwrap();
+    assert_eq!(instruction.program_id, jupiter::ID);
+    assert_eq!(
+        instruction.accounts,
+        amm.get_swap_leg_and_account_metas(&exact_out_swap_params).unwrap().account_metas
+    );
+    assert_eq!(instruction.data[..8], jupiter::instruction::WhirlpoolSwapExactOutput::discriminator());
+    let data = jupiter::instruction::WhirlpoolSwapExactOutput::deserialize(&mut &instruction.data[8..]).unwrap();
+    assert_eq!(data._out_amount, 996_999);
+    assert_eq!(data._in_amount_with_slippage.amount, exact_out_quote.in_amount);
+    assert_eq!(data._in_amount_with_slippage.slippage_bps, 50);
+    assert!(data._a_to_b);
+    assert!(amm
+        .get_swap_exact_out_instruction(&exact_out_swap_params, exact_out_quote.out_amount, 10_001, 0)
+        .is_err());
+
+    // Past tick 64 there is no liquidity left
+    assert!(
+        amm.quote(&QuoteParams {
+            in_amount: 10_000_000_000,
+            input_mint: usdt_mint,
+            output_mint: USDC_MINT,
+        })
+        .unwrap()
+        .not_enough_liquidity
+    );
+
+    let (user_usdc, user_usdt) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let swap_leg_and_account_metas = amm
+        .get_swap_leg_and_account_metas(&SwapParams {
+            source_mint: usdt_mint,
+            destination_mint: USDC_MINT,
+            user_source_token_account: user_usdt,
+            user_destination_token_account: user_usdc,
+            user_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            in_amount: 1_000_000,
+        })
+        .unwrap();
+    assert!(matches!(
+        swap_leg_and_account_metas.swap_leg,
+        SwapLeg::Swap {
+            swap: Swap::Whirlpool { a_to_b: false }
+        }
+    ));
+    let account_metas = swap_leg_and_account_metas.account_metas;
+    assert_eq!(account_metas.len(), 12);
+    assert_eq!(account_metas[4].pubkey, user_usdc);
+    assert_eq!(account_metas[6].pubkey, user_usdt);
+    assert_eq!(
+        account_metas[9].pubkey,
+        get_tick_array_address(&key, 704, &WHIRLPOOL_PROGRAM_ID)
+    );
+    assert_eq!(account_metas[11].pubkey, get_oracle_address(&key, &WHIRLPOOL_PROGRAM_ID));
+}
@@ -1,6 +1,12 @@
 mod amms;
 
 pub use amms::amm;
+pub use amms::user_setup;
+pub use amms::{lifinity_amm, marinade_amm, mercurial_amm, openbook_amm, raydium_amm, raydium_clmm_amm, saber_add_decimals_amm, saber_amm, spl_token_swap_amm, step_token_swap_amm, symmetry_token_swap, whirlpool_amm};
 
+pub mod account_source;
 pub mod config;
 pub mod constants;
+mod math;
+pub mod recorder;
+pub mod router;
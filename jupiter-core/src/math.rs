@@ -0,0 +1,20 @@
+/// Division rounding up, None when dividing by zero
+pub fn checked_ceil_div(numerator: u128, denominator: u128) -> Option<u128> {
+    let quotient = numerator.checked_div(denominator)?;
+    let remainder = numerator.checked_rem(denominator)?;
+    if remainder == 0 {
+        Some(quotient)
+    } else {
+        quotient.checked_add(1)
+    }
+}
+
+#[test]
+fn test_checked_ceil_div() {
+    assert_eq!(checked_ceil_div(10, 5), Some(2));
+    assert_eq!(checked_ceil_div(11, 5), Some(3));
+    assert_eq!(checked_ceil_div(0, 5), Some(0));
+    assert_eq!(checked_ceil_div(1, 1_000), Some(1));
+    assert_eq!(checked_ceil_div(u128::MAX, 1), Some(u128::MAX));
+    assert_eq!(checked_ceil_div(1, 0), None);
+}
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::account_source::{AccountSource, FixtureAccountSource};
+use crate::amms::amm::{Amm, KeyedAccount};
+
+/// Rounds of update after which an amm still asking for new accounts is given up on
+const MAX_UPDATE_ROUNDS: usize = 10;
+
+/// Copies accounts from a source, usually an rpc, into a fixture directory read by the offline test harness
+pub struct AccountRecorder<S: AccountSource> {
+    account_source: S,
+    fixture_account_source: FixtureAccountSource,
+    recorded_keys: HashSet<Pubkey>,
+}
+
+impl<S: AccountSource> AccountRecorder<S> {
+    pub fn new(account_source: S, directory: impl Into<PathBuf>) -> Self {
+        Self {
+            account_source,
+            fixture_account_source: FixtureAccountSource::new(directory),
+            recorded_keys: HashSet::new(),
+        }
+    }
+
+    pub fn recorded_keys(&self) -> Vec<Pubkey> {
+        let mut recorded_keys: Vec<Pubkey> = self.recorded_keys.iter().copied().collect();
+        recorded_keys.sort();
+        recorded_keys
+    }
+
+    /// Records the accounts an amm is built from, such as a Symmetry fund state and its token info
+    pub fn record_keyed_accounts(&mut self, keys: &[Pubkey]) -> Result<Vec<KeyedAccount>> {
+        let (slot, accounts) = self.account_source.get_multiple_accounts_with_slot(keys)?;
+        let mut keyed_accounts = Vec::new();
+        for (key, account) in keys.iter().zip(accounts) {
+            let account = account.ok_or_else(|| anyhow!("Account {} not found", key))?;
+            self.fixture_account_source.save_account(key, &account, Some(slot))?;
+            self.recorded_keys.insert(*key);
+            keyed_accounts.push(KeyedAccount {
+                key: *key,
+                account,
+                params: None,
+            });
+        }
+        Ok(keyed_accounts)
+    }
+
+    /// Records the accounts to update of the amm, updating it with them until it stops asking for
+    /// accounts not yet recorded. Missing accounts are skipped, as the amm update would.
+    pub fn record_amm(&mut self, amm: &mut dyn Amm) -> Result<()> {
+        let mut accounts_map: HashMap<Pubkey, Vec<u8>> = HashMap::new();
+        for _ in 0..MAX_UPDATE_ROUNDS {
+            let keys: Vec<Pubkey> = amm
+                .get_accounts_to_update()
+                .into_iter()
+                .filter(|key| !accounts_map.contains_key(key))
+                .collect();
+            if keys.is_empty() {
+                return Ok(());
+            }
+
+            let (slot, accounts) = self.account_source.get_multiple_accounts_with_slot(&keys)?;
+            let mut recorded_any = false;
+            for (key, account) in keys.iter().zip(accounts) {
+                if let Some(account) = account {
+                    self.fixture_account_source.save_account(key, &account, Some(slot))?;
+                    self.recorded_keys.insert(*key);
+                    accounts_map.insert(*key, account.data);
+                    recorded_any = true;
+                }
+            }
+            if !recorded_any {
+                return Ok(());
+            }
+            amm.update(&accounts_map)?;
+        }
+        Err(anyhow!("Amm {} kept asking for new accounts", amm.key()))
+    }
+}
+
+#[test]
+fn test_record_symmetry_fund() {
+    use crate::constants::USDC_MINT;
+    use crate::symmetry_token_swap::SymmetryTokenSwap;
+    use solana_sdk::pubkey;
+    use spl_token::native_mint;
+
+    const FUND_STATE_ACCOUNT: Pubkey = pubkey!("Db86JGJnM58KtcZjqf8JFn3md98TDWJZLJJFBzkEWccZ");
+
+    // The checked in fixtures stand in for the rpc
+    let fixtures = FixtureAccountSource::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/synthetic_symmetry_sol_usdc"));
+    let directory = std::env::temp_dir().join(format!("account-recorder-{}", Pubkey::new_unique()));
+    let mut recorder = AccountRecorder::new(fixtures, &directory);
+
+    let keyed_accounts = recorder
+        .record_keyed_accounts(&[FUND_STATE_ACCOUNT, SymmetryTokenSwap::TOKEN_INFO_ADDRESS])
+        .unwrap();
+    let mut amm = SymmetryTokenSwap::from_keyed_account(&keyed_accounts[0], &keyed_accounts[1]).unwrap();
+    recorder.record_amm(&mut amm).unwrap();
+    // Fund state, token info, curve data, the clock and both oracles
+    assert_eq!(recorder.recorded_keys().len(), 6);
+
+    let recorded = FixtureAccountSource::new(&directory);
+    for key in recorder.recorded_keys() {
+        assert!(recorded.get_account(&key).is_ok());
+    }
+    let mut recorded_amm = SymmetryTokenSwap::from_keyed_account(
+        &KeyedAccount {
+            key: FUND_STATE_ACCOUNT,
+            account: recorded.get_account(&FUND_STATE_ACCOUNT).unwrap(),
+            params: None,
+        },
+        &KeyedAccount {
+            key: SymmetryTokenSwap::TOKEN_INFO_ADDRESS,
+            account: recorded.get_account(&SymmetryTokenSwap::TOKEN_INFO_ADDRESS).unwrap(),
+            params: None,
+        },
+    )
+    .unwrap();
+    let keys = recorded_amm.get_accounts_to_update();
+    let accounts_map = keys
+        .iter()
+        .zip(recorded.get_multiple_accounts(&keys).unwrap())
+        .filter_map(|(key, account)| account.map(|account| (*key, account.data)))
+        .collect();
+    recorded_amm.update(&accounts_map).unwrap();
+
+    let quote_params = crate::amms::amm::QuoteParams {
+        in_amount: 1_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: USDC_MINT,
+    };
+    assert_eq!(
+        recorded_amm.quote(&quote_params).unwrap().out_amount,
+        amm.quote(&quote_params).unwrap().out_amount
+    );
+    std::fs::remove_dir_all(directory).unwrap();
+}
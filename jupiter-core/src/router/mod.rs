@@ -0,0 +1,295 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+
+use crate::amms::amm::{Amm, Quote, QuoteParams};
+
+pub mod multi_hop;
+pub mod route_decoder;
+pub mod route_instruction;
+pub mod slippage;
+pub mod split;
+
+/// Quote of a single amm able to serve the request
+pub struct AmmQuote {
+    pub amm_key: Pubkey,
+    pub label: String,
+    pub quote: Quote,
+}
+
+/// Quote the router can route through, an approximate out amount could fail the slippage check on chain
+pub(crate) fn is_routable(quote: &Quote) -> bool {
+    !quote.not_enough_liquidity && !quote.approximate
+}
+
+/// Holds amms indexed by the mint pairs they can swap
+pub struct Router {
+    amms: HashMap<Pubkey, Box<dyn Amm + Send + Sync>>,
+    amm_keys_by_pair: HashMap<(Pubkey, Pubkey), Vec<Pubkey>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self {
+            amms: HashMap::new(),
+            amm_keys_by_pair: HashMap::new(),
+        }
+    }
+
+    /// Adds the amm under its key, replacing any amm with the same key
+    pub fn add_amm(&mut self, amm: Box<dyn Amm + Send + Sync>) {
+        let key = amm.key();
+        if self.amms.contains_key(&key) {
+            self.remove_amm(&key);
+        }
+
+        let reserve_mints = amm.get_reserve_mints();
+        for input_mint in reserve_mints.iter() {
+            for output_mint in reserve_mints.iter() {
+                if input_mint != output_mint {
+                    self.amm_keys_by_pair
+                        .entry((*input_mint, *output_mint))
+                        .or_default()
+                        .push(key);
+                }
+            }
+        }
+        self.amms.insert(key, amm);
+    }
+
+    pub fn remove_amm(&mut self, key: &Pubkey) -> Option<Box<dyn Amm + Send + Sync>> {
+        let amm = self.amms.remove(key)?;
+        for amm_keys in self.amm_keys_by_pair.values_mut() {
+            amm_keys.retain(|amm_key| amm_key != key);
+        }
+        self.amm_keys_by_pair.retain(|_, amm_keys| !amm_keys.is_empty());
+        Some(amm)
+    }
+
+    pub fn get_amm(&self, key: &Pubkey) -> Option<&(dyn Amm + Send + Sync)> {
+        self.amms.get(key).map(|amm| amm.as_ref())
+    }
+
+    /// Amms able to swap input_mint into output_mint
+    pub fn get_amms_for_pair(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+    ) -> Vec<&(dyn Amm + Send + Sync)> {
+        self.amm_keys_by_pair
+            .get(&(*input_mint, *output_mint))
+            .map(|amm_keys| {
+                amm_keys
+                    .iter()
+                    .filter_map(|amm_key| self.get_amm(amm_key))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Mints reachable from input_mint in a single swap
+    pub fn get_output_mints(&self, input_mint: &Pubkey) -> Vec<Pubkey> {
+        let mut output_mints: Vec<Pubkey> = self
+            .amm_keys_by_pair
+            .keys()
+            .filter(|(pair_input_mint, _)| pair_input_mint == input_mint)
+            .map(|(_, output_mint)| *output_mint)
+            .collect();
+        output_mints.sort();
+        output_mints
+    }
+
+    /// Accounts of all amms, without duplicates
+    pub fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut seen: HashSet<Pubkey> = HashSet::new();
+        let mut accounts_to_update = Vec::new();
+        for amm in self.amms.values() {
+            for account in amm.get_accounts_to_update() {
+                if seen.insert(account) {
+                    accounts_to_update.push(account);
+                }
+            }
+        }
+        accounts_to_update
+    }
+
+    /// Updates every amm, amms failing to update are reported but the others are still updated
+    pub fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+        let mut failed_amm_keys = Vec::new();
+        for (key, amm) in self.amms.iter_mut() {
+            if amm.update(accounts_map).is_err() {
+                failed_amm_keys.push(*key);
+            }
+        }
+        if failed_amm_keys.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to update amms: {:?}", failed_amm_keys))
+        }
+    }
+
+    /// Quotes every amm serving the pair, best output first.
+    /// Amms erroring, without enough liquidity or with an approximate quote are left out.
+    pub fn quote(&self, quote_params: &QuoteParams) -> Vec<AmmQuote> {
+        let mut amm_quotes: Vec<AmmQuote> = self
+            .get_amms_for_pair(&quote_params.input_mint, &quote_params.output_mint)
+            .into_iter()
+            .filter_map(|amm| match amm.quote(quote_params) {
+                Ok(quote) if is_routable(&quote) => Some(AmmQuote {
+                    amm_key: amm.key(),
+                    label: amm.label(),
+                    quote,
+                }),
+                _ => None,
+            })
+            .collect();
+        amm_quotes.sort_by(|a, b| {
+            b.quote
+                .out_amount
+                .cmp(&a.quote.out_amount)
+                .then_with(|| a.amm_key.cmp(&b.amm_key))
+        });
+        amm_quotes
+    }
+
+    /// Best quote for the request, if any amm can serve it
+    pub fn best_quote(&self, quote_params: &QuoteParams) -> Option<AmmQuote> {
+        self.quote(quote_params).into_iter().next()
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_router_quote() {
+    use crate::amms::test_harness::new_spl_token_swap_amm;
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use spl_token::native_mint;
+
+    let shallow_pool = new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 1_000_000_000_000, 20_000_000_000);
+    let deep_pool = new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 100_000_000_000_000, 2_000_000_000_000);
+    let empty_pool = new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 0, 0);
+    let stable_pool = new_spl_token_swap_amm(USDC_MINT, USDT_MINT, 1_000_000_000_000, 1_000_000_000_000);
+    let deep_pool_key = deep_pool.key();
+
+    let mut router = Router::new();
+    router.add_amm(Box::new(shallow_pool));
+    router.add_amm(Box::new(deep_pool));
+    router.add_amm(Box::new(empty_pool));
+    router.add_amm(Box::new(stable_pool));
+
+    assert_eq!(router.get_amms_for_pair(&USDC_MINT, &native_mint::id()).len(), 3);
+    assert_eq!(router.get_amms_for_pair(&native_mint::id(), &USDT_MINT).len(), 0);
+    assert_eq!(router.get_accounts_to_update().len(), 8);
+
+    let amm_quotes = router.quote(&QuoteParams {
+        in_amount: 10_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: USDC_MINT,
+    });
+
+    // The empty pool fails to quote
+    assert_eq!(amm_quotes.len(), 2);
+    assert_eq!(amm_quotes[0].amm_key, deep_pool_key);
+    assert!(amm_quotes[0].quote.out_amount > amm_quotes[1].quote.out_amount);
+
+    router.remove_amm(&deep_pool_key);
+    assert_eq!(router.get_amms_for_pair(&native_mint::id(), &USDC_MINT).len(), 2);
+}
+
+#[test]
+fn test_router_skips_approximate_quotes() {
+    use crate::amms::amm::{SwapLegAndAccountMetas, SwapParams};
+    use crate::amms::test_harness::new_spl_token_swap_amm;
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use spl_token::native_mint;
+
+    /// Pool quoting from a model of its curve
+    struct ApproximateAmm {
+        amm: Box<dyn Amm + Send + Sync>,
+    }
+
+    impl Amm for ApproximateAmm {
+        fn label(&self) -> String {
+            self.amm.label()
+        }
+
+        fn key(&self) -> Pubkey {
+            self.amm.key()
+        }
+
+        fn get_reserve_mints(&self) -> Vec<Pubkey> {
+            self.amm.get_reserve_mints()
+        }
+
+        fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+            self.amm.get_accounts_to_update()
+        }
+
+        fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+            self.amm.update(accounts_map)
+        }
+
+        fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+            Ok(Quote {
+                approximate: true,
+                ..self.amm.quote(quote_params)?
+            })
+        }
+
+        fn get_swap_leg_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapLegAndAccountMetas> {
+            self.amm.get_swap_leg_and_account_metas(swap_params)
+        }
+
+        fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+            Box::new(ApproximateAmm {
+                amm: self.amm.clone_amm(),
+            })
+        }
+    }
+
+    // The approximate pool is the deepest, it would win every route
+    let shallow_pool = new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 1_000_000_000_000, 20_000_000_000);
+    let shallow_pool_key = shallow_pool.key();
+    let approximate_pool = ApproximateAmm {
+        amm: Box::new(new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 100_000_000_000_000, 2_000_000_000_000)),
+    };
+    let mut router = Router::new();
+    router.add_amm(Box::new(shallow_pool));
+    router.add_amm(Box::new(approximate_pool));
+    router.add_amm(Box::new(new_spl_token_swap_amm(USDC_MINT, USDT_MINT, 1_000_000_000_000, 1_000_000_000_000)));
+
+    let quote_params = QuoteParams {
+        in_amount: 10_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: USDC_MINT,
+    };
+    let amm_quotes = router.quote(&quote_params);
+    assert_eq!(amm_quotes.len(), 1);
+    assert_eq!(router.best_quote(&quote_params).unwrap().amm_key, shallow_pool_key);
+
+    let split_route = router.find_split_route(&quote_params, split::DEFAULT_PERCENT_STEP, 2).unwrap();
+    assert_eq!(split_route.legs.len(), 1);
+    assert_eq!(split_route.legs[0].amm_key, shallow_pool_key);
+
+    let route = router
+        .best_multi_hop_route(
+            &QuoteParams {
+                output_mint: USDT_MINT,
+                ..quote_params
+            },
+            &[USDC_MINT],
+            2,
+        )
+        .unwrap();
+    assert_eq!(route.hops[0].amm_key, shallow_pool_key);
+
+    // Without another pool there is no route at all
+    router.remove_amm(&shallow_pool_key);
+    assert!(router.best_quote(&quote_params).is_none());
+    assert!(router.find_split_route(&quote_params, split::DEFAULT_PERCENT_STEP, 2).is_none());
+}
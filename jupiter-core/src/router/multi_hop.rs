@@ -0,0 +1,249 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+
+use super::Router;
+use crate::amms::amm::{Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use jupiter::jupiter_override::SwapLeg;
+
+/// Longest chain of swaps considered
+pub const MAX_HOPS: usize = 3;
+
+/// A single swap of a route through the best amm for its pair
+pub struct RouteHop {
+    pub amm_key: Pubkey,
+    pub label: String,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub quote: Quote,
+}
+
+/// Swaps chained one after the other, each hop out amount being the next hop in amount
+pub struct MultiHopRoute {
+    pub hops: Vec<RouteHop>,
+    pub in_amount: u64,
+    pub out_amount: u64,
+}
+
+impl MultiHopRoute {
+    pub fn mints(&self) -> Vec<Pubkey> {
+        let mut mints: Vec<Pubkey> = self.hops.iter().map(|hop| hop.input_mint).collect();
+        mints.extend(self.hops.last().map(|hop| hop.output_mint));
+        mints
+    }
+
+    /// Chain of each hop swap leg with their account metas concatenated in order.
+    /// Intermediate tokens go through the associated token accounts of the user transfer authority,
+    /// a single hop route is returned as its swap leg directly.
+    pub fn get_swap_leg_and_account_metas(
+        &self,
+        router: &Router,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let mut swap_legs = Vec::new();
+        let mut account_metas = Vec::new();
+
+        for (index, hop) in self.hops.iter().enumerate() {
+            let amm = router
+                .get_amm(&hop.amm_key)
+                .ok_or_else(|| anyhow!("Amm {} is not in the router", hop.amm_key))?;
+
+            let user_source_token_account = if index == 0 {
+                swap_params.user_source_token_account
+            } else {
+                get_associated_token_address(&swap_params.user_transfer_authority, &hop.input_mint)
+            };
+            let user_destination_token_account = if index == self.hops.len() - 1 {
+                swap_params.user_destination_token_account
+            } else {
+                get_associated_token_address(&swap_params.user_transfer_authority, &hop.output_mint)
+            };
+
+            let hop_swap_leg_and_account_metas = amm.get_swap_leg_and_account_metas(&SwapParams {
+                source_mint: hop.input_mint,
+                destination_mint: hop.output_mint,
+                user_source_token_account,
+                user_destination_token_account,
+                user_transfer_authority: swap_params.user_transfer_authority,
+                open_order_address: swap_params.open_order_address,
+                quote_mint_to_referrer: swap_params.quote_mint_to_referrer.clone(),
+                in_amount: hop.quote.in_amount,
+            })?;
+            swap_legs.push(hop_swap_leg_and_account_metas.swap_leg);
+            account_metas.extend(hop_swap_leg_and_account_metas.account_metas);
+        }
+
+        let swap_leg = if swap_legs.len() == 1 {
+            swap_legs.pop().unwrap()
+        } else {
+            SwapLeg::Chain { swap_legs }
+        };
+        Ok(SwapLegAndAccountMetas {
+            swap_leg,
+            account_metas,
+        })
+    }
+}
+
+impl Router {
+    /// Quotes the chain of swaps through mints, picking the best amm for every hop
+    pub fn quote_path(&self, mints: &[Pubkey], in_amount: u64) -> Option<MultiHopRoute> {
+        let mut hops = Vec::new();
+        let mut hop_in_amount = in_amount;
+
+        for pair in mints.windows(2) {
+            let amm_quote = self.best_quote(&QuoteParams {
+                in_amount: hop_in_amount,
+                input_mint: pair[0],
+                output_mint: pair[1],
+            })?;
+            if amm_quote.quote.out_amount == 0 {
+                return None;
+            }
+            hops.push(RouteHop {
+                amm_key: amm_quote.amm_key,
+                label: amm_quote.label,
+                input_mint: pair[0],
+                output_mint: pair[1],
+                quote: Quote {
+                    in_amount: hop_in_amount,
+                    ..amm_quote.quote
+                },
+            });
+            hop_in_amount = amm_quote.quote.out_amount;
+        }
+
+        if hops.is_empty() {
+            return None;
+        }
+        Some(MultiHopRoute {
+            hops,
+            in_amount,
+            out_amount: hop_in_amount,
+        })
+    }
+
+    /// Routes from the direct swap up to max_hops swaps going through the intermediate mints,
+    /// best output first
+    pub fn find_multi_hop_routes(
+        &self,
+        quote_params: &QuoteParams,
+        intermediate_mints: &[Pubkey],
+        max_hops: usize,
+    ) -> Vec<MultiHopRoute> {
+        let max_hops = max_hops.min(MAX_HOPS);
+        let intermediate_mints: Vec<Pubkey> = intermediate_mints
+            .iter()
+            .filter(|mint| **mint != quote_params.input_mint && **mint != quote_params.output_mint)
+            .copied()
+            .collect();
+
+        let mut paths: Vec<Vec<Pubkey>> = vec![vec![quote_params.input_mint, quote_params.output_mint]];
+        if max_hops >= 2 {
+            for first_mint in intermediate_mints.iter() {
+                paths.push(vec![quote_params.input_mint, *first_mint, quote_params.output_mint]);
+            }
+        }
+        if max_hops >= 3 {
+            for first_mint in intermediate_mints.iter() {
+                for second_mint in intermediate_mints.iter().filter(|mint| *mint != first_mint) {
+                    paths.push(vec![
+                        quote_params.input_mint,
+                        *first_mint,
+                        *second_mint,
+                        quote_params.output_mint,
+                    ]);
+                }
+            }
+        }
+
+        let mut routes: Vec<MultiHopRoute> = paths
+            .iter()
+            .filter(|path| self.has_path(path))
+            .filter_map(|path| self.quote_path(path, quote_params.in_amount))
+            .collect();
+        routes.sort_by(|a, b| {
+            b.out_amount
+                .cmp(&a.out_amount)
+                .then_with(|| a.hops.len().cmp(&b.hops.len()))
+        });
+        routes
+    }
+
+    /// Best route up to max_hops swaps
+    pub fn best_multi_hop_route(
+        &self,
+        quote_params: &QuoteParams,
+        intermediate_mints: &[Pubkey],
+        max_hops: usize,
+    ) -> Option<MultiHopRoute> {
+        self.find_multi_hop_routes(quote_params, intermediate_mints, max_hops)
+            .into_iter()
+            .next()
+    }
+
+    fn has_path(&self, mints: &[Pubkey]) -> bool {
+        mints
+            .windows(2)
+            .all(|pair| !self.get_amms_for_pair(&pair[0], &pair[1]).is_empty())
+    }
+}
+
+#[test]
+fn test_find_multi_hop_routes() {
+    use crate::amms::test_harness::new_spl_token_swap_amm;
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use spl_token::native_mint;
+
+    let fund_token_mint = Pubkey::new_unique();
+
+    let mut router = Router::new();
+    router.add_amm(Box::new(new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 100_000_000_000_000, 2_000_000_000_000)));
+    router.add_amm(Box::new(new_spl_token_swap_amm(USDC_MINT, USDT_MINT, 1_000_000_000_000, 1_000_000_000_000)));
+    router.add_amm(Box::new(new_spl_token_swap_amm(USDT_MINT, fund_token_mint, 1_000_000_000_000, 1_000_000_000_000)));
+
+    let quote_params = QuoteParams {
+        in_amount: 1_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: fund_token_mint,
+    };
+    let intermediate_mints = [USDC_MINT, USDT_MINT];
+
+    assert!(router.best_multi_hop_route(&quote_params, &intermediate_mints, 2).is_none());
+
+    let route = router.best_multi_hop_route(&quote_params, &intermediate_mints, 3).unwrap();
+    assert_eq!(route.mints(), vec![native_mint::id(), USDC_MINT, USDT_MINT, fund_token_mint]);
+    for (hop, next_hop) in route.hops.iter().zip(route.hops.iter().skip(1)) {
+        assert_eq!(hop.quote.out_amount, next_hop.quote.in_amount);
+    }
+    assert_eq!(route.out_amount, route.hops[2].quote.out_amount);
+
+    let user = Pubkey::new_unique();
+    let swap_leg_and_account_metas = route
+        .get_swap_leg_and_account_metas(
+            &router,
+            &SwapParams {
+                source_mint: native_mint::id(),
+                destination_mint: fund_token_mint,
+                user_source_token_account: get_associated_token_address(&user, &native_mint::id()),
+                user_destination_token_account: get_associated_token_address(&user, &fund_token_mint),
+                user_transfer_authority: user,
+                open_order_address: None,
+                quote_mint_to_referrer: None,
+                in_amount: quote_params.in_amount,
+            },
+        )
+        .unwrap();
+
+    match swap_leg_and_account_metas.swap_leg {
+        SwapLeg::Chain { swap_legs } => assert_eq!(swap_legs.len(), 3),
+        _ => panic!("Expected a chain"),
+    }
+    // Token swap takes 11 accounts per leg
+    assert_eq!(swap_leg_and_account_metas.account_metas.len(), 33);
+    // The USDC the first hop outputs is the input of the second hop
+    assert_eq!(
+        swap_leg_and_account_metas.account_metas[8].pubkey,
+        swap_leg_and_account_metas.account_metas[11 + 5].pubkey
+    );
+}
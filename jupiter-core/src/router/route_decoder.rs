@@ -0,0 +1,479 @@
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::{anyhow, Context, Result};
+use solana_sdk::pubkey::Pubkey;
+
+use super::Router;
+use crate::constants::{OPENBOOK_PROGRAM_ID, SERUM_V3_PROGRAM_ID};
+use crate::lifinity_amm::{LIFINITY_V1_PROGRAM_ID, LIFINITY_V2_PROGRAM_ID};
+use crate::marinade_amm::MARINADE_PROGRAM_ID;
+use crate::mercurial_amm::MERCURIAL_PROGRAM_ID;
+use crate::raydium_amm::RAYDIUM_AMM_V4_PROGRAM_ID;
+use crate::raydium_clmm_amm::{RAYDIUM_CLMM_PROGRAM_ID, SWAP_TICK_ARRAYS};
+use crate::saber_add_decimals_amm::SABER_ADD_DECIMALS_PROGRAM_ID;
+use crate::saber_amm::SABER_PROGRAM_ID;
+use crate::spl_token_swap_amm::SPL_TOKEN_SWAP_PROGRAMS;
+use crate::step_token_swap_amm::STEP_TOKEN_SWAP_PROGRAM_ID;
+use crate::symmetry_token_swap::SymmetryTokenSwap;
+use crate::whirlpool_amm::WHIRLPOOL_PROGRAM_ID;
+use jupiter::jupiter_override::{Route, Swap, SwapLeg};
+
+/// Accounts of the route instruction before the platform fee account and the legs
+const ROUTE_ACCOUNTS_LEN: usize = 3;
+/// Accounts of a Symmetry swap before the oracle of each fund token
+const SYMMETRY_ACCOUNTS_LEN: usize = 13;
+
+/// A single swap of a decoded route with the accounts it was given
+pub struct DecodedSwap {
+    pub swap: Swap,
+    pub label: String,
+    /// Pool or fund the swap went through, when it could be recognised
+    pub amm_key: Option<Pubkey>,
+    pub account_keys: Vec<Pubkey>,
+}
+
+pub enum DecodedSwapLeg {
+    Chain { swap_legs: Vec<DecodedSwapLeg> },
+    Split { split_legs: Vec<(u8, DecodedSwapLeg)> },
+    Swap(DecodedSwap),
+}
+
+impl DecodedSwapLeg {
+    /// Swaps of the leg in the order their accounts appear
+    pub fn swaps(&self) -> Vec<&DecodedSwap> {
+        match self {
+            DecodedSwapLeg::Chain { swap_legs } => swap_legs.iter().flat_map(|swap_leg| swap_leg.swaps()).collect(),
+            DecodedSwapLeg::Split { split_legs } => split_legs
+                .iter()
+                .flat_map(|(_, swap_leg)| swap_leg.swaps())
+                .collect(),
+            DecodedSwapLeg::Swap(decoded_swap) => vec![decoded_swap],
+        }
+    }
+
+    fn last_swap_mut(&mut self) -> Option<&mut DecodedSwap> {
+        match self {
+            DecodedSwapLeg::Chain { swap_legs } => swap_legs.last_mut()?.last_swap_mut(),
+            DecodedSwapLeg::Split { split_legs } => split_legs.last_mut()?.1.last_swap_mut(),
+            DecodedSwapLeg::Swap(decoded_swap) => Some(decoded_swap),
+        }
+    }
+}
+
+pub struct DecodedRoute {
+    pub token_program: Pubkey,
+    pub user_transfer_authority: Pubkey,
+    pub destination_token_account: Pubkey,
+    pub platform_fee_account: Option<Pubkey>,
+    pub swap_leg: DecodedSwapLeg,
+    pub in_amount: u64,
+    pub quoted_out_amount: u64,
+    pub slippage_bps: u16,
+    pub platform_fee_bps: u8,
+}
+
+impl DecodedRoute {
+    /// Whether any swap of the route went through the amm
+    pub fn touches(&self, amm_key: &Pubkey) -> bool {
+        self.swap_leg
+            .swaps()
+            .iter()
+            .any(|decoded_swap| decoded_swap.amm_key.as_ref() == Some(amm_key))
+    }
+}
+
+/// Accounts each swap takes in the Jupiter IDL
+fn swap_accounts_len(swap: &Swap) -> usize {
+    match swap {
+        Swap::Saber | Swap::Sencha => 10,
+        Swap::SaberAddDecimalsDeposit | Swap::SaberAddDecimalsWithdraw => 8,
+        Swap::TokenSwap | Swap::Step | Swap::Cykura | Swap::Aldrin { .. } | Swap::Invariant { .. } => 11,
+        // Raydium CLMM swaps pass the tick arrays after the first one as remaining accounts
+        Swap::RaydiumClmm => 10 + SWAP_TICK_ARRAYS,
+        Swap::Cropper | Swap::MarinadeUnstake | Swap::AldrinV2 { .. } | Swap::Whirlpool { .. } => 12,
+        Swap::DeltaFi { .. } => 13,
+        Swap::Lifinity | Swap::LifinityV2 => 14,
+        Swap::Crema { .. } | Swap::GooseFX => 15,
+        Swap::Serum { .. } | Swap::Openbook { .. } | Swap::Meteora | Swap::Balansol => 16,
+        Swap::MarinadeDeposit | Swap::Dradex { .. } => 17,
+        Swap::Raydium | Swap::MarcoPolo { .. } => 18,
+        // Mercurial swaps pass every token account of the pool after these
+        Swap::Mercurial => 7,
+    }
+}
+
+fn is_symmetry_swap(swap: &Swap, accounts: &[Pubkey]) -> bool {
+    matches!(swap, Swap::TokenSwap) && accounts.get(2) == Some(&SymmetryTokenSwap::PDA_ADDRESS)
+}
+
+/// Swaps of the leg tree in the order their accounts appear
+fn collect_swaps<'a>(swap_leg: &'a SwapLeg, swaps: &mut Vec<&'a Swap>) {
+    match swap_leg {
+        SwapLeg::Chain { swap_legs } => swap_legs.iter().for_each(|swap_leg| collect_swaps(swap_leg, swaps)),
+        SwapLeg::Split { split_legs } => split_legs
+            .iter()
+            .for_each(|split_leg| collect_swaps(&split_leg.swap_leg, swaps)),
+        SwapLeg::Swap { swap } => swaps.push(swap),
+    }
+}
+
+/// Symmetry swaps pass the oracle of each fund token after their accounts and Mercurial swaps
+/// the token account of each pool token, the number of tokens is only known when the fund or
+/// pool is in the router, otherwise the swap takes the accounts left once the swaps after it
+/// have their fixed accounts. With more than one such swap missing from the router, the first one
+/// gets the accounts of the others past their fixed accounts.
+fn leg_accounts_len(swap: &Swap, accounts: &[Pubkey], later_accounts_len: usize, router: Option<&Router>) -> usize {
+    let fixed_accounts_len = if is_symmetry_swap(swap, accounts) {
+        SYMMETRY_ACCOUNTS_LEN
+    } else if matches!(swap, Swap::Mercurial) {
+        swap_accounts_len(swap)
+    } else {
+        return swap_accounts_len(swap);
+    };
+    match router.and_then(|router| router.get_amm(accounts.get(1)?)) {
+        Some(amm) => fixed_accounts_len + amm.get_reserve_mints().len(),
+        None => accounts.len().saturating_sub(later_accounts_len).max(fixed_accounts_len),
+    }
+}
+
+fn identify_amm(swap: &Swap, account_keys: &[Pubkey], router: Option<&Router>) -> (String, Option<Pubkey>) {
+    if let Some(router) = router {
+        if let Some(amm) = account_keys.iter().find_map(|account_key| router.get_amm(account_key)) {
+            return (amm.label(), Some(amm.key()));
+        }
+    }
+    if is_symmetry_swap(swap, account_keys) {
+        return (String::from("Symmetry"), Some(account_keys[1]));
+    }
+    if let Swap::TokenSwap = swap {
+        if let Some(label) = SPL_TOKEN_SWAP_PROGRAMS.get(&account_keys[0]) {
+            return (label.clone(), Some(account_keys[2]));
+        }
+    }
+    match swap {
+        Swap::Step if account_keys[0] == STEP_TOKEN_SWAP_PROGRAM_ID => {
+            return (String::from("Step"), Some(account_keys[2]));
+        }
+        Swap::Saber if account_keys[0] == SABER_PROGRAM_ID => {
+            return (String::from("Saber"), Some(account_keys[2]));
+        }
+        Swap::SaberAddDecimalsDeposit | Swap::SaberAddDecimalsWithdraw
+            if account_keys[0] == SABER_ADD_DECIMALS_PROGRAM_ID =>
+        {
+            return (String::from("Saber (Decimals)"), Some(account_keys[1]));
+        }
+        Swap::Raydium if account_keys[0] == RAYDIUM_AMM_V4_PROGRAM_ID => {
+            return (String::from("Raydium"), Some(account_keys[2]));
+        }
+        Swap::Mercurial if account_keys[0] == MERCURIAL_PROGRAM_ID => {
+            return (String::from("Mercurial"), Some(account_keys[1]));
+        }
+        Swap::Lifinity if account_keys[0] == LIFINITY_V1_PROGRAM_ID => {
+            return (String::from("Lifinity"), Some(account_keys[2]));
+        }
+        Swap::LifinityV2 if account_keys[0] == LIFINITY_V2_PROGRAM_ID => {
+            return (String::from("Lifinity V2"), Some(account_keys[2]));
+        }
+        Swap::MarinadeDeposit | Swap::MarinadeUnstake if account_keys[0] == MARINADE_PROGRAM_ID => {
+            return (String::from("Marinade"), Some(account_keys[1]));
+        }
+        // Market accounts come first, the dex program after the user accounts
+        Swap::Openbook { .. } if account_keys[13] == OPENBOOK_PROGRAM_ID => {
+            return (String::from("Openbook"), Some(account_keys[0]));
+        }
+        Swap::Serum { .. } if account_keys[13] == SERUM_V3_PROGRAM_ID => {
+            return (String::from("Serum"), Some(account_keys[0]));
+        }
+        Swap::RaydiumClmm if account_keys[0] == RAYDIUM_CLMM_PROGRAM_ID => {
+            return (String::from("Raydium CLMM"), Some(account_keys[3]));
+        }
+        Swap::Whirlpool { .. } if account_keys[0] == WHIRLPOOL_PROGRAM_ID => {
+            return (String::from("Orca (Whirlpools)"), Some(account_keys[3]));
+        }
+        _ => (),
+    }
+    let label = format!("{:?}", swap);
+    (label.split(' ').next().unwrap_or_default().to_string(), None)
+}
+
+/// later_accounts_lens holds, for each swap left to decode, the fixed accounts of the swaps after it
+fn decode_swap_leg(
+    swap_leg: SwapLeg,
+    accounts: &mut &[Pubkey],
+    later_accounts_lens: &mut &[usize],
+    router: Option<&Router>,
+) -> Result<DecodedSwapLeg> {
+    match swap_leg {
+        SwapLeg::Chain { swap_legs } => Ok(DecodedSwapLeg::Chain {
+            swap_legs: swap_legs
+                .into_iter()
+                .map(|swap_leg| decode_swap_leg(swap_leg, accounts, later_accounts_lens, router))
+                .collect::<Result<_>>()?,
+        }),
+        SwapLeg::Split { split_legs } => Ok(DecodedSwapLeg::Split {
+            split_legs: split_legs
+                .into_iter()
+                .map(|split_leg| Ok((split_leg.percent, decode_swap_leg(split_leg.swap_leg, accounts, later_accounts_lens, router)?)))
+                .collect::<Result<_>>()?,
+        }),
+        SwapLeg::Swap { swap } => {
+            let (later_accounts_len, remaining_later_accounts_lens) = later_accounts_lens
+                .split_first()
+                .map(|(later_accounts_len, rest)| (*later_accounts_len, rest))
+                .unwrap_or_default();
+            *later_accounts_lens = remaining_later_accounts_lens;
+            let accounts_len = leg_accounts_len(&swap, accounts, later_accounts_len, router);
+            if accounts.len() < accounts_len {
+                return Err(anyhow!(
+                    "Not enough account keys for {:?}, expected {} got {}",
+                    swap,
+                    accounts_len,
+                    accounts.len()
+                ));
+            }
+            let (account_keys, remaining_accounts) = accounts.split_at(accounts_len);
+            *accounts = remaining_accounts;
+
+            let (label, amm_key) = identify_amm(&swap, account_keys, router);
+            Ok(DecodedSwapLeg::Swap(DecodedSwap {
+                swap,
+                label,
+                amm_key,
+                account_keys: account_keys.to_vec(),
+            }))
+        }
+    }
+}
+
+/// Decodes a Jupiter route instruction, walking its swap leg tree to give each swap
+/// the slice of accounts it consumes. Amms in the router are recognised by their key,
+/// Symmetry funds and spl token swap pools are recognised without it.
+/// Accounts left once every swap is decoded, such as a host fee account, go to the last swap.
+pub fn decode_route_instruction(data: &[u8], account_keys: &[Pubkey], router: Option<&Router>) -> Result<DecodedRoute> {
+    if data.len() < 8 || data[..8] != Route::DISCRIMINATOR {
+        return Err(anyhow!("Not a route instruction"));
+    }
+    let Route {
+        swap_leg,
+        in_amount,
+        quoted_out_amount,
+        slippage_bps,
+        platform_fee_bps,
+    } = Route::try_from_slice(&data[8..]).context("Failed to deserialize route")?;
+
+    let platform_fee_accounts_len = if platform_fee_bps > 0 { 1 } else { 0 };
+    if account_keys.len() < ROUTE_ACCOUNTS_LEN + platform_fee_accounts_len {
+        return Err(anyhow!("Not enough account keys for the route"));
+    }
+    let platform_fee_account = if platform_fee_bps > 0 {
+        Some(account_keys[ROUTE_ACCOUNTS_LEN])
+    } else {
+        None
+    };
+
+    let mut accounts = &account_keys[ROUTE_ACCOUNTS_LEN + platform_fee_accounts_len..];
+    let mut swaps = Vec::new();
+    collect_swaps(&swap_leg, &mut swaps);
+    let later_accounts_lens: Vec<usize> = (0..swaps.len())
+        .map(|index| swaps[index + 1..].iter().map(|swap| swap_accounts_len(swap)).sum())
+        .collect();
+    let mut swap_leg = decode_swap_leg(swap_leg, &mut accounts, &mut &later_accounts_lens[..], router)?;
+    if !accounts.is_empty() {
+        if let Some(last_swap) = swap_leg.last_swap_mut() {
+            last_swap.account_keys.extend_from_slice(accounts);
+        }
+    }
+
+    Ok(DecodedRoute {
+        token_program: account_keys[0],
+        user_transfer_authority: account_keys[1],
+        destination_token_account: account_keys[2],
+        platform_fee_account,
+        swap_leg,
+        in_amount,
+        quoted_out_amount,
+        slippage_bps,
+        platform_fee_bps,
+    })
+}
+
+#[test]
+fn test_decode_route_instruction() {
+    use super::route_instruction::{build_route_instruction, RouteInstructionParams, RoutePlan};
+    use crate::amms::amm::{Amm, QuoteParams, SwapParams};
+    use crate::amms::test_harness::new_spl_token_swap_amm;
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use anchor_lang::InstructionData;
+    use spl_associated_token_account::get_associated_token_address;
+    use spl_token::native_mint;
+
+    let sol_usdc_pool = new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 100_000_000_000_000, 2_000_000_000_000);
+    let usdc_usdt_pool = new_spl_token_swap_amm(USDC_MINT, USDT_MINT, 1_000_000_000_000, 1_000_000_000_000);
+    let sol_usdc_pool_key = sol_usdc_pool.key();
+    let usdc_usdt_pool_key = usdc_usdt_pool.key();
+    let mut router = Router::new();
+    router.add_amm(Box::new(sol_usdc_pool));
+    router.add_amm(Box::new(usdc_usdt_pool));
+
+    let quote_params = QuoteParams {
+        in_amount: 1_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: USDT_MINT,
+    };
+    let route = router.best_multi_hop_route(&quote_params, &[USDC_MINT], 2).unwrap();
+    let user = Pubkey::new_unique();
+    let user_destination_token_account = get_associated_token_address(&user, &USDT_MINT);
+    let swap_leg_and_account_metas = route
+        .get_swap_leg_and_account_metas(
+            &router,
+            &SwapParams {
+                source_mint: native_mint::id(),
+                destination_mint: USDT_MINT,
+                user_source_token_account: get_associated_token_address(&user, &native_mint::id()),
+                user_destination_token_account,
+                user_transfer_authority: user,
+                open_order_address: None,
+                quote_mint_to_referrer: None,
+                in_amount: quote_params.in_amount,
+            },
+        )
+        .unwrap();
+    let platform_fee_account = Pubkey::new_unique();
+    let instruction = build_route_instruction(
+        RoutePlan {
+            legs: vec![swap_leg_and_account_metas],
+            in_amount: route.in_amount,
+            quoted_out_amount: route.out_amount,
+        },
+        &RouteInstructionParams {
+            user_transfer_authority: user,
+            user_destination_token_account,
+            platform_fee_account: Some(platform_fee_account),
+            slippage_bps: 50,
+            platform_fee_bps: 20,
+        },
+    )
+    .unwrap();
+    let account_keys: Vec<Pubkey> = instruction.accounts.iter().map(|account_meta| account_meta.pubkey).collect();
+
+    let decoded_route = decode_route_instruction(&instruction.data, &account_keys, Some(&router)).unwrap();
+    assert_eq!(decoded_route.user_transfer_authority, user);
+    assert_eq!(decoded_route.destination_token_account, user_destination_token_account);
+    assert_eq!(decoded_route.platform_fee_account, Some(platform_fee_account));
+    assert_eq!(decoded_route.in_amount, route.in_amount);
+    assert_eq!(decoded_route.quoted_out_amount, route.out_amount);
+    let swaps = decoded_route.swap_leg.swaps();
+    assert_eq!(swaps.len(), 2);
+    assert_eq!(swaps[0].amm_key, Some(sol_usdc_pool_key));
+    assert_eq!(swaps[1].amm_key, Some(usdc_usdt_pool_key));
+    assert_eq!(swaps[0].account_keys[..], account_keys[4..15]);
+    assert_eq!(swaps[1].account_keys[..], account_keys[15..]);
+    assert!(decoded_route.touches(&usdc_usdt_pool_key));
+
+    // Pools are recognised from their program without the router
+    let decoded_route = decode_route_instruction(&instruction.data, &account_keys, None).unwrap();
+    assert_eq!(decoded_route.swap_leg.swaps()[1].amm_key, Some(usdc_usdt_pool_key));
+
+    // A Symmetry swap split with a whirlpool swap, the fund is not in the router so it takes the accounts left
+    let fund = Pubkey::new_unique();
+    let whirlpool_account_keys: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+    let mut symmetry_account_keys: Vec<Pubkey> = (0..SYMMETRY_ACCOUNTS_LEN + 2).map(|_| Pubkey::new_unique()).collect();
+    symmetry_account_keys[1] = fund;
+    symmetry_account_keys[2] = SymmetryTokenSwap::PDA_ADDRESS;
+    let data = Route {
+        swap_leg: SwapLeg::Split {
+            split_legs: vec![
+                jupiter::jupiter_override::SplitLeg {
+                    percent: 30,
+                    swap_leg: SwapLeg::Swap {
+                        swap: Swap::Whirlpool { a_to_b: true },
+                    },
+                },
+                jupiter::jupiter_override::SplitLeg {
+                    percent: 70,
+                    swap_leg: SwapLeg::Swap { swap: Swap::TokenSwap },
+                },
+            ],
+        },
+        in_amount: 1_000,
+        quoted_out_amount: 990,
+        slippage_bps: 50,
+        platform_fee_bps: 0,
+    }
+    .data();
+    let mut account_keys = account_keys[..3].to_vec();
+    account_keys.extend(whirlpool_account_keys.iter());
+    account_keys.extend(symmetry_account_keys.iter());
+
+    let decoded_route = decode_route_instruction(&data, &account_keys, Some(&router)).unwrap();
+    assert_eq!(decoded_route.platform_fee_account, None);
+    match &decoded_route.swap_leg {
+        DecodedSwapLeg::Split { split_legs } => {
+            assert_eq!(split_legs[0].0, 30);
+            assert_eq!(split_legs[1].0, 70);
+        }
+        _ => panic!("Expected a split"),
+    }
+    let swaps = decoded_route.swap_leg.swaps();
+    assert_eq!(swaps[0].label, "Whirlpool");
+    assert_eq!(swaps[0].account_keys, whirlpool_account_keys);
+    assert_eq!(swaps[1].label, "Symmetry");
+    assert_eq!(swaps[1].amm_key, Some(fund));
+    assert_eq!(swaps[1].account_keys, symmetry_account_keys);
+
+    // Without the router a Symmetry swap in the middle of a chain leaves the fixed accounts of the swaps after it
+    let whirlpool_out_account_keys: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+    let chain_data = Route {
+        swap_leg: SwapLeg::Chain {
+            swap_legs: vec![
+                SwapLeg::Swap {
+                    swap: Swap::Whirlpool { a_to_b: true },
+                },
+                SwapLeg::Swap { swap: Swap::TokenSwap },
+                SwapLeg::Swap {
+                    swap: Swap::Whirlpool { a_to_b: false },
+                },
+            ],
+        },
+        in_amount: 1_000,
+        quoted_out_amount: 990,
+        slippage_bps: 50,
+        platform_fee_bps: 0,
+    }
+    .data();
+    let mut chain_account_keys = account_keys.clone();
+    chain_account_keys.extend(whirlpool_out_account_keys.iter());
+    let decoded_route = decode_route_instruction(&chain_data, &chain_account_keys, None).unwrap();
+    let swaps = decoded_route.swap_leg.swaps();
+    assert_eq!(swaps[0].account_keys, whirlpool_account_keys);
+    assert_eq!(swaps[1].amm_key, Some(fund));
+    assert_eq!(swaps[1].account_keys, symmetry_account_keys);
+    assert_eq!(swaps[2].account_keys, whirlpool_out_account_keys);
+
+    // Same for a Mercurial swap with its 3 pool token accounts
+    let mercurial_account_keys: Vec<Pubkey> = (0..10).map(|_| Pubkey::new_unique()).collect();
+    let chain_data = Route {
+        swap_leg: SwapLeg::Chain {
+            swap_legs: vec![
+                SwapLeg::Swap { swap: Swap::Mercurial },
+                SwapLeg::Swap {
+                    swap: Swap::Whirlpool { a_to_b: false },
+                },
+            ],
+        },
+        in_amount: 1_000,
+        quoted_out_amount: 990,
+        slippage_bps: 50,
+        platform_fee_bps: 0,
+    }
+    .data();
+    let mut chain_account_keys = account_keys[..3].to_vec();
+    chain_account_keys.extend(mercurial_account_keys.iter());
+    chain_account_keys.extend(whirlpool_out_account_keys.iter());
+    let decoded_route = decode_route_instruction(&chain_data, &chain_account_keys, None).unwrap();
+    let swaps = decoded_route.swap_leg.swaps();
+    assert_eq!(swaps[0].account_keys, mercurial_account_keys);
+    assert_eq!(swaps[1].account_keys, whirlpool_out_account_keys);
+
+    assert!(decode_route_instruction(&data, &account_keys[..10], Some(&router)).is_err());
+    assert!(decode_route_instruction(&data[8..], &account_keys, Some(&router)).is_err());
+}
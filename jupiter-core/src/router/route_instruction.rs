@@ -0,0 +1,161 @@
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::amms::amm::SwapLegAndAccountMetas;
+use jupiter::jupiter_override::{Route, SwapLeg};
+
+/// Legs swapped one after the other from the input to the output of the route
+pub struct RoutePlan {
+    pub legs: Vec<SwapLegAndAccountMetas>,
+    pub in_amount: u64,
+    pub quoted_out_amount: u64,
+}
+
+pub struct RouteInstructionParams {
+    pub user_transfer_authority: Pubkey,
+    pub user_destination_token_account: Pubkey,
+    pub platform_fee_account: Option<Pubkey>,
+    pub slippage_bps: u16,
+    pub platform_fee_bps: u8,
+}
+
+/// Builds the Jupiter route instruction. Top level accounts come first, then the platform fee account
+/// when a platform fee is charged, then the account metas of every leg in order.
+pub fn build_route_instruction(
+    route_plan: RoutePlan,
+    route_instruction_params: &RouteInstructionParams,
+) -> Result<Instruction> {
+    let RoutePlan {
+        mut legs,
+        in_amount,
+        quoted_out_amount,
+    } = route_plan;
+    if legs.is_empty() {
+        return Err(anyhow!("Empty route"));
+    }
+
+    let mut accounts = jupiter::accounts::Route {
+        token_program: spl_token::ID,
+        user_transfer_authority: route_instruction_params.user_transfer_authority,
+        destination_token_account: route_instruction_params.user_destination_token_account,
+    }
+    .to_account_metas(None);
+
+    if route_instruction_params.platform_fee_bps > 0 {
+        let platform_fee_account = route_instruction_params
+            .platform_fee_account
+            .ok_or_else(|| anyhow!("Missing platform fee account"))?;
+        accounts.push(AccountMeta::new(platform_fee_account, false));
+    }
+
+    let swap_leg = if legs.len() == 1 {
+        let leg = legs.pop().unwrap();
+        accounts.extend(leg.account_metas);
+        leg.swap_leg
+    } else {
+        let mut swap_legs = Vec::new();
+        for leg in legs {
+            accounts.extend(leg.account_metas);
+            swap_legs.push(leg.swap_leg);
+        }
+        SwapLeg::Chain { swap_legs }
+    };
+
+    Ok(Instruction {
+        program_id: jupiter::ID,
+        accounts,
+        data: Route {
+            swap_leg,
+            in_amount,
+            quoted_out_amount,
+            slippage_bps: route_instruction_params.slippage_bps,
+            platform_fee_bps: route_instruction_params.platform_fee_bps,
+        }
+        .data(),
+    })
+}
+
+#[test]
+fn test_build_route_instruction() {
+    use super::Router;
+    use crate::amms::amm::{QuoteParams, SwapParams};
+    use crate::amms::test_harness::new_spl_token_swap_amm;
+    use crate::constants::{USDC_MINT, USDT_MINT};
+    use anchor_lang::Discriminator;
+    use spl_associated_token_account::get_associated_token_address;
+    use spl_token::native_mint;
+
+    let mut router = Router::new();
+    router.add_amm(Box::new(new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 100_000_000_000_000, 2_000_000_000_000)));
+    router.add_amm(Box::new(new_spl_token_swap_amm(USDC_MINT, USDT_MINT, 1_000_000_000_000, 1_000_000_000_000)));
+
+    let quote_params = QuoteParams {
+        in_amount: 1_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: USDT_MINT,
+    };
+    let route = router.best_multi_hop_route(&quote_params, &[USDC_MINT], 2).unwrap();
+
+    let user = Pubkey::new_unique();
+    let user_destination_token_account = get_associated_token_address(&user, &USDT_MINT);
+    let platform_fee_account = Pubkey::new_unique();
+    let swap_params = SwapParams {
+        source_mint: native_mint::id(),
+        destination_mint: USDT_MINT,
+        user_source_token_account: get_associated_token_address(&user, &native_mint::id()),
+        user_destination_token_account,
+        user_transfer_authority: user,
+        open_order_address: None,
+        quote_mint_to_referrer: None,
+        in_amount: quote_params.in_amount,
+    };
+    let swap_leg_and_account_metas = route.get_swap_leg_and_account_metas(&router, &swap_params).unwrap();
+
+    let route_instruction_params = RouteInstructionParams {
+        user_transfer_authority: user,
+        user_destination_token_account,
+        platform_fee_account: None,
+        slippage_bps: 50,
+        platform_fee_bps: 20,
+    };
+    let route_plan = RoutePlan {
+        legs: vec![swap_leg_and_account_metas],
+        in_amount: route.in_amount,
+        quoted_out_amount: route.out_amount,
+    };
+    assert!(build_route_instruction(route_plan, &route_instruction_params).is_err());
+
+    let swap_leg_and_account_metas = route.get_swap_leg_and_account_metas(&router, &swap_params).unwrap();
+    let route_plan = RoutePlan {
+        legs: vec![swap_leg_and_account_metas],
+        in_amount: route.in_amount,
+        quoted_out_amount: route.out_amount,
+    };
+    let instruction = build_route_instruction(
+        route_plan,
+        &RouteInstructionParams {
+            platform_fee_account: Some(platform_fee_account),
+            ..route_instruction_params
+        },
+    )
+    .unwrap();
+
+    assert_eq!(instruction.program_id, jupiter::ID);
+    // 3 top level accounts, the platform fee account and 11 accounts for each token swap
+    assert_eq!(instruction.accounts.len(), 3 + 1 + 22);
+    assert_eq!(instruction.accounts[0].pubkey, spl_token::ID);
+    assert_eq!(instruction.accounts[1].pubkey, user);
+    assert!(instruction.accounts[1].is_signer);
+    assert_eq!(instruction.accounts[2].pubkey, user_destination_token_account);
+    assert_eq!(instruction.accounts[3].pubkey, platform_fee_account);
+    assert_eq!(instruction.data[..8], Route::DISCRIMINATOR);
+    // Chain tag and 2 legs
+    assert_eq!(instruction.data[8..13], [0, 2, 0, 0, 0]);
+    let amounts_offset = instruction.data.len() - 19;
+    assert_eq!(instruction.data[amounts_offset..amounts_offset + 8], route.in_amount.to_le_bytes());
+    assert_eq!(instruction.data[instruction.data.len() - 3..], [50, 0, 20]);
+}
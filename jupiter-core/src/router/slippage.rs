@@ -0,0 +1,93 @@
+//! Slippage and platform fee amounts of a route. The rounding directions are the conservative ones
+//! for the user, they are not verified against the Jupiter program, whose source is not available here.
+use anyhow::{anyhow, Result};
+use std::convert::TryInto;
+use jupiter::{AmountWithSlippage, JupiterError};
+
+use crate::amms::amm::Quote;
+use crate::math::checked_ceil_div;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+fn check_slippage_bps(slippage_bps: u16) -> Result<()> {
+    if slippage_bps as u64 > BPS_DENOMINATOR {
+        return Err(anyhow!("{}", JupiterError::InvalidSlippage));
+    }
+    Ok(())
+}
+
+/// Least output accepted under the slippage, rounded down
+pub fn get_min_out_amount(out_amount: u64, slippage_bps: u16) -> Result<u64> {
+    check_slippage_bps(slippage_bps)?;
+    Ok((out_amount as u128 * (BPS_DENOMINATOR - slippage_bps as u64) as u128 / BPS_DENOMINATOR as u128) as u64)
+}
+
+/// Most input accepted under the slippage, rounded up. Errors when it does not fit a u64
+pub fn get_max_in_amount(in_amount: u64, slippage_bps: u16) -> Result<u64> {
+    check_slippage_bps(slippage_bps)?;
+    let max_in_amount = checked_ceil_div(
+        in_amount as u128 * (BPS_DENOMINATOR + slippage_bps as u64) as u128,
+        BPS_DENOMINATOR as u128,
+    )
+    .ok_or_else(|| anyhow!("Max in amount overflows"))?;
+    max_in_amount.try_into().map_err(|_| anyhow!("Max in amount overflows"))
+}
+
+/// Platform fee taken from the output, rounded down
+pub fn get_platform_fee_amount(out_amount: u64, platform_fee_bps: u8) -> u64 {
+    (out_amount as u128 * platform_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+}
+
+pub fn get_out_amount_after_platform_fee(out_amount: u64, platform_fee_bps: u8) -> u64 {
+    out_amount - get_platform_fee_amount(out_amount, platform_fee_bps)
+}
+
+pub fn get_amount_with_slippage(amount: u64, slippage_bps: u16) -> Result<AmountWithSlippage> {
+    check_slippage_bps(slippage_bps)?;
+    Ok(AmountWithSlippage { amount, slippage_bps })
+}
+
+/// Quote with the platform fee taken from its output and min_out_amount set to the least output
+/// the route accepts, the platform fee is taken out before the slippage is applied.
+/// The input of an exact in swap is not subject to slippage, min_in_amount is the in amount.
+pub fn apply_slippage_and_platform_fee(quote: &Quote, slippage_bps: u16, platform_fee_bps: u8) -> Result<Quote> {
+    let out_amount = get_out_amount_after_platform_fee(quote.out_amount, platform_fee_bps);
+    Ok(Quote {
+        min_in_amount: Some(quote.in_amount),
+        min_out_amount: Some(get_min_out_amount(out_amount, slippage_bps)?),
+        out_amount,
+        ..*quote
+    })
+}
+
+#[test]
+fn test_slippage_and_platform_fee() {
+    assert_eq!(get_min_out_amount(1_000_000, 50).unwrap(), 995_000);
+    assert_eq!(get_min_out_amount(999, 50).unwrap(), 994);
+    assert_eq!(get_min_out_amount(u64::MAX, 0).unwrap(), u64::MAX);
+    assert_eq!(get_min_out_amount(1_000, 10_000).unwrap(), 0);
+    assert!(get_min_out_amount(1_000, 10_001).is_err());
+
+    assert_eq!(get_max_in_amount(1_000_000, 50).unwrap(), 1_005_000);
+    assert_eq!(get_max_in_amount(999, 50).unwrap(), 1_004);
+    assert_eq!(get_max_in_amount(u64::MAX, 0).unwrap(), u64::MAX);
+    assert!(get_max_in_amount(u64::MAX, 50).is_err());
+
+    assert_eq!(get_platform_fee_amount(1_000_000, 20), 2_000);
+    assert_eq!(get_platform_fee_amount(499, 20), 0);
+    assert_eq!(get_out_amount_after_platform_fee(1_000_000, 20), 998_000);
+
+    let amount_with_slippage = get_amount_with_slippage(1_000, 50).unwrap();
+    assert_eq!(amount_with_slippage.amount, 1_000);
+    assert_eq!(amount_with_slippage.slippage_bps, 50);
+
+    let quote = Quote {
+        in_amount: 1_000_000_000,
+        out_amount: 20_000_000,
+        ..Quote::default()
+    };
+    let quote = apply_slippage_and_platform_fee(&quote, 100, 20).unwrap();
+    assert_eq!(quote.out_amount, 19_960_000);
+    assert_eq!(quote.min_out_amount, Some(19_760_400));
+    assert_eq!(quote.min_in_amount, Some(1_000_000_000));
+}
@@ -0,0 +1,319 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+
+use super::{is_routable, Router};
+use crate::amms::amm::{Amm, Quote, QuoteParams, SwapLegAndAccountMetas, SwapParams};
+use jupiter::jupiter_override::{SplitLeg, SwapLeg};
+
+/// Default granularity of the percent allocated to each leg
+pub const DEFAULT_PERCENT_STEP: u8 = 5;
+/// Default maximum number of amms sharing the input
+pub const DEFAULT_MAX_SPLIT_LEGS: usize = 3;
+
+/// Share of the input going through a single amm
+pub struct SplitRouteLeg {
+    pub amm_key: Pubkey,
+    pub label: String,
+    pub percent: u8,
+    pub quote: Quote,
+}
+
+/// Input split across amms serving the same pair, percents add up to 100
+pub struct SplitRoute {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub legs: Vec<SplitRouteLeg>,
+    pub in_amount: u64,
+    pub out_amount: u64,
+}
+
+impl SplitRoute {
+    /// Split of each leg swap leg with their account metas concatenated in order,
+    /// a route through a single amm is returned as its swap leg directly
+    pub fn get_swap_leg_and_account_metas(
+        &self,
+        router: &Router,
+        swap_params: &SwapParams,
+    ) -> Result<SwapLegAndAccountMetas> {
+        let mut split_legs = Vec::new();
+        let mut account_metas = Vec::new();
+
+        for leg in self.legs.iter() {
+            let amm = router
+                .get_amm(&leg.amm_key)
+                .ok_or_else(|| anyhow!("Amm {} is not in the router", leg.amm_key))?;
+            let leg_swap_leg_and_account_metas = amm.get_swap_leg_and_account_metas(&SwapParams {
+                source_mint: self.input_mint,
+                destination_mint: self.output_mint,
+                user_source_token_account: swap_params.user_source_token_account,
+                user_destination_token_account: swap_params.user_destination_token_account,
+                user_transfer_authority: swap_params.user_transfer_authority,
+                open_order_address: swap_params.open_order_address,
+                quote_mint_to_referrer: swap_params.quote_mint_to_referrer.clone(),
+                in_amount: leg.quote.in_amount,
+            })?;
+            split_legs.push(SplitLeg {
+                percent: leg.percent,
+                swap_leg: leg_swap_leg_and_account_metas.swap_leg,
+            });
+            account_metas.extend(leg_swap_leg_and_account_metas.account_metas);
+        }
+
+        let swap_leg = if split_legs.len() == 1 {
+            split_legs.pop().unwrap().swap_leg
+        } else {
+            SwapLeg::Split { split_legs }
+        };
+        Ok(SwapLegAndAccountMetas {
+            swap_leg,
+            account_metas,
+        })
+    }
+}
+
+fn amount_for_percent(in_amount: u64, percent: u8) -> u64 {
+    (in_amount as u128 * percent as u128 / 100) as u64
+}
+
+fn quote_out_amount(amm: &(dyn Amm + Send + Sync), quote_params: &QuoteParams) -> Option<u64> {
+    if quote_params.in_amount == 0 {
+        return Some(0);
+    }
+    match amm.quote(quote_params) {
+        Ok(quote) if is_routable(&quote) => Some(quote.out_amount),
+        _ => None,
+    }
+}
+
+impl Router {
+    /// Splits the input across up to max_legs amms of the pair.
+    /// Percents are handed out percent_step at a time to the amm adding the most output,
+    /// which converges to the best allocation as long as outputs have diminishing returns.
+    pub fn find_split_route(
+        &self,
+        quote_params: &QuoteParams,
+        percent_step: u8,
+        max_legs: usize,
+    ) -> Option<SplitRoute> {
+        let percent_step = percent_step.clamp(1, 100);
+        let amms = self.get_amms_for_pair(&quote_params.input_mint, &quote_params.output_mint);
+        if amms.is_empty() || max_legs == 0 {
+            return None;
+        }
+
+        let mut percents: Vec<u8> = vec![0; amms.len()];
+        let mut out_amounts: Vec<u64> = vec![0; amms.len()];
+        let mut percent_left: u8 = 100;
+
+        while percent_left > 0 {
+            let step = percent_step.min(percent_left);
+            let legs_used = percents.iter().filter(|percent| **percent > 0).count();
+
+            let mut best: Option<(usize, u64)> = None;
+            for (index, amm) in amms.iter().enumerate() {
+                if percents[index] == 0 && legs_used >= max_legs {
+                    continue;
+                }
+                let out_amount = match quote_out_amount(
+                    *amm,
+                    &QuoteParams {
+                        in_amount: amount_for_percent(quote_params.in_amount, percents[index] + step),
+                        input_mint: quote_params.input_mint,
+                        output_mint: quote_params.output_mint,
+                    },
+                ) {
+                    Some(out_amount) => out_amount,
+                    None => continue,
+                };
+                let gain = out_amount.saturating_sub(out_amounts[index]);
+                match best {
+                    Some((_, best_gain)) if best_gain >= gain => {}
+                    _ => best = Some((index, gain)),
+                }
+            }
+
+            let (index, gain) = best?;
+            percents[index] += step;
+            out_amounts[index] += gain;
+            percent_left -= step;
+        }
+
+        // The last leg takes what rounding down each leg amount left over
+        let allocated: Vec<(usize, u8)> = percents
+            .iter()
+            .enumerate()
+            .filter(|(_, percent)| **percent > 0)
+            .map(|(index, percent)| (index, *percent))
+            .collect();
+        let mut amount_left = quote_params.in_amount;
+        let mut legs = Vec::new();
+        for (position, (index, percent)) in allocated.iter().enumerate() {
+            let in_amount = if position == allocated.len() - 1 {
+                amount_left
+            } else {
+                amount_for_percent(quote_params.in_amount, *percent)
+            };
+            amount_left -= in_amount;
+
+            // The last leg is quoted for more than it was allocated, it may not have the liquidity
+            let amm = amms[*index];
+            let quote = amm
+                .quote(&QuoteParams {
+                    in_amount,
+                    input_mint: quote_params.input_mint,
+                    output_mint: quote_params.output_mint,
+                })
+                .ok()
+                .filter(is_routable)?;
+            legs.push(SplitRouteLeg {
+                amm_key: amm.key(),
+                label: amm.label(),
+                percent: *percent,
+                quote: Quote { in_amount, ..quote },
+            });
+        }
+
+        let out_amount = legs.iter().map(|leg| leg.quote.out_amount).sum();
+        Some(SplitRoute {
+            input_mint: quote_params.input_mint,
+            output_mint: quote_params.output_mint,
+            legs,
+            in_amount: quote_params.in_amount,
+            out_amount,
+        })
+    }
+}
+
+#[test]
+fn test_find_split_route() {
+    use crate::amms::test_harness::new_spl_token_swap_amm;
+    use crate::constants::USDC_MINT;
+    use spl_associated_token_account::get_associated_token_address;
+    use spl_token::native_mint;
+
+    let mut router = Router::new();
+    router.add_amm(Box::new(new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 1_000_000_000_000, 20_000_000_000)));
+    router.add_amm(Box::new(new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 1_000_000_000_000, 20_000_000_000)));
+    router.add_amm(Box::new(new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 3_000_000_000_000, 60_000_000_000)));
+
+    let quote_params = QuoteParams {
+        in_amount: 500_000_000_000,
+        input_mint: native_mint::id(),
+        output_mint: USDC_MINT,
+    };
+    let single_pool_out_amount = router.best_quote(&quote_params).unwrap().quote.out_amount;
+
+    let split_route = router
+        .find_split_route(&quote_params, DEFAULT_PERCENT_STEP, DEFAULT_MAX_SPLIT_LEGS)
+        .unwrap();
+    assert_eq!(split_route.legs.len(), 3);
+    assert_eq!(split_route.legs.iter().map(|leg| leg.percent as u64).sum::<u64>(), 100);
+    assert_eq!(split_route.legs.iter().map(|leg| leg.quote.in_amount).sum::<u64>(), quote_params.in_amount);
+    assert!(split_route.out_amount > single_pool_out_amount);
+    // Allocation follows the depth of the pools
+    let mut percents: Vec<u8> = split_route.legs.iter().map(|leg| leg.percent).collect();
+    percents.sort();
+    assert_eq!(percents, vec![20, 20, 60]);
+
+    let two_legs_route = router.find_split_route(&quote_params, DEFAULT_PERCENT_STEP, 2).unwrap();
+    assert_eq!(two_legs_route.legs.len(), 2);
+
+    let user = Pubkey::new_unique();
+    let swap_leg_and_account_metas = split_route
+        .get_swap_leg_and_account_metas(
+            &router,
+            &SwapParams {
+                source_mint: native_mint::id(),
+                destination_mint: USDC_MINT,
+                user_source_token_account: get_associated_token_address(&user, &native_mint::id()),
+                user_destination_token_account: get_associated_token_address(&user, &USDC_MINT),
+                user_transfer_authority: user,
+                open_order_address: None,
+                quote_mint_to_referrer: None,
+                in_amount: quote_params.in_amount,
+            },
+        )
+        .unwrap();
+    match swap_leg_and_account_metas.swap_leg {
+        SwapLeg::Split { split_legs } => assert_eq!(split_legs.len(), 3),
+        _ => panic!("Expected a split"),
+    }
+    assert_eq!(swap_leg_and_account_metas.account_metas.len(), 33);
+}
+
+#[test]
+fn test_find_split_route_last_leg_liquidity() {
+    use crate::amms::test_harness::new_spl_token_swap_amm;
+    use crate::constants::USDC_MINT;
+    use spl_token::native_mint;
+    use std::collections::HashMap;
+
+    /// Pool without the liquidity for more than max_in_amount
+    struct CappedAmm {
+        amm: Box<dyn Amm + Send + Sync>,
+        max_in_amount: u64,
+    }
+
+    impl Amm for CappedAmm {
+        fn label(&self) -> String {
+            self.amm.label()
+        }
+
+        fn key(&self) -> Pubkey {
+            self.amm.key()
+        }
+
+        fn get_reserve_mints(&self) -> Vec<Pubkey> {
+            self.amm.get_reserve_mints()
+        }
+
+        fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+            self.amm.get_accounts_to_update()
+        }
+
+        fn update(&mut self, accounts_map: &HashMap<Pubkey, Vec<u8>>) -> Result<()> {
+            self.amm.update(accounts_map)
+        }
+
+        fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+            let quote = self.amm.quote(quote_params)?;
+            Ok(Quote {
+                not_enough_liquidity: quote_params.in_amount > self.max_in_amount,
+                ..quote
+            })
+        }
+
+        fn get_swap_leg_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapLegAndAccountMetas> {
+            self.amm.get_swap_leg_and_account_metas(swap_params)
+        }
+
+        fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+            Box::new(CappedAmm {
+                amm: self.amm.clone_amm(),
+                max_in_amount: self.max_in_amount,
+            })
+        }
+    }
+
+    // Half of the input rounds down to 500_000_001, the last leg gets 500_000_002
+    let quote_params = QuoteParams {
+        in_amount: 1_000_000_003,
+        input_mint: native_mint::id(),
+        output_mint: USDC_MINT,
+    };
+    let mut router = Router::new();
+    for _ in 0..2 {
+        router.add_amm(Box::new(CappedAmm {
+            amm: Box::new(new_spl_token_swap_amm(native_mint::id(), USDC_MINT, 1_000_000_000_000, 20_000_000_000)),
+            max_in_amount: 500_000_001,
+        }));
+    }
+    assert!(router.find_split_route(&quote_params, DEFAULT_PERCENT_STEP, 2).is_none());
+
+    let quote_params = QuoteParams {
+        in_amount: 1_000_000_002,
+        ..quote_params
+    };
+    let split_route = router.find_split_route(&quote_params, DEFAULT_PERCENT_STEP, 2).unwrap();
+    assert!(split_route.legs.iter().all(|leg| !leg.quote.not_enough_liquidity));
+}
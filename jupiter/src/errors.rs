@@ -0,0 +1,70 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::InstructionError;
+
+// Errors declared in the IDL, codes start at 6000
+#[error_code]
+#[derive(PartialEq, Eq)]
+pub enum JupiterError {
+    #[msg("Empty route")]
+    EmptyRoute,
+    #[msg("Slippage tolerance exceeded")]
+    SlippageToleranceExceeded,
+    #[msg("Invalid calculation")]
+    InvalidCalculation,
+    #[msg("Missing platform fee account")]
+    MissingPlatformFeeAccount,
+    #[msg("Invalid slippage")]
+    InvalidSlippage,
+    #[msg("Not enough percent to 100")]
+    NotEnoughPercent,
+    #[msg("In amounts stack is empty")]
+    InAmountsStackIsEmpty,
+    #[msg("Out amounts stack is empty")]
+    OutAmountsStackIsEmpty,
+    #[msg("Not Enough Account keys")]
+    NotEnoughAccountKeys,
+}
+
+impl JupiterError {
+    const ALL: [JupiterError; 9] = [
+        JupiterError::EmptyRoute,
+        JupiterError::SlippageToleranceExceeded,
+        JupiterError::InvalidCalculation,
+        JupiterError::MissingPlatformFeeAccount,
+        JupiterError::InvalidSlippage,
+        JupiterError::NotEnoughPercent,
+        JupiterError::InAmountsStackIsEmpty,
+        JupiterError::OutAmountsStackIsEmpty,
+        JupiterError::NotEnoughAccountKeys,
+    ];
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        let index = code.checked_sub(anchor_lang::error::ERROR_CODE_OFFSET)?;
+        Self::ALL.get(index as usize).copied()
+    }
+
+    /// Jupiter error behind a failed instruction, only meaningful when the instruction is the Jupiter one
+    pub fn from_instruction_error(instruction_error: &InstructionError) -> Option<Self> {
+        match instruction_error {
+            InstructionError::Custom(code) => Self::from_code(*code),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_jupiter_error_from_code() {
+    assert_eq!(JupiterError::from_code(6000), Some(JupiterError::EmptyRoute));
+    assert_eq!(JupiterError::from_code(6008), Some(JupiterError::NotEnoughAccountKeys));
+    assert_eq!(JupiterError::from_code(6009), None);
+    assert_eq!(JupiterError::from_code(1), None);
+    for error in JupiterError::ALL {
+        assert_eq!(JupiterError::from_code(error.into()), Some(error));
+    }
+    assert_eq!(
+        JupiterError::from_instruction_error(&InstructionError::Custom(6001)),
+        Some(JupiterError::SlippageToleranceExceeded)
+    );
+    assert_eq!(JupiterError::from_instruction_error(&InstructionError::InvalidArgument), None);
+    assert_eq!(JupiterError::SlippageToleranceExceeded.to_string(), "Slippage tolerance exceeded");
+}
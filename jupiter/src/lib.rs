@@ -5,14 +5,17 @@ anchor_lang::declare_id!("JUPSjgjMFjU4453KMgxhqVmzep6W352bQpE4RsNqXAx");
 #[cfg(not(feature = "staging"))]
 anchor_lang::declare_id!("JUP4Fb2cqiRUcaTHdrPC8h2gNsA2ETXiPDD33WcGuJB");
 
-// Temporarily redefined it until solution is found
+mod errors;
+pub use errors::JupiterError;
+
+// Temporarily redefined it until solution is found, test_override_matches_idl keeps it in line with the IDL
 pub mod jupiter_override {
     use super::Side;
     use anchor_lang::{prelude::*, Discriminator};
-    use anchor_lang::{AnchorSerialize, InstructionData};
-    use std::io::Write;
+    use anchor_lang::{AnchorDeserialize, AnchorSerialize, InstructionData};
+    use std::io::{Error, ErrorKind, Write};
 
-    #[derive(AnchorSerialize)]
+    #[derive(AnchorSerialize, AnchorDeserialize, Debug)]
     pub enum Swap {
         Saber,
         SaberAddDecimalsDeposit,
@@ -22,7 +25,7 @@ pub mod jupiter_override {
         Step,
         Cropper,
         Raydium,
-        Crema,
+        Crema { a_to_b: bool },
         Lifinity,
         Mercurial,
         Cykura,
@@ -44,12 +47,13 @@ pub mod jupiter_override {
         Openbook { side: Side },
     }
 
-    #[derive(AnchorSerialize)]
+    #[derive(AnchorSerialize, AnchorDeserialize, Debug)]
     pub struct SplitLeg {
         pub percent: u8,
         pub swap_leg: SwapLeg,
     }
 
+    #[derive(Debug)]
     pub enum SwapLeg {
         Chain { swap_legs: Vec<SwapLeg> },
         Split { split_legs: Vec<SplitLeg> },
@@ -76,7 +80,29 @@ pub mod jupiter_override {
         }
     }
 
-    #[derive(AnchorSerialize)]
+    impl AnchorDeserialize for SwapLeg {
+        #[inline]
+        fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+            let variant: u8 = AnchorDeserialize::deserialize(buf)?;
+            match variant {
+                0 => Ok(SwapLeg::Chain {
+                    swap_legs: AnchorDeserialize::deserialize(buf)?,
+                }),
+                1 => Ok(SwapLeg::Split {
+                    split_legs: AnchorDeserialize::deserialize(buf)?,
+                }),
+                2 => Ok(SwapLeg::Swap {
+                    swap: AnchorDeserialize::deserialize(buf)?,
+                }),
+                _ => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unexpected swap leg variant index: {}", variant),
+                )),
+            }
+        }
+    }
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
     pub struct Route {
         pub swap_leg: SwapLeg,
         pub in_amount: u64,
@@ -89,4 +115,169 @@ pub mod jupiter_override {
     }
 
     impl InstructionData for Route {}
+    #[test]
+    fn test_route_round_trip() {
+        let route = Route {
+            swap_leg: SwapLeg::Chain {
+                swap_legs: vec![
+                    SwapLeg::Swap {
+                        swap: Swap::Serum { side: Side::Ask },
+                    },
+                    SwapLeg::Split {
+                        split_legs: vec![
+                            SplitLeg {
+                                percent: 60,
+                                swap_leg: SwapLeg::Swap {
+                                    swap: Swap::Whirlpool { a_to_b: true },
+                                },
+                            },
+                            SplitLeg {
+                                percent: 40,
+                                swap_leg: SwapLeg::Swap {
+                                    swap: Swap::TokenSwap,
+                                },
+                            },
+                        ],
+                    },
+                ],
+            },
+            in_amount: 1_000_000,
+            quoted_out_amount: 990_000,
+            slippage_bps: 50,
+            platform_fee_bps: 0,
+        };
+        let data = route.try_to_vec().unwrap();
+        let decoded_route = Route::try_from_slice(&data).unwrap();
+        assert_eq!(decoded_route.try_to_vec().unwrap(), data);
+        assert_eq!(decoded_route.in_amount, 1_000_000);
+
+        match decoded_route.swap_leg {
+            SwapLeg::Chain { swap_legs } => match &swap_legs[1] {
+                SwapLeg::Split { split_legs } => assert_eq!(split_legs[0].percent, 60),
+                _ => panic!("Expected a split"),
+            },
+            _ => panic!("Expected a chain"),
+        }
+
+        // Route data starts with the instruction discriminator
+        let instruction_data = route.data();
+        assert_eq!(instruction_data[..8], Route::DISCRIMINATOR);
+        assert_eq!(instruction_data[8..], data[..]);
+
+        assert!(SwapLeg::try_from_slice(&[3]).is_err());
+    }
+
+    #[cfg(test)]
+    fn idl_type(name: &str) -> serde_json::Value {
+        let idl: serde_json::Value = serde_json::from_str(include_str!("../idl.json")).unwrap();
+        idl["types"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|idl_type| idl_type["name"] == name)
+            .unwrap_or_else(|| panic!("{} is not in the IDL", name))["type"]
+            .clone()
+    }
+
+    #[cfg(test)]
+    fn to_snake_case(name: &str) -> String {
+        let mut snake_case = String::new();
+        for c in name.chars() {
+            if c.is_ascii_uppercase() {
+                snake_case.push('_');
+                snake_case.push(c.to_ascii_lowercase());
+            } else {
+                snake_case.push(c);
+            }
+        }
+        snake_case
+    }
+
+    #[cfg(test)]
+    /// Borsh encoding of the first value of an IDL type, vecs are encoded empty
+    fn default_idl_encoding(field_type: &serde_json::Value) -> Vec<u8> {
+        if field_type.get("vec").is_some() {
+            return vec![0; 4];
+        }
+        if let Some(defined) = field_type["defined"].as_str() {
+            return match defined {
+                // The IDL redefines the recursive swap leg types, see SwapLegDeeper
+                "SwapLegDeeper" | "SwapLegSwap" => vec![0, 0, 0, 0, 0],
+                _ => default_fields_encoding(&idl_type_variant_or_struct(&idl_type(defined))),
+            };
+        }
+        match field_type.as_str().unwrap() {
+            "bool" | "u8" | "i8" => vec![0],
+            "u16" | "i16" => vec![0; 2],
+            "u32" | "i32" => vec![0; 4],
+            "u64" | "i64" => vec![0; 8],
+            other => panic!("Unsupported IDL type {}", other),
+        }
+    }
+
+    #[cfg(test)]
+    fn idl_type_variant_or_struct(idl_type: &serde_json::Value) -> serde_json::Value {
+        match idl_type["kind"].as_str().unwrap() {
+            // First variant, prefixed by its tag
+            "enum" => serde_json::json!({ "tag": 0, "fields": idl_type["variants"][0]["fields"] }),
+            _ => serde_json::json!({ "fields": idl_type["fields"] }),
+        }
+    }
+
+    #[cfg(test)]
+    fn default_fields_encoding(variant_or_struct: &serde_json::Value) -> Vec<u8> {
+        let mut encoding = Vec::new();
+        if variant_or_struct.get("tag").is_some() {
+            encoding.push(0);
+        }
+        for field in variant_or_struct["fields"].as_array().into_iter().flatten() {
+            encoding.extend(default_idl_encoding(&field["type"]));
+        }
+        encoding
+    }
+
+    #[cfg(test)]
+    /// Walks the variants of an IDL enum: every tag must deserialize into the override variant
+    /// of the same name and fields, consuming exactly the payload the IDL declares
+    fn assert_enum_matches_idl<T: AnchorDeserialize + std::fmt::Debug>(name: &str) {
+        let variants = idl_type(name)["variants"].as_array().unwrap().clone();
+        for (tag, variant) in variants.iter().enumerate() {
+            let variant_name = variant["name"].as_str().unwrap();
+            let mut data = vec![tag as u8];
+            data.extend(default_fields_encoding(variant));
+            let value = T::try_from_slice(&data)
+                .unwrap_or_else(|error| panic!("{}::{} does not match the IDL: {}", name, variant_name, error));
+
+            let debug = format!("{:?}", value);
+            assert_eq!(debug.split(' ').next().unwrap(), variant_name, "{} variant {} is out of order", name, tag);
+            for field in variant["fields"].as_array().into_iter().flatten() {
+                let field_name = to_snake_case(field["name"].as_str().unwrap());
+                assert!(
+                    debug.contains(&format!("{}: ", field_name)),
+                    "{}::{} is missing field {}",
+                    name,
+                    variant_name,
+                    field_name
+                );
+            }
+        }
+        assert!(
+            T::try_from_slice(&[variants.len() as u8]).is_err(),
+            "{} has more variants than the IDL",
+            name
+        );
+    }
+
+    #[test]
+    fn test_override_matches_idl() {
+        assert_enum_matches_idl::<Swap>("Swap");
+        assert_enum_matches_idl::<SwapLeg>("SwapLeg");
+
+        let split_leg_fields = idl_type("SplitLeg")["fields"].clone();
+        let data = default_fields_encoding(&serde_json::json!({ "fields": split_leg_fields }));
+        let debug = format!("{:?}", SplitLeg::try_from_slice(&data).unwrap());
+        for field in split_leg_fields.as_array().unwrap() {
+            assert!(debug.contains(&format!("{}: ", to_snake_case(field["name"].as_str().unwrap()))));
+        }
+    }
 }